@@ -1,4 +1,6 @@
+use std::io;
 use rusoto;
+use hyper;
 use monotone;
 use log;
 use clap;
@@ -12,10 +14,18 @@ error_chain! {
         ParseRegion(rusoto::ParseRegionError);
         Clap(clap::Error);
         Json(serde_json::Error);
+        Io(io::Error);
+        Http(hyper::Error);
     }
 
     links {
+        Monotone(monotone::error::Error, monotone::error::ErrorKind);
         MonotoneAws(monotone::aws::error::Error, monotone::aws::error::ErrorKind);
+        MonotoneStore(monotone::store::error::Error, monotone::store::error::ErrorKind);
+        MonotoneSocket(monotone::socket::error::Error, monotone::socket::error::ErrorKind) #[cfg(unix)];
+        MonotoneSqlite(monotone::sqlite::error::Error, monotone::sqlite::error::ErrorKind) #[cfg(feature = "sqlite")];
+        MonotonePostgres(monotone::postgres::error::Error, monotone::postgres::error::ErrorKind) #[cfg(feature = "postgres")];
+        MonotoneRedis(monotone::redis::error::Error, monotone::redis::error::ErrorKind) #[cfg(feature = "redis")];
     }
 
     errors {
@@ -28,5 +38,50 @@ error_chain! {
             description("invalid tag")
             display("invalid tag: {}", t)
         }
+
+        InvalidLease(l: String) {
+            description("invalid lease")
+            display("invalid lease: {}", l)
+        }
+
+        InvalidRange(r: String) {
+            description("invalid range")
+            display("invalid range: {}", r)
+        }
+
+        InvalidPosition(p: String) {
+            description("invalid queue position")
+            display("invalid queue position: {}", p)
+        }
+
+        InvalidTimeout(t: String) {
+            description("invalid timeout")
+            display("invalid timeout: {}", t)
+        }
+
+        InvalidRetryTime(t: String) {
+            description("invalid retry time")
+            display("invalid retry time: {}", t)
+        }
+
+        InvalidValue(v: String) {
+            description("invalid counter value")
+            display("invalid counter value: {}", v)
+        }
+
+        CompareAndSetFailed(expected: u64) {
+            description("counter did not have the expected value")
+            display("counter did not have the expected value {}", expected)
+        }
+
+        RouteNotFound(path: String) {
+            description("no such route")
+            display("no such route: {}", path)
+        }
+
+        MethodNotAllowed(method: String) {
+            description("method not allowed")
+            display("method not allowed: {}", method)
+        }
     }
 }
\ No newline at end of file
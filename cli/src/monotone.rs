@@ -10,13 +10,16 @@ extern crate log;
 #[macro_use]
 extern crate serde_derive;
 extern crate serde_json;
+extern crate hyper;
+extern crate url;
 
 pub mod error;
+pub mod server;
 
 use std::time::Duration;
 use std::str::FromStr;
 use std::collections::BTreeMap;
-use rusoto::{DefaultCredentialsProvider, Region, ProvideAwsCredentials, DispatchSignedRequest};
+use rusoto::{DefaultCredentialsProvider, DefaultCredentialsProviderSync, Region, ProvideAwsCredentials, DispatchSignedRequest};
 use rusoto::dynamodb::*;
 use rusoto::default_tls_client;
 use error::*;
@@ -25,8 +28,15 @@ use monotone::string::*;
 use monotone::aws::dynamodb::*;
 use monotone::aws::counter::*;
 use monotone::aws::queue::*;
+use monotone::aws::store::*;
+use monotone::allocator::*;
+use monotone::watch::*;
+use monotone::file::store::FileStore;
+use server::{Backend, Server, StoreBackend};
+#[cfg(unix)]
+use server::SocketBackend;
 
-use clap::{Arg, App, SubCommand, ArgMatches};
+use clap::{Arg, ArgGroup, App, SubCommand, ArgMatches};
 
 #[derive(Serialize, Deserialize)]
 pub struct CounterValue {
@@ -88,8 +98,20 @@ fn main() {
 pub fn run() -> Result<()> {
     let matches = parse_args();
 
-    let provider = DefaultCredentialsProvider::new()?;
     let region = Region::from_str(matches.value_of("region").unwrap_or("eu-west-1"))?;
+
+    if let Some(sub_matches) = matches.subcommand_matches("serve") {
+        return run_serve(region, &matches, sub_matches);
+    }
+
+    #[cfg(unix)]
+    {
+        if let Some(sub_matches) = matches.subcommand_matches("daemon") {
+            return run_daemon(sub_matches);
+        }
+    }
+
+    let provider = DefaultCredentialsProvider::new()?;
     let client = DynamoDbClient::new(default_tls_client()?, provider, region);
 
     match matches.subcommand_name() {
@@ -122,13 +144,14 @@ pub fn run_counter<'a,P,D>(region: Region, client: DynamoDbClient<P,D>, matches:
 
     let table_name = matches.value_of("table").unwrap_or("Counters");
     let id = matches.value_of("id").ok_or(ErrorKind::MissingArgument(s("id")))?;
+    let retry_time = retry_time(matches)?;
 
     match sub_matches.subcommand_name() {
         Some("get")  => {
             create_table_if_needed(&client, table_name, 1, 1)?;
             wait_for_table(&client, table_name)?;
 
-            let counter = Counter::new(client, table_name, id, Duration::from_millis(100));
+            let counter = Counter::new(DynamoDbStore::new(client, table_name), id, retry_time);
 
             let value = counter.get_value()?;
 
@@ -145,7 +168,7 @@ pub fn run_counter<'a,P,D>(region: Region, client: DynamoDbClient<P,D>, matches:
             create_table_if_needed(&client, table_name, 1, 1)?;
             wait_for_table(&client, table_name)?;
 
-            let counter = Counter::new(client, table_name, id, Duration::from_millis(100));
+            let counter = Counter::new(DynamoDbStore::new(client, table_name), id, retry_time);
 
             let value = counter.next_value()?;
 
@@ -158,13 +181,64 @@ pub fn run_counter<'a,P,D>(region: Region, client: DynamoDbClient<P,D>, matches:
 
             println!("{}", serde_json::to_string_pretty(&result)?);
         },
+        Some("set") => {
+            let set_matches = sub_matches.subcommand_matches("set").unwrap();
+
+            let expected = parse_value(set_matches.value_of("expected").ok_or(ErrorKind::MissingArgument(s("expected")))?)?;
+            let new = parse_value(set_matches.value_of("value").ok_or(ErrorKind::MissingArgument(s("value")))?)?;
+
+            create_table_if_needed(&client, table_name, 1, 1)?;
+            wait_for_table(&client, table_name)?;
+
+            let counter = Counter::new(DynamoDbStore::new(client, table_name), id, retry_time);
+
+            if !counter.compare_and_set(expected, new)? {
+                bail!(ErrorKind::CompareAndSetFailed(expected));
+            }
+
+            let result = CounterValue {
+                id: s(id),
+                region: region.to_string(),
+                value: new,
+                table: s(table_name),
+            };
+
+            println!("{}", serde_json::to_string_pretty(&result)?);
+        },
+        Some("advance") => {
+            let advance_matches = sub_matches.subcommand_matches("advance").unwrap();
+
+            let min_value = parse_value(advance_matches.value_of("value").ok_or(ErrorKind::MissingArgument(s("value")))?)?;
+
+            create_table_if_needed(&client, table_name, 1, 1)?;
+            wait_for_table(&client, table_name)?;
+
+            let counter = Counter::new(DynamoDbStore::new(client, table_name), id, retry_time);
+
+            let value = counter.advance_to(min_value)?;
+
+            let result = CounterValue {
+                id: s(id),
+                region: region.to_string(),
+                value: value,
+                table: s(table_name),
+            };
+
+            println!("{}", serde_json::to_string_pretty(&result)?);
+        },
         Some("rm") => {
             create_table_if_needed(&client, table_name, 1, 1)?;
             wait_for_table(&client, table_name)?;
 
-            let counter = Counter::new(client, table_name, id, Duration::from_millis(100));
+            let rm_matches = sub_matches.subcommand_matches("rm").unwrap();
+
+            let counter = Counter::new(DynamoDbStore::new(client, table_name), id, retry_time);
 
-            counter.remove()?;
+            if rm_matches.is_present("purge") {
+                counter.purge()?;
+            } else {
+                counter.remove()?;
+            }
         },
         Some(c) => {
             error!("Unrecognised subcommand: {}\n", c);
@@ -185,6 +259,7 @@ pub fn run_queue<'a,P,D>(region: Region, client: DynamoDbClient<P,D>, matches: &
 
     let table_name = matches.value_of("table").unwrap_or("Counters");
     let id = matches.value_of("id").ok_or(ErrorKind::MissingArgument(s("id")))?;
+    let retry_time = retry_time(matches)?;
 
     match sub_matches.subcommand_name() {
         Some("get")  => {
@@ -193,7 +268,7 @@ pub fn run_queue<'a,P,D>(region: Region, client: DynamoDbClient<P,D>, matches: &
             create_table_if_needed(&client, table_name, 1, 1)?;
             wait_for_table(&client, table_name)?;
 
-            let queue = Queue::new(client, table_name, id, Duration::from_millis(100));
+            let queue = Queue::new(DynamoDbStore::new(client, table_name), id, retry_time);
 
             let (version, ticket) = queue.get_ticket(process_id)?;
 
@@ -216,7 +291,7 @@ pub fn run_queue<'a,P,D>(region: Region, client: DynamoDbClient<P,D>, matches: &
             create_table_if_needed(&client, table_name, 1, 1)?;
             wait_for_table(&client, table_name)?;
 
-            let queue = Queue::new(client, table_name, id, Duration::from_millis(100));
+            let queue = Queue::new(DynamoDbStore::new(client, table_name), id, retry_time);
 
             let (version, tickets) = queue.get_tickets()?;
 
@@ -243,16 +318,39 @@ pub fn run_queue<'a,P,D>(region: Region, client: DynamoDbClient<P,D>, matches: &
         Some("join") => {
             let join_matches = sub_matches.subcommand_matches("join").unwrap();
 
-            let tags = join_matches.values_of("tag").map(|values| parse_tags(values)).unwrap_or(Ok(BTreeMap::new()))?;
+            let process_id = sub_matches.value_of("process_id").ok_or(ErrorKind::MissingArgument(s("process")))?;
+
+            create_table_if_needed(&client, table_name, 1, 1)?;
+            wait_for_table(&client, table_name)?;
 
+            let queue = Queue::new(DynamoDbStore::new(client, table_name), id, retry_time);
+
+            let (version, ticket) = join(queue, process_id, join_matches, retry_time)?;
+
+            let result = QueueTicketOutput {
+                id: s(id),
+                region: region.to_string(),
+                table: s(table_name),
+                fencing_token: version,
+                ticket: QueueTicket {
+                    process_id: s(ticket.process_id),
+                    counter: ticket.counter,
+                    position: ticket.position,
+                    tags: ticket.tags,
+                }
+            };
+
+            println!("{}", serde_json::to_string_pretty(&result)?);
+        },
+        Some("heartbeat") => {
             let process_id = sub_matches.value_of("process_id").ok_or(ErrorKind::MissingArgument(s("process")))?;
 
             create_table_if_needed(&client, table_name, 1, 1)?;
             wait_for_table(&client, table_name)?;
 
-            let queue = Queue::new(client, table_name, id, Duration::from_millis(100));
+            let queue = Queue::new(DynamoDbStore::new(client, table_name), id, retry_time);
 
-            let (version, ticket) = queue.join_queue(s(process_id), tags)?;
+            let (version, ticket) = queue.heartbeat(process_id)?;
 
             let result = QueueTicketOutput {
                 id: s(id),
@@ -269,13 +367,59 @@ pub fn run_queue<'a,P,D>(region: Region, client: DynamoDbClient<P,D>, matches: &
 
             println!("{}", serde_json::to_string_pretty(&result)?);
         },
+        Some("wait") => {
+            let wait_matches = sub_matches.subcommand_matches("wait").unwrap();
+
+            let process_id = sub_matches.value_of("process_id").ok_or(ErrorKind::MissingArgument(s("process")))?;
+
+            let position = wait_matches.value_of("position").map(parse_position).unwrap_or(Ok(0))?;
+
+            let timeout = wait_matches.value_of("timeout").map(parse_timeout).unwrap_or(Ok(None))?;
+
+            let lease = wait_matches.value_of("lease").map(parse_lease).unwrap_or(Ok(None))?;
+
+            create_table_if_needed(&client, table_name, 1, 1)?;
+            wait_for_table(&client, table_name)?;
+
+            let queue = Queue::new(DynamoDbStore::new(client, table_name), id, retry_time);
+
+            if let Some((version, ticket)) = queue.wait_for_position(process_id, position, lease, timeout)? {
+                let result = QueueTicketOutput {
+                    id: s(id),
+                    region: region.to_string(),
+                    table: s(table_name),
+                    fencing_token: version,
+                    ticket: QueueTicket {
+                        process_id: s(ticket.process_id),
+                        counter: ticket.counter,
+                        position: ticket.position,
+                        tags: ticket.tags,
+                    }
+                };
+
+                println!("{}", serde_json::to_string_pretty(&result)?);
+            } else {
+                error!("Timed out waiting for {} to reach position {}", process_id, position);
+                std::process::exit(2);
+            }
+        },
+        Some("watch") => {
+            create_table_if_needed(&client, table_name, 1, 1)?;
+            wait_for_table(&client, table_name)?;
+
+            let queue = Queue::new(DynamoDbStore::new(client, table_name), id, retry_time);
+
+            for event in queue.watch() {
+                println!("{}", serde_json::to_string(&event?)?);
+            }
+        },
         Some("leave") => {
             let process_id = sub_matches.value_of("process_id").ok_or(ErrorKind::MissingArgument(s("process")))?;
 
             create_table_if_needed(&client, table_name, 1, 1)?;
             wait_for_table(&client, table_name)?;
 
-            let queue = Queue::new(client, table_name, id, Duration::from_millis(100));
+            let queue = Queue::new(DynamoDbStore::new(client, table_name), id, retry_time);
             
             let version = queue.leave_queue(process_id)?;
 
@@ -292,9 +436,15 @@ pub fn run_queue<'a,P,D>(region: Region, client: DynamoDbClient<P,D>, matches: &
             create_table_if_needed(&client, table_name, 1, 1)?;
             wait_for_table(&client, table_name)?;
 
-            let queue = Queue::new(client, table_name, id, Duration::from_millis(100));
-            
-            queue.remove()?;
+            let rm_matches = sub_matches.subcommand_matches("rm").unwrap();
+
+            let queue = Queue::new(DynamoDbStore::new(client, table_name), id, retry_time);
+
+            if rm_matches.is_present("purge") {
+                queue.purge()?;
+            } else {
+                queue.remove()?;
+            }
         },
         Some(c) => {
             error!("Unrecognised subcommand: {}\n", c);
@@ -311,6 +461,100 @@ pub fn run_queue<'a,P,D>(region: Region, client: DynamoDbClient<P,D>, matches: &
     Ok(())
 }
 
+pub fn run_serve<'a>(region: Region, matches: &ArgMatches<'a>, sub_matches: &ArgMatches<'a>) -> Result<()> {
+    let listen = sub_matches.value_of("listen").unwrap_or("127.0.0.1:8080");
+    let table_name = matches.value_of("table").unwrap_or("Counters");
+    let retry_time = retry_time(matches)?;
+
+    if let Some(path) = sub_matches.value_of("file") {
+        return serve(listen, Server::new(StoreBackend::new(FileStore::new(path), retry_time), "file", path));
+    }
+
+    #[cfg(feature = "sqlite")]
+    {
+        if let Some(path) = sub_matches.value_of("sqlite") {
+            let store = monotone::sqlite::store::SqliteStore::open(path, table_name)?;
+
+            return serve(listen, Server::new(StoreBackend::new(store, retry_time), "sqlite", table_name));
+        }
+    }
+
+    #[cfg(feature = "postgres")]
+    {
+        if let Some(url) = sub_matches.value_of("postgres") {
+            let store = monotone::postgres::store::PostgresStore::connect(url, table_name)?;
+
+            return serve(listen, Server::new(StoreBackend::new(store, retry_time), "postgres", table_name));
+        }
+    }
+
+    #[cfg(feature = "redis")]
+    {
+        if let Some(url) = sub_matches.value_of("redis") {
+            let key_prefix = format!("{}:", table_name);
+            let store = monotone::redis::store::RedisStore::open(url, key_prefix.as_str())?;
+
+            return serve(listen, Server::new(StoreBackend::new(store, retry_time), "redis", key_prefix));
+        }
+    }
+
+    #[cfg(unix)]
+    {
+        if let Some(path) = sub_matches.value_of("socket") {
+            return serve(listen, Server::new(SocketBackend::new(path), "socket", path));
+        }
+    }
+
+    // requests are handled on several threads, so the credentials must be shareable
+    let provider = DefaultCredentialsProviderSync::new()?;
+    let client = DynamoDbClient::new(default_tls_client()?, provider, region);
+
+    create_table_if_needed(&client, table_name, 1, 1)?;
+    wait_for_table(&client, table_name)?;
+
+    serve(listen, Server::new(StoreBackend::new(DynamoDbStore::new(client, table_name), retry_time), region.to_string(), table_name))
+}
+
+pub fn serve<B>(listen: &str, server: Server<B>) -> Result<()> where B: Backend + Send + Sync + 'static {
+    let listening = hyper::server::Server::http(listen)?.handle(server)?;
+
+    info!("listening on {}", listening.socket);
+
+    // dropping the listener waits for the server threads, which run until the process is killed
+    drop(listening);
+
+    Ok(())
+}
+
+#[cfg(unix)]
+pub fn run_daemon<'a>(sub_matches: &ArgMatches<'a>) -> Result<()> {
+    let path = sub_matches.value_of("socket").ok_or(ErrorKind::MissingArgument(s("socket")))?;
+
+    monotone::socket::daemon::Daemon::new().listen(path)?;
+
+    Ok(())
+}
+
+/// Join `queue` with the tags and lease given to `queue join`, allocating an id
+/// from the range given by `--bounded` if there is one, waiting `retry_time` between attempts.
+pub fn join<Q>(queue: Q, process_id: &str, join_matches: &ArgMatches, retry_time: Duration) -> Result<(u64, Ticket)> where Q: MonotonicQueue {
+    let tags = join_matches.values_of("tag").map(parse_tags).unwrap_or(Ok(BTreeMap::new()))?;
+
+    let lease = join_matches.value_of("lease").map(parse_lease).unwrap_or(Ok(None))?;
+
+    let bounds = join_matches.value_of("bounded").map(parse_range).map_or(Ok(None), |r| r.map(Some))?;
+
+    if let Some((min, max)) = bounds {
+        let allocator = BoundedAllocator::new(queue, min, max, retry_time);
+
+        let (version, ticket, _id) = allocator.allocate(s(process_id), tags, lease)?;
+
+        Ok((version, ticket))
+    } else {
+        Ok(queue.join_queue(s(process_id), tags, lease).map_err(Into::<monotone::error::Error>::into)?)
+    }
+}
+
 pub fn parse_tags<'a, I>(tags: I) -> Result<BTreeMap<String, String>> where I: Iterator<Item=&'a str> {
     let mut result = BTreeMap::new();
     for t in tags {
@@ -324,6 +568,47 @@ pub fn parse_tags<'a, I>(tags: I) -> Result<BTreeMap<String, String>> where I: I
     Ok(result)
 }
 
+pub fn parse_value(value: &str) -> Result<u64> {
+    value.parse::<u64>().map_err(|_| ErrorKind::InvalidValue(s(value)).into())
+}
+
+pub fn parse_range(range: &str) -> Result<(u64, u64)> {
+    let parts = range.splitn(2, "..").collect::<Vec<&str>>();
+
+    if parts.len() == 2 {
+        if let (Ok(min), Ok(max)) = (parts[0].parse::<u64>(), parts[1].parse::<u64>()) {
+            if min <= max {
+                return Ok((min, max));
+            }
+        }
+    }
+
+    bail!(ErrorKind::InvalidRange(s(range)));
+}
+
+pub fn parse_lease(lease: &str) -> Result<Option<Duration>> {
+    let seconds = lease.parse::<u64>().map_err(|_| ErrorKind::InvalidLease(s(lease)))?;
+
+    Ok(Some(Duration::from_secs(seconds)))
+}
+
+/// How long to wait before retrying a conflicting write, from `--retry-time`.
+pub fn retry_time(matches: &ArgMatches) -> Result<Duration> {
+    let millis = matches.value_of("retry_time").unwrap_or("100");
+
+    Ok(Duration::from_millis(millis.parse::<u64>().map_err(|_| ErrorKind::InvalidRetryTime(s(millis)))?))
+}
+
+pub fn parse_position(position: &str) -> Result<usize> {
+    position.parse::<usize>().map_err(|_| ErrorKind::InvalidPosition(s(position)).into())
+}
+
+pub fn parse_timeout(timeout: &str) -> Result<Option<Duration>> {
+    let seconds = timeout.parse::<u64>().map_err(|_| ErrorKind::InvalidTimeout(s(timeout)))?;
+
+    Ok(Some(Duration::from_secs(seconds)))
+}
+
 pub fn clap_app<'a,'b>() -> App<'a,'b> {
     App::new("aws-counter")
         .version("0.1")
@@ -347,6 +632,11 @@ pub fn clap_app<'a,'b>() -> App<'a,'b> {
             .value_name("COUNTER_ID")
             .help("ID of the counter to manage")
             .takes_value(true))
+        .arg(Arg::with_name("retry_time")
+            .long("retry-time")
+            .value_name("MILLIS")
+            .help("Time to wait before retrying a write that conflicted with another, plus up to 100ms. Defaults to 100")
+            .takes_value(true))
         .subcommand(SubCommand::with_name("counter")
             .subcommand(SubCommand::with_name("get")
                 .about("Get the value of the counter")
@@ -356,9 +646,45 @@ pub fn clap_app<'a,'b>() -> App<'a,'b> {
                 .about("Increment and get the value of the counter")
                 .version("0.1")
                 )
+            .subcommand(SubCommand::with_name("set")
+                .about("Set the value of the counter if it has the expected value. The counter cannot be decreased")
+                .version("0.1")
+                .arg(Arg::with_name("expected")
+                    .short("e")
+                    .long("expected")
+                    .value_name("EXPECTED")
+                    .help("Value the counter must currently have")
+                    .takes_value(true)
+                    .required(true)
+                    )
+                .arg(Arg::with_name("value")
+                    .short("v")
+                    .long("value")
+                    .value_name("VALUE")
+                    .help("New value of the counter")
+                    .takes_value(true)
+                    .required(true)
+                    )
+                )
+            .subcommand(SubCommand::with_name("advance")
+                .about("Raise the counter to at least the given value. The counter is never decreased")
+                .version("0.1")
+                .arg(Arg::with_name("value")
+                    .short("v")
+                    .long("value")
+                    .value_name("VALUE")
+                    .help("Minimum value of the counter")
+                    .takes_value(true)
+                    .required(true)
+                    )
+                )
             .subcommand(SubCommand::with_name("rm")
-                .about("Remove the counter from the table")
+                .about("Remove the counter from the table. A counter recreated with the same id continues from the removed counter's value")
                 .version("0.1")
+                .arg(Arg::with_name("purge")
+                    .long("purge")
+                    .help("Delete the counter's history so a recreated counter starts from 0")
+                    )
                 )
         )
         .subcommand(SubCommand::with_name("queue")
@@ -387,18 +713,157 @@ pub fn clap_app<'a,'b>() -> App<'a,'b> {
                     .takes_value(true)
                     .multiple(true)
                     )
+                .arg(Arg::with_name("lease")
+                    .short("l")
+                    .long("lease")
+                    .value_name("SECONDS")
+                    .help("Lease duration after which the ticket expires unless renewed with heartbeat")
+                    .takes_value(true)
+                    )
+                .arg(Arg::with_name("bounded")
+                    .short("b")
+                    .long("bounded")
+                    .value_name("MIN..MAX")
+                    .help("Allocate the smallest free id in the inclusive range, stored in the allocated_id tag")
+                    .takes_value(true)
+                    )
+                )
+            .subcommand(SubCommand::with_name("heartbeat")
+                .about("Renew the lease on the process id's ticket")
+                .version("0.1")
+                )
+            .subcommand(SubCommand::with_name("wait")
+                .about("Wait until the process id reaches a position in the queue, renewing its lease meanwhile if given. Exits with status 2 on timeout")
+                .version("0.1")
+                .arg(Arg::with_name("position")
+                    .short("n")
+                    .long("position")
+                    .value_name("POSITION")
+                    .help("Position to wait for, where 0 is the head of the queue. Defaults to 0")
+                    .takes_value(true)
+                    )
+                .arg(Arg::with_name("timeout")
+                    .short("w")
+                    .long("timeout")
+                    .value_name("SECONDS")
+                    .help("Give up after this many seconds. Waits forever by default")
+                    .takes_value(true)
+                    )
+                .arg(Arg::with_name("lease")
+                    .short("l")
+                    .long("lease")
+                    .value_name("SECONDS")
+                    .help("Lease the ticket was joined with, renewed every half lease while waiting")
+                    .takes_value(true)
+                    )
+                )
+            .subcommand(SubCommand::with_name("watch")
+                .about("Print each change to the queue as a line of JSON, starting with the processes already in it")
+                .version("0.1")
                 )
             .subcommand(SubCommand::with_name("leave")
                 .about("Remove the process id from the queue")
                 .version("0.1")
                 )
             .subcommand(SubCommand::with_name("rm")
-                .about("Remove the queue from the table")
+                .about("Remove the queue from the table. A queue recreated with the same id continues from the removed queue's counter and fencing token")
                 .version("0.1")
+                .arg(Arg::with_name("purge")
+                    .long("purge")
+                    .help("Delete the queue's history so a recreated queue starts from 0")
+                    )
+                )
+        )
+        .subcommand(serve_subcommand())
+        .subcommand(SubCommand::with_name("daemon")
+            .about("Keep in-memory counters and queues for processes on this host, listening on a Unix socket")
+            .version("0.1")
+            .arg(Arg::with_name("socket")
+                .short("s")
+                .long("socket")
+                .value_name("PATH")
+                .help("Path of the Unix socket to listen on")
+                .takes_value(true)
+                .required(true)
                 )
         )
 }
 
+/// The `serve` subcommand, with an option for each backend this build supports.
+/// DynamoDB is served if none of them are given.
+pub fn serve_subcommand<'a,'b>() -> App<'a,'b> {
+    let mut backends = vec!["file"];
+
+    let serve = SubCommand::with_name("serve")
+        .about("Serve the counters and queues in the table over HTTP")
+        .version("0.1")
+        .arg(Arg::with_name("listen")
+            .short("l")
+            .long("listen")
+            .value_name("ADDRESS")
+            .help("Address to listen on. Defaults to 127.0.0.1:8080")
+            .takes_value(true)
+            )
+        .arg(Arg::with_name("file")
+            .short("f")
+            .long("file")
+            .value_name("PATH")
+            .help("Serve from a state file shared by processes on this host instead of DynamoDB")
+            .takes_value(true)
+            );
+
+    #[cfg(feature = "sqlite")]
+    let serve = {
+        backends.push("sqlite");
+
+        serve.arg(Arg::with_name("sqlite")
+            .long("sqlite")
+            .value_name("PATH")
+            .help("Serve from the table in a SQLite database file instead of DynamoDB")
+            .takes_value(true)
+            )
+    };
+
+    #[cfg(feature = "postgres")]
+    let serve = {
+        backends.push("postgres");
+
+        serve.arg(Arg::with_name("postgres")
+            .long("postgres")
+            .value_name("URL")
+            .help("Serve from the table in a PostgreSQL database instead of DynamoDB")
+            .takes_value(true)
+            )
+    };
+
+    #[cfg(feature = "redis")]
+    let serve = {
+        backends.push("redis");
+
+        serve.arg(Arg::with_name("redis")
+            .long("redis")
+            .value_name("URL")
+            .help("Serve from Redis instead of DynamoDB, keeping each ID under the table name and a colon")
+            .takes_value(true)
+            )
+    };
+
+    #[cfg(unix)]
+    let serve = {
+        backends.push("socket");
+
+        serve.arg(Arg::with_name("socket")
+            .short("s")
+            .long("socket")
+            .value_name("PATH")
+            .help("Serve from the daemon listening on this Unix socket instead of DynamoDB")
+            .takes_value(true)
+            )
+    };
+
+    serve.group(ArgGroup::with_name("backend").args(&backends))
+}
+
 pub fn print_help() -> Result<()> {
     clap_app().print_help()?;
 
@@ -407,4 +872,67 @@ pub fn print_help() -> Result<()> {
 
 pub fn parse_args<'a>() -> ArgMatches<'a> {
     clap_app().get_matches()
-}
\ No newline at end of file
+}
+
+#[cfg(test)]
+mod tests {
+    use monotone;
+    use monotone::local::queue::Queue;
+    use super::*;
+
+    fn join_matches<'a>(args: &[&str]) -> ArgMatches<'a> {
+        let mut argv = vec!["monotone", "-i", "myqueue", "queue", "join"];
+        argv.extend_from_slice(args);
+
+        let matches = clap_app().get_matches_from(argv);
+        let queue_matches = matches.subcommand_matches("queue").expect("queue");
+
+        queue_matches.subcommand_matches("join").expect("join").clone()
+    }
+
+    #[test]
+    pub fn test_parse_range() {
+        assert_eq!(parse_range("1..255").expect("range"), (1, 255));
+        assert_eq!(parse_range("7..7").expect("range"), (7, 7));
+        assert_eq!(parse_range("0..18446744073709551615").expect("range"), (0, u64::MAX));
+
+        for range in &["3..1", "1-3", "1..", "..3", "a..b", "1..2..3", "-1..3"] {
+            assert!(parse_range(range).is_err(), "{} should not parse", range);
+        }
+    }
+
+    fn retry_time() -> Duration {
+        Duration::from_millis(10)
+    }
+
+    #[test]
+    pub fn test_join_bounded() {
+        let q = Queue::new();
+
+        let (_ft, foo) = join(q.clone(), "foo", &join_matches(&["--bounded", "1..2", "-t", "role=db"]), retry_time()).expect("join");
+        assert_eq!(foo.tags.get("allocated_id").map(|id| &id[..]), Some("1"));
+        assert_eq!(foo.tags.get("role").map(|r| &r[..]), Some("db"));
+
+        let (_ft, bar) = join(q.clone(), "bar", &join_matches(&["-b", "1..2"]), retry_time()).expect("join");
+        assert_eq!(bar.tags.get("allocated_id").map(|id| &id[..]), Some("2"));
+
+        match join(q.clone(), "baz", &join_matches(&["-b", "1..2"]), retry_time()) {
+            Err(Error(ErrorKind::Monotone(monotone::error::ErrorKind::Exhausted(1, 2)), _)) => {},
+            r => panic!("expected Exhausted, got {:?}", r),
+        }
+
+        match join(q.clone(), "baz", &join_matches(&["-b", "2..1"]), retry_time()) {
+            Err(Error(ErrorKind::InvalidRange(ref r), _)) if r == "2..1" => {},
+            r => panic!("expected InvalidRange, got {:?}", r),
+        }
+    }
+
+    #[test]
+    pub fn test_join_unbounded() {
+        let q = Queue::new();
+
+        let (ft, foo) = join(q.clone(), "foo", &join_matches(&["-l", "30"]), retry_time()).expect("join");
+        assert_eq!(ft, 1);
+        assert_eq!(foo.tags.get("allocated_id"), None);
+    }
+}
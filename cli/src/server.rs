@@ -0,0 +1,404 @@
+use std::io::Read;
+use std::result;
+use std::sync::Arc;
+use std::time::Duration;
+#[cfg(unix)]
+use std::path::PathBuf;
+use std::collections::BTreeMap;
+use hyper::server::{Handler, Request, Response};
+use hyper::status::StatusCode;
+use hyper::method::Method;
+use hyper::uri::RequestUri;
+use hyper::header::ContentType;
+use url::percent_encoding::percent_decode;
+use serde_json;
+use monotone::*;
+use monotone::string::*;
+use monotone::store::VersionedStore;
+use monotone::store::counter::Counter;
+use monotone::store::queue::Queue;
+use monotone::error::Error as MonotoneError;
+use monotone::error::ErrorKind as MonotoneErrorKind;
+use error::*;
+use super::{CounterValue, QueueTicket, QueueTicketOutput, QueueTicketListOutput, QueueTicketEmptyOutput};
+
+/// Body of a queue join request. Both fields may be left out. The lease is in milliseconds,
+/// as in the socket protocol; unknown fields such as `lease` in seconds are rejected.
+#[derive(Serialize, Deserialize, Default)]
+#[serde(deny_unknown_fields)]
+pub struct JoinRequest {
+    #[serde(default)]
+    pub tags: BTreeMap<String, String>,
+    #[serde(default)]
+    pub lease_millis: Option<u64>,
+}
+
+#[derive(Serialize, Deserialize)]
+pub struct ErrorOutput {
+    pub error: String,
+}
+
+/// REST API over the counters and queues in a store:
+///
+/// ```text
+/// GET    /counters/{id}                      get the counter value
+/// POST   /counters/{id}/next                 increment and get the counter value
+/// GET    /queues/{id}/tickets                list the queue
+/// GET    /queues/{id}/tickets/{process_id}   get the process's ticket
+/// PUT    /queues/{id}/tickets/{process_id}   join the queue, with an optional JoinRequest body
+/// DELETE /queues/{id}/tickets/{process_id}   leave the queue
+/// ```
+///
+/// Responses have the same JSON shapes as the counter and queue subcommands.
+pub struct Server<B> where B: Backend {
+    pub backend: B,
+    pub region: String,
+    pub table: String,
+}
+
+/// Where a `Server` finds the counter or queue for each request.
+pub trait Backend {
+    type Counter: MonotonicCounter;
+    type Queue: MonotonicQueue;
+
+    fn counter(&self, id: &str) -> Self::Counter;
+
+    fn queue(&self, id: &str) -> Self::Queue;
+}
+
+/// Counters and queues kept in a `VersionedStore`, retrying conflicting writes after `retry_time`.
+pub struct StoreBackend<S> where S: VersionedStore {
+    pub store: Arc<S>,
+    pub retry_time: Duration,
+}
+
+impl <S> StoreBackend<S> where S: VersionedStore {
+    pub fn new(store: S, retry_time: Duration) -> StoreBackend<S> {
+        StoreBackend {
+            store: Arc::new(store),
+            retry_time: retry_time,
+        }
+    }
+}
+
+impl <S> Backend for StoreBackend<S> where S: VersionedStore {
+    type Counter = Counter<Arc<S>>;
+    type Queue = Queue<Arc<S>>;
+
+    fn counter(&self, id: &str) -> Counter<Arc<S>> {
+        Counter::new(self.store.clone(), id, self.retry_time)
+    }
+
+    fn queue(&self, id: &str) -> Queue<Arc<S>> {
+        Queue::new(self.store.clone(), id, self.retry_time)
+    }
+}
+
+/// Counters and queues kept by a `monotone daemon` listening on a Unix socket.
+#[cfg(unix)]
+pub struct SocketBackend {
+    pub path: PathBuf,
+}
+
+#[cfg(unix)]
+impl SocketBackend {
+    pub fn new<P>(path: P) -> SocketBackend where P: Into<PathBuf> {
+        SocketBackend {
+            path: path.into(),
+        }
+    }
+}
+
+#[cfg(unix)]
+impl Backend for SocketBackend {
+    type Counter = monotone::socket::counter::Counter;
+    type Queue = monotone::socket::queue::Queue;
+
+    fn counter(&self, id: &str) -> Self::Counter {
+        monotone::socket::counter::Counter::new(self.path.clone(), id)
+    }
+
+    fn queue(&self, id: &str) -> Self::Queue {
+        monotone::socket::queue::Queue::new(self.path.clone(), id)
+    }
+}
+
+/// The result of a counter or queue call, with its error converted so `error_status` can see its `ErrorKind`.
+fn checked<T, E>(result: result::Result<T, E>) -> Result<T> where E: Into<MonotoneError> {
+    result.map_err(|e| Into::<MonotoneError>::into(e).into())
+}
+
+impl <B> Server<B> where B: Backend {
+    pub fn new<S1, S2>(backend: B, region: S1, table: S2) -> Server<B> where S1: Into<String>, S2: Into<String> {
+        Server {
+            backend: backend,
+            region: region.into(),
+            table: table.into(),
+        }
+    }
+
+    /// Run the request for `method` and the decoded `path` segments, returning the response body.
+    pub fn route(&self, method: &Method, path: &[String], body: &str) -> Result<String> {
+        let segments: Vec<&str> = path.iter().map(|p| p.as_str()).collect();
+
+        match (method, segments.as_slice()) {
+            (&Method::Get, &["counters", id]) => {
+                let value = checked(self.backend.counter(id).get_value())?;
+
+                self.counter_value(id, value)
+            },
+            (&Method::Post, &["counters", id, "next"]) => {
+                let value = checked(self.backend.counter(id).next_value())?;
+
+                self.counter_value(id, value)
+            },
+            (&Method::Get, &["queues", id, "tickets"]) => {
+                let (version, tickets) = checked(self.backend.queue(id).get_tickets())?;
+
+                let result = QueueTicketListOutput {
+                    id: s(id),
+                    region: self.region.clone(),
+                    table: self.table.clone(),
+                    fencing_token: version,
+                    tickets: tickets.into_iter().map(queue_ticket).collect(),
+                };
+
+                Ok(serde_json::to_string_pretty(&result)?)
+            },
+            (&Method::Get, &["queues", id, "tickets", process_id]) => {
+                let (version, ticket) = checked(self.backend.queue(id).get_ticket(process_id))?;
+
+                self.ticket_output(id, version, ticket)
+            },
+            (&Method::Put, &["queues", id, "tickets", process_id]) => {
+                let join: JoinRequest = if body.trim().is_empty() {
+                    JoinRequest::default()
+                } else {
+                    serde_json::from_str(body)?
+                };
+
+                let lease = join.lease_millis.map(Duration::from_millis);
+
+                let (version, ticket) = checked(self.backend.queue(id).join_queue(s(process_id), join.tags, lease))?;
+
+                self.ticket_output(id, version, ticket)
+            },
+            (&Method::Delete, &["queues", id, "tickets", process_id]) => {
+                let version = checked(self.backend.queue(id).leave_queue(process_id))?;
+
+                let result = QueueTicketEmptyOutput {
+                    id: s(id),
+                    region: self.region.clone(),
+                    table: self.table.clone(),
+                    fencing_token: version,
+                };
+
+                Ok(serde_json::to_string_pretty(&result)?)
+            },
+            (_, &["counters", _]) |
+            (_, &["counters", _, "next"]) |
+            (_, &["queues", _, "tickets"]) |
+            (_, &["queues", _, "tickets", _]) => {
+                bail!(ErrorKind::MethodNotAllowed(method.to_string()));
+            },
+            _ => {
+                bail!(ErrorKind::RouteNotFound(path.join("/")));
+            }
+        }
+    }
+
+    fn counter_value(&self, id: &str, value: u64) -> Result<String> {
+        let result = CounterValue {
+            id: s(id),
+            region: self.region.clone(),
+            value: value,
+            table: self.table.clone(),
+        };
+
+        Ok(serde_json::to_string_pretty(&result)?)
+    }
+
+    fn ticket_output(&self, id: &str, version: u64, ticket: Ticket) -> Result<String> {
+        let result = QueueTicketOutput {
+            id: s(id),
+            region: self.region.clone(),
+            table: self.table.clone(),
+            fencing_token: version,
+            ticket: queue_ticket(ticket),
+        };
+
+        Ok(serde_json::to_string_pretty(&result)?)
+    }
+
+    fn respond(&self, req: &mut Request) -> Result<String> {
+        let path = match req.uri {
+            RequestUri::AbsolutePath(ref p) => p.clone(),
+            ref uri => bail!(ErrorKind::RouteNotFound(uri.to_string())),
+        };
+
+        let path = path.split('?').next().unwrap_or("");
+
+        let segments = path.split('/')
+            .filter(|p| !p.is_empty())
+            .map(|p| percent_decode(p.as_bytes()).decode_utf8().map(|p| p.into_owned()).map_err(|_| ErrorKind::RouteNotFound(s(path)).into()))
+            .collect::<Result<Vec<String>>>()?;
+
+        let mut body = String::new();
+        req.read_to_string(&mut body)?;
+
+        let method = req.method.clone();
+
+        self.route(&method, &segments, &body)
+    }
+}
+
+pub fn queue_ticket(ticket: Ticket) -> QueueTicket {
+    QueueTicket {
+        process_id: ticket.process_id,
+        counter: ticket.counter,
+        position: ticket.position,
+        tags: ticket.tags,
+    }
+}
+
+/// HTTP status for a failed request.
+pub fn error_status(e: &Error) -> StatusCode {
+    match *e.kind() {
+        ErrorKind::RouteNotFound(_) => StatusCode::NotFound,
+        ErrorKind::MethodNotAllowed(_) => StatusCode::MethodNotAllowed,
+        ErrorKind::Json(_) => StatusCode::BadRequest,
+        ErrorKind::Monotone(MonotoneErrorKind::NotFound(_)) => StatusCode::NotFound,
+        ErrorKind::Monotone(MonotoneErrorKind::WrongType) => StatusCode::Conflict,
+        ErrorKind::Monotone(MonotoneErrorKind::Conflict) => StatusCode::Conflict,
+        ErrorKind::Monotone(MonotoneErrorKind::RetriesExhausted(_)) => StatusCode::Conflict,
+        ErrorKind::Monotone(MonotoneErrorKind::Timeout) => StatusCode::GatewayTimeout,
+        _ => StatusCode::InternalServerError,
+    }
+}
+
+impl <B> Handler for Server<B> where B: Backend + Send + Sync + 'static {
+    fn handle(&self, mut req: Request, mut res: Response) {
+        info!("{} {}", req.method, req.uri);
+
+        let body = match self.respond(&mut req) {
+            Ok(body) => body,
+            Err(e) => {
+                let status = error_status(&e);
+
+                if status == StatusCode::InternalServerError {
+                    error!("{} {}: {}", req.method, req.uri, e);
+                }
+
+                *res.status_mut() = status;
+
+                serde_json::to_string_pretty(&ErrorOutput { error: e.to_string() }).unwrap_or_default()
+            }
+        };
+
+        res.headers_mut().set(ContentType::json());
+
+        if let Err(e) = res.send(body.as_bytes()) {
+            error!("error sending response: {}", e);
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::time::Duration;
+    use hyper::method::Method;
+    use hyper::status::StatusCode;
+    use serde_json;
+    use serde_json::Value;
+    use monotone::string::*;
+    use monotone::store::memory::MemoryStore;
+    use monotone::error::ErrorKind as MonotoneErrorKind;
+    use super::*;
+
+    fn server() -> Server<StoreBackend<MemoryStore>> {
+        Server::new(StoreBackend::new(MemoryStore::new(), Duration::from_millis(1)), "local", "Counters")
+    }
+
+    fn route(server: &Server<StoreBackend<MemoryStore>>, method: Method, path: &str, body: &str) -> Result<Value> {
+        let path: Vec<String> = path.split('/').filter(|p| !p.is_empty()).map(s).collect();
+
+        Ok(serde_json::from_str(&server.route(&method, &path, body)?)?)
+    }
+
+    fn status(result: Result<Value>) -> StatusCode {
+        error_status(&result.expect_err("error"))
+    }
+
+    #[test]
+    pub fn test_counter_routes() {
+        let server = server();
+
+        assert_eq!(route(&server, Method::Get, "/counters/foo", "").expect("get")["value"], 0);
+        assert_eq!(route(&server, Method::Post, "/counters/foo/next", "").expect("next")["value"], 1);
+
+        let value = route(&server, Method::Get, "/counters/foo", "").expect("get");
+        assert_eq!(value["id"], "foo");
+        assert_eq!(value["value"], 1);
+        assert_eq!(value["region"], "local");
+        assert_eq!(value["table"], "Counters");
+    }
+
+    #[test]
+    pub fn test_queue_routes() {
+        let server = server();
+
+        assert_eq!(route(&server, Method::Get, "/queues/q/tickets", "").expect("list")["tickets"].as_array().map(Vec::len), Some(0));
+
+        let foo = route(&server, Method::Put, "/queues/q/tickets/foo", "").expect("join");
+        assert_eq!(foo["fencing_token"], 1);
+        assert_eq!(foo["ticket"]["process_id"], "foo");
+        assert_eq!(foo["ticket"]["position"], 0);
+
+        let bar = route(&server, Method::Put, "/queues/q/tickets/bar", r#"{"tags": {"role": "web"}, "lease_millis": 60000}"#).expect("join");
+        assert_eq!(bar["fencing_token"], 2);
+        assert_eq!(bar["ticket"]["position"], 1);
+        assert_eq!(bar["ticket"]["tags"]["role"], "web");
+
+        assert_eq!(route(&server, Method::Get, "/queues/q/tickets/bar", "").expect("get")["ticket"], bar["ticket"]);
+        assert_eq!(route(&server, Method::Get, "/queues/q/tickets", "").expect("list")["tickets"].as_array().map(Vec::len), Some(2));
+
+        assert_eq!(route(&server, Method::Delete, "/queues/q/tickets/foo", "").expect("leave")["fencing_token"], 3);
+
+        let bar = route(&server, Method::Get, "/queues/q/tickets/bar", "").expect("get");
+        assert_eq!(bar["fencing_token"], 3);
+        assert_eq!(bar["ticket"]["position"], 0);
+    }
+
+    #[test]
+    pub fn test_join_body() {
+        let server = server();
+
+        // An empty or blank body joins without tags or a lease.
+        let foo = route(&server, Method::Put, "/queues/q/tickets/foo", " \n").expect("join");
+        assert_eq!(foo["ticket"]["tags"], Value::Object(Default::default()));
+
+        assert_eq!(status(route(&server, Method::Put, "/queues/q/tickets/bar", "{")), StatusCode::BadRequest);
+        assert_eq!(status(route(&server, Method::Put, "/queues/q/tickets/bar", r#"{"lease_millis": "soon"}"#)), StatusCode::BadRequest);
+        assert_eq!(status(route(&server, Method::Put, "/queues/q/tickets/bar", r#"{"lease": 30}"#)), StatusCode::BadRequest);
+        assert_eq!(status(route(&server, Method::Get, "/queues/q/tickets/bar", "")), StatusCode::NotFound);
+    }
+
+    #[test]
+    pub fn test_error_status() {
+        let server = server();
+
+        assert_eq!(status(route(&server, Method::Get, "/nothing/here", "")), StatusCode::NotFound);
+        assert_eq!(status(route(&server, Method::Delete, "/counters/foo", "")), StatusCode::MethodNotAllowed);
+        assert_eq!(status(route(&server, Method::Post, "/queues/q/tickets", "")), StatusCode::MethodNotAllowed);
+        assert_eq!(status(route(&server, Method::Get, "/queues/q/tickets/foo", "")), StatusCode::NotFound);
+        assert_eq!(status(route(&server, Method::Delete, "/queues/q/tickets/foo", "")), StatusCode::NotFound);
+
+        route(&server, Method::Post, "/counters/foo/next", "").expect("next");
+        assert_eq!(status(route(&server, Method::Put, "/queues/foo/tickets/bar", "")), StatusCode::Conflict);
+
+        assert_eq!(error_status(&ErrorKind::Monotone(MonotoneErrorKind::Conflict).into()), StatusCode::Conflict);
+        assert_eq!(error_status(&ErrorKind::Monotone(MonotoneErrorKind::RetriesExhausted(3)).into()), StatusCode::Conflict);
+        assert_eq!(error_status(&ErrorKind::Monotone(MonotoneErrorKind::Timeout).into()), StatusCode::GatewayTimeout);
+        assert_eq!(error_status(&ErrorKind::Monotone(MonotoneErrorKind::Backend(s("down"))).into()), StatusCode::InternalServerError);
+    }
+}
@@ -0,0 +1,230 @@
+use std::thread;
+use std::time::Duration;
+use std::collections::{BTreeMap, BTreeSet};
+
+use ::*;
+use ::error::*;
+use string::*;
+use time::*;
+
+pub const ALLOCATED_ID_TAG: &str = "allocated_id";
+
+/// Hands out the smallest free integer in `min..=max` to each process joining a queue.
+///
+/// The ID is stored in the `ALLOCATED_ID_TAG` tag of the process's ticket, so it is freed
+/// when the ticket leaves the queue or its lease expires. If two processes pick the same ID
+/// concurrently the one with the earlier ticket keeps it and the other tries again.
+pub struct BoundedAllocator<Q> where Q: MonotonicQueue {
+    pub queue: Q,
+    pub min: u64,
+    pub max: u64,
+    pub retry_time: Duration,
+    pub jitter_millis: u64,
+}
+
+impl <Q> BoundedAllocator<Q> where Q: MonotonicQueue {
+    pub fn new(queue: Q, min: u64, max: u64, retry_time: Duration) -> BoundedAllocator<Q> {
+        BoundedAllocator {
+            queue: queue,
+            min: min,
+            max: max,
+            retry_time: retry_time,
+            jitter_millis: 100,
+        }
+    }
+
+    /// Join the queue with the smallest free ID, or return the ID already held by the process.
+    /// Fails with `Exhausted` if every ID in the range is held.
+    pub fn allocate<T, L>(&self, process_id: String, tags: T, lease: L) -> Result<(FencingToken, Ticket, u64)>
+            where T: Into<Option<BTreeMap<String, String>>>, L: Into<Option<Duration>> {
+        let tags = tags.into().unwrap_or(BTreeMap::new());
+        let lease = lease.into();
+
+        loop {
+            let (ft, tickets) = self.queue.get_tickets().map_err(Into::into)?;
+
+            if let Some(ticket) = tickets.iter().find(|t| t.process_id == process_id) {
+                let id = allocated_id(ticket).ok_or_else(|| ErrorKind::NotAllocated(process_id.clone()))?;
+
+                return Ok((ft, ticket.clone(), id));
+            }
+
+            let used = tickets.iter().filter_map(allocated_id).collect::<BTreeSet<u64>>();
+            let id = (self.min..=self.max)
+                .find(|id| !used.contains(id))
+                .ok_or(ErrorKind::Exhausted(self.min, self.max))?;
+
+            let mut ticket_tags = tags.clone();
+            ticket_tags.insert(s(ALLOCATED_ID_TAG), format!("{}", id));
+
+            self.queue.join_queue(process_id.clone(), ticket_tags, lease).map_err(Into::into)?;
+
+            let (ft, tickets) = self.queue.get_tickets().map_err(Into::into)?;
+
+            let owner = tickets.into_iter().find(|t| allocated_id(t) == Some(id));
+
+            match owner {
+                Some(ref ticket) if ticket.process_id == process_id => {
+                    return Ok((ft, ticket.clone(), id));
+                },
+                _ => {
+                    info!("id {} was allocated concurrently to another process", id);
+                    self.release(&process_id)?;
+                    thread::sleep(self.retry_time.jitter(self.jitter_millis));
+                }
+            }
+        }
+    }
+
+    /// The ID held by the process.
+    pub fn get(&self, process_id: &str) -> Result<(FencingToken, Ticket, u64)> {
+        let (ft, ticket) = self.queue.get_ticket(process_id).map_err(Into::into)?;
+        let id = allocated_id(&ticket).ok_or_else(|| ErrorKind::NotAllocated(s(process_id)))?;
+
+        Ok((ft, ticket, id))
+    }
+
+    /// Leave the queue, freeing the process's ID.
+    pub fn release(&self, process_id: &str) -> Result<FencingToken> {
+        self.queue.leave_queue(process_id).map_err(Into::into)
+    }
+}
+
+pub fn allocated_id(ticket: &Ticket) -> Option<u64> {
+    ticket.tags.get(ALLOCATED_ID_TAG).and_then(|id| id.parse().ok())
+}
+
+#[cfg(test)]
+mod tests {
+    use std::cell::Cell;
+    use std::time::Duration;
+    use local::queue::Queue;
+    use string::*;
+    use super::*;
+
+    fn retry_time() -> Duration {
+        Duration::from_millis(10)
+    }
+
+    #[test]
+    pub fn test_allocator_smallest_free() {
+        let a = BoundedAllocator::new(Queue::new(), 1, 255, retry_time());
+
+        let (_ft, ticket, id) = a.allocate(s("foo"), None, None).expect("allocate");
+        assert_eq!(id, 1);
+        assert_eq!(ticket.tags.get(ALLOCATED_ID_TAG), Some(&s("1")));
+
+        let (_ft, _ticket, id) = a.allocate(s("bar"), None, None).expect("allocate");
+        assert_eq!(id, 2);
+
+        let (_ft, _ticket, id) = a.allocate(s("foo"), None, None).expect("allocate");
+        assert_eq!(id, 1);
+    }
+
+    #[test]
+    pub fn test_allocator_reuses_released() {
+        let a = BoundedAllocator::new(Queue::new(), 1, 255, retry_time());
+
+        a.allocate(s("foo"), None, None).expect("allocate");
+        a.allocate(s("bar"), None, None).expect("allocate");
+        a.release("foo").expect("release");
+
+        let (_ft, ticket, id) = a.allocate(s("baz"), None, None).expect("allocate");
+        assert_eq!(id, 1);
+        assert_eq!(ticket.position, 1);
+
+        let (_ft, _ticket, id) = a.get("bar").expect("get");
+        assert_eq!(id, 2);
+    }
+
+    #[test]
+    pub fn test_allocator_exhausted() {
+        let a = BoundedAllocator::new(Queue::new(), 1, 2, retry_time());
+
+        a.allocate(s("foo"), None, None).expect("allocate");
+        a.allocate(s("bar"), None, None).expect("allocate");
+
+        match a.allocate(s("baz"), None, None) {
+            Err(Error(ErrorKind::Exhausted(1, 2), _)) => {},
+            r => panic!("expected exhausted: {:?}", r),
+        }
+    }
+
+    #[test]
+    pub fn test_allocator_range_ends_at_max() {
+        let a = BoundedAllocator::new(Queue::new(), u64::MAX - 1, u64::MAX, retry_time());
+
+        assert_eq!(a.allocate(s("foo"), None, None).expect("allocate").2, u64::MAX - 1);
+        assert_eq!(a.allocate(s("bar"), None, None).expect("allocate").2, u64::MAX);
+        assert!(a.allocate(s("baz"), None, None).is_err());
+    }
+
+    #[test]
+    pub fn test_allocator_not_allocated() {
+        let q = Queue::new();
+        q.join_queue(s("foo"), None, None).expect("join");
+
+        let a = BoundedAllocator::new(q, 1, 255, retry_time());
+
+        match a.get("foo") {
+            Err(Error(ErrorKind::NotAllocated(ref p), _)) if p == "foo" => {},
+            r => panic!("expected not allocated: {:?}", r),
+        }
+
+        let (_ft, _ticket, id) = a.allocate(s("bar"), None, None).expect("allocate");
+        assert_eq!(id, 1);
+    }
+
+    /// Queue where a rival process takes ID 1 just before our first join.
+    struct RacingQueue {
+        queue: Queue,
+        raced: Cell<bool>,
+    }
+
+    impl MonotonicQueue for RacingQueue {
+        type Error = ::error::Error;
+
+        fn join_queue<T, L>(&self, process_id: String, tags: T, lease: L) -> ::error::Result<(FencingToken, Ticket)>
+                where T: Into<Option<BTreeMap<String, String>>>, L: Into<Option<Duration>> {
+            if !self.raced.get() {
+                self.raced.set(true);
+
+                let mut rival_tags = BTreeMap::new();
+                rival_tags.insert(s(ALLOCATED_ID_TAG), s("1"));
+                self.queue.join_queue(s("rival"), rival_tags, None)?;
+            }
+
+            self.queue.join_queue(process_id, tags, lease)
+        }
+
+        fn leave_queue(&self, process_id: &str) -> ::error::Result<FencingToken> {
+            self.queue.leave_queue(process_id)
+        }
+
+        fn heartbeat(&self, process_id: &str) -> ::error::Result<(FencingToken, Ticket)> {
+            self.queue.heartbeat(process_id)
+        }
+
+        fn get_ticket(&self, process_id: &str) -> ::error::Result<(FencingToken, Ticket)> {
+            self.queue.get_ticket(process_id)
+        }
+
+        fn get_tickets(&self) -> ::error::Result<(FencingToken, Vec<Ticket>)> {
+            self.queue.get_tickets()
+        }
+    }
+
+    #[test]
+    pub fn test_allocator_concurrent_same_id() {
+        let q = RacingQueue { queue: Queue::new(), raced: Cell::new(false) };
+        let a = BoundedAllocator::new(q, 1, 255, retry_time());
+
+        let (_ft, ticket, id) = a.allocate(s("foo"), None, None).expect("allocate");
+        assert_eq!(id, 2);
+        assert_eq!(ticket.position, 1);
+
+        let (_ft, tickets) = a.queue.get_tickets().expect("get all");
+        assert_eq!(tickets.len(), 2);
+        assert_eq!(&tickets[0].process_id, "rival");
+    }
+}
@@ -0,0 +1,860 @@
+//! In-memory stand-in for DynamoDB, for testing the `aws` module without AWS.
+//!
+//! `Emulator` is a `DispatchSignedRequest`, so it takes the place of the HTTP client in a
+//! `DynamoDbClient`. It answers the requests monotone makes: GetItem, PutItem and DeleteItem
+//! with condition expressions, CreateTable, DescribeTable and ListTables. Tables have a single
+//! hash key and are `ACTIVE` as soon as they are created. Errors come back with the same
+//! `__type` and message as the real service.
+//!
+//! Condition expressions may use `attribute_exists`, `attribute_not_exists`, the comparison
+//! operators `=`, `<>`, `<`, `<=`, `>` and `>=`, `AND`, `OR`, `NOT` and parentheses, on
+//! top-level attributes named directly or through `ExpressionAttributeNames`.
+
+use std::cmp::Ordering;
+use std::collections::{BTreeMap, VecDeque};
+use std::io::Cursor;
+use std::result;
+use std::sync::{Arc, Mutex};
+use std::time::{SystemTime, UNIX_EPOCH};
+use hyper::status::StatusCode;
+use rusoto::{DispatchSignedRequest, SignedRequest, HttpResponse, HttpDispatchError};
+use serde::Serialize;
+use serde::de::Deserialize;
+use serde_json;
+use serde_json::Value;
+use string::*;
+use super::*;
+
+/// An item as it appears on the wire: attribute names to `{"S": ...}`, `{"N": ...}` and so on.
+pub type Item = BTreeMap<String, Value>;
+
+const ERROR_PREFIX: &str = "com.amazonaws.dynamodb.v20120810#";
+
+type Interleaved = Box<dyn FnMut(&Emulator) + Send>;
+
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
+pub struct KeySchemaElement {
+    #[serde(rename="AttributeName")]
+    pub attribute_name: String,
+    #[serde(rename="KeyType")]
+    pub key_type: String,
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
+pub struct AttributeDefinition {
+    #[serde(rename="AttributeName")]
+    pub attribute_name: String,
+    #[serde(rename="AttributeType")]
+    pub attribute_type: String,
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
+pub struct ProvisionedThroughput {
+    #[serde(rename="ReadCapacityUnits")]
+    pub read_capacity_units: i64,
+    #[serde(rename="WriteCapacityUnits")]
+    pub write_capacity_units: i64,
+}
+
+#[derive(Serialize, Debug, Clone)]
+struct TableDescription {
+    #[serde(rename="AttributeDefinitions")]
+    attribute_definitions: Vec<AttributeDefinition>,
+    #[serde(rename="CreationDateTime")]
+    creation_date_time: f64,
+    #[serde(rename="ItemCount")]
+    item_count: usize,
+    #[serde(rename="KeySchema")]
+    key_schema: Vec<KeySchemaElement>,
+    #[serde(rename="ProvisionedThroughput")]
+    provisioned_throughput: ProvisionedThroughput,
+    #[serde(rename="TableArn")]
+    table_arn: String,
+    #[serde(rename="TableName")]
+    table_name: String,
+    #[serde(rename="TableStatus")]
+    table_status: String,
+}
+
+#[derive(Deserialize)]
+struct GetItemRequest {
+    #[serde(rename="TableName")]
+    table_name: String,
+    #[serde(rename="Key")]
+    key: Item,
+}
+
+#[derive(Deserialize)]
+struct PutItemRequest {
+    #[serde(rename="TableName")]
+    table_name: String,
+    #[serde(rename="Item")]
+    item: Item,
+    #[serde(rename="ConditionExpression", default)]
+    condition_expression: Option<String>,
+    #[serde(rename="ExpressionAttributeNames", default)]
+    expression_attribute_names: Option<BTreeMap<String, String>>,
+    #[serde(rename="ExpressionAttributeValues", default)]
+    expression_attribute_values: Option<Item>,
+    #[serde(rename="ReturnValues", default)]
+    return_values: Option<String>,
+}
+
+#[derive(Deserialize)]
+struct DeleteItemRequest {
+    #[serde(rename="TableName")]
+    table_name: String,
+    #[serde(rename="Key")]
+    key: Item,
+    #[serde(rename="ConditionExpression", default)]
+    condition_expression: Option<String>,
+    #[serde(rename="ExpressionAttributeNames", default)]
+    expression_attribute_names: Option<BTreeMap<String, String>>,
+    #[serde(rename="ExpressionAttributeValues", default)]
+    expression_attribute_values: Option<Item>,
+    #[serde(rename="ReturnValues", default)]
+    return_values: Option<String>,
+}
+
+#[derive(Deserialize)]
+struct CreateTableRequest {
+    #[serde(rename="TableName")]
+    table_name: String,
+    #[serde(rename="KeySchema")]
+    key_schema: Vec<KeySchemaElement>,
+    #[serde(rename="AttributeDefinitions")]
+    attribute_definitions: Vec<AttributeDefinition>,
+    #[serde(rename="ProvisionedThroughput")]
+    provisioned_throughput: ProvisionedThroughput,
+}
+
+#[derive(Deserialize)]
+struct DescribeTableRequest {
+    #[serde(rename="TableName")]
+    table_name: String,
+}
+
+#[derive(Deserialize)]
+struct ListTablesRequest {
+    #[serde(rename="ExclusiveStartTableName", default)]
+    exclusive_start_table_name: Option<String>,
+    #[serde(rename="Limit", default)]
+    limit: Option<usize>,
+}
+
+#[derive(Serialize)]
+struct GetItemResponse {
+    #[serde(rename="Item", skip_serializing_if="Option::is_none")]
+    item: Option<Item>,
+}
+
+#[derive(Serialize)]
+struct WriteItemResponse {
+    #[serde(rename="Attributes", skip_serializing_if="Option::is_none")]
+    attributes: Option<Item>,
+}
+
+#[derive(Serialize)]
+struct TableResponse {
+    #[serde(rename="Table")]
+    table: TableDescription,
+}
+
+#[derive(Serialize)]
+struct CreateTableResponse {
+    #[serde(rename="TableDescription")]
+    table_description: TableDescription,
+}
+
+#[derive(Serialize)]
+struct ListTablesResponse {
+    #[serde(rename="TableNames")]
+    table_names: Vec<String>,
+    #[serde(rename="LastEvaluatedTableName", skip_serializing_if="Option::is_none")]
+    last_evaluated_table_name: Option<String>,
+}
+
+#[derive(Debug, Clone)]
+struct Table {
+    description: TableDescription,
+    hash_key: String,
+    items: BTreeMap<String, Item>,
+}
+
+impl Table {
+    fn key(&self, item: &Item) -> result::Result<String, AWSError> {
+        match item.get(&self.hash_key).and_then(|v| v.as_object()) {
+            Some(value) => {
+                value.get("S").or_else(|| value.get("N"))
+                    .and_then(|v| v.as_str())
+                    .map(|k| k.to_owned())
+                    .ok_or_else(|| validation("The provided key element does not match the schema"))
+            },
+            None => Err(validation("The provided key element does not match the schema")),
+        }
+    }
+
+    fn describe(&self) -> TableDescription {
+        TableDescription {
+            item_count: self.items.len(),
+            ..self.description.clone()
+        }
+    }
+}
+
+fn error(typ: &str, message: String) -> AWSError {
+    AWSError {
+        typ: format!("{}{}", ERROR_PREFIX, typ),
+        message: message,
+    }
+}
+
+fn validation(message: &str) -> AWSError {
+    error("ValidationException", s(message))
+}
+
+fn table_not_found(name: &str) -> AWSError {
+    error("ResourceNotFoundException", format!("Requested resource not found: Table: {} not found", name))
+}
+
+fn parse<T>(payload: &[u8]) -> result::Result<T, AWSError> where T: Deserialize {
+    serde_json::from_slice(payload).map_err(|e| error("SerializationException", e.to_string()))
+}
+
+fn to_body<T>(value: &T) -> result::Result<String, AWSError> where T: Serialize {
+    serde_json::to_string(value).map_err(|e| error("InternalServerError", e.to_string()))
+}
+
+/// In-memory DynamoDB. Clones share the same tables.
+#[derive(Clone, Default)]
+pub struct Emulator {
+    tables: Arc<Mutex<BTreeMap<String, Table>>>,
+    interleaved: Arc<Mutex<VecDeque<Interleaved>>>,
+}
+
+impl Emulator {
+    pub fn new() -> Emulator {
+        Emulator::default()
+    }
+
+    /// An emulator with a table keyed on a string `ID`, as `dynamodb::create_table` makes.
+    pub fn with_table<S>(name: S) -> Emulator where S: Into<String> {
+        let emulator = Emulator::new();
+
+        emulator.create_table(name, "ID");
+
+        emulator
+    }
+
+    /// Create a table with a string hash key, returning false if it already exists.
+    pub fn create_table<S1, S2>(&self, name: S1, hash_key: S2) -> bool where S1: Into<String>, S2: Into<String> {
+        let hash_key = hash_key.into();
+
+        let request = CreateTableRequest {
+            table_name: name.into(),
+            key_schema: vec![KeySchemaElement { attribute_name: hash_key.clone(), key_type: s("HASH") }],
+            attribute_definitions: vec![AttributeDefinition { attribute_name: hash_key, attribute_type: s("S") }],
+            provisioned_throughput: ProvisionedThroughput { read_capacity_units: 1, write_capacity_units: 1 },
+        };
+
+        self.try_create_table(request).is_ok()
+    }
+
+    /// The item with string hash key `key` in `table`, if there is one.
+    pub fn get_item(&self, table: &str, key: &str) -> Option<Item> {
+        let tables = self.tables.lock().unwrap();
+
+        tables.get(table).and_then(|t| t.items.get(key).cloned())
+    }
+
+    /// Write `item` to `table` unconditionally, returning false if there is no such table.
+    pub fn put_item(&self, table: &str, item: Item) -> bool {
+        let mut tables = self.tables.lock().unwrap();
+
+        match tables.get_mut(table) {
+            Some(table) => {
+                match table.key(&item) {
+                    Ok(key) => {
+                        table.items.insert(key, item);
+                        true
+                    },
+                    Err(_) => false,
+                }
+            },
+            None => false,
+        }
+    }
+
+    /// Run `f` just before the next PutItem or DeleteItem request is applied, as though another
+    /// client got its write in first. `f` may make requests of its own through the emulator.
+    pub fn interleave<F>(&self, f: F) where F: FnOnce(&Emulator) + Send + 'static {
+        let mut f = Some(f);
+
+        self.interleaved.lock().unwrap().push_back(Box::new(move |emulator| {
+            if let Some(f) = f.take() {
+                f(emulator);
+            }
+        }));
+    }
+
+    fn run_interleaved(&self) {
+        let next = self.interleaved.lock().unwrap().pop_front();
+
+        if let Some(mut f) = next {
+            f(self);
+        }
+    }
+
+    fn handle(&self, target: &str, payload: &[u8]) -> result::Result<String, AWSError> {
+        debug!("emulator {} {}", target, String::from_utf8_lossy(payload));
+
+        match target {
+            "GetItem" => self.try_get_item(parse(payload)?),
+            "PutItem" => {
+                self.run_interleaved();
+                self.try_put_item(parse(payload)?)
+            },
+            "DeleteItem" => {
+                self.run_interleaved();
+                self.try_delete_item(parse(payload)?)
+            },
+            "CreateTable" => self.try_create_table(parse(payload)?),
+            "DescribeTable" => self.try_describe_table(parse(payload)?),
+            "ListTables" => self.try_list_tables(parse(payload)?),
+            _ => Err(error("UnknownOperationException", format!("unsupported operation: {}", target))),
+        }
+    }
+
+    fn try_get_item(&self, request: GetItemRequest) -> result::Result<String, AWSError> {
+        let tables = self.tables.lock().unwrap();
+        let table = tables.get(&request.table_name).ok_or_else(|| table_not_found(&request.table_name))?;
+        let key = table.key(&request.key)?;
+
+        to_body(&GetItemResponse { item: table.items.get(&key).cloned() })
+    }
+
+    fn try_put_item(&self, request: PutItemRequest) -> result::Result<String, AWSError> {
+        let mut tables = self.tables.lock().unwrap();
+        let table = tables.get_mut(&request.table_name).ok_or_else(|| table_not_found(&request.table_name))?;
+        let key = table.key(&request.item)?;
+
+        if let Some(ref condition) = request.condition_expression {
+            let names = request.expression_attribute_names.unwrap_or_default();
+            let values = request.expression_attribute_values.unwrap_or_default();
+
+            if !Condition::parse(condition)?.eval(table.items.get(&key), &names, &values)? {
+                return Err(error("ConditionalCheckFailedException", s("The conditional request failed")));
+            }
+        }
+
+        let old = table.items.insert(key, request.item);
+
+        to_body(&WriteItemResponse { attributes: return_values(request.return_values, old) })
+    }
+
+    fn try_delete_item(&self, request: DeleteItemRequest) -> result::Result<String, AWSError> {
+        let mut tables = self.tables.lock().unwrap();
+        let table = tables.get_mut(&request.table_name).ok_or_else(|| table_not_found(&request.table_name))?;
+        let key = table.key(&request.key)?;
+
+        if let Some(ref condition) = request.condition_expression {
+            let names = request.expression_attribute_names.unwrap_or_default();
+            let values = request.expression_attribute_values.unwrap_or_default();
+
+            if !Condition::parse(condition)?.eval(table.items.get(&key), &names, &values)? {
+                return Err(error("ConditionalCheckFailedException", s("The conditional request failed")));
+            }
+        }
+
+        let old = table.items.remove(&key);
+
+        to_body(&WriteItemResponse { attributes: return_values(request.return_values, old) })
+    }
+
+    fn try_create_table(&self, request: CreateTableRequest) -> result::Result<String, AWSError> {
+        let mut tables = self.tables.lock().unwrap();
+
+        if tables.contains_key(&request.table_name) {
+            return Err(error("ResourceInUseException", format!("Table already exists: {}", request.table_name)));
+        }
+
+        let hash_key = request.key_schema.iter()
+            .find(|k| k.key_type == "HASH")
+            .map(|k| k.attribute_name.clone())
+            .ok_or_else(|| validation("No Hash Key specified in schema.  All Dynamo DB tables must have exactly one hash key"))?;
+
+        let created = SystemTime::now().duration_since(UNIX_EPOCH).map(|d| d.as_secs() as f64).unwrap_or(0.0);
+
+        let table = Table {
+            description: TableDescription {
+                attribute_definitions: request.attribute_definitions,
+                creation_date_time: created,
+                item_count: 0,
+                key_schema: request.key_schema,
+                provisioned_throughput: request.provisioned_throughput,
+                table_arn: format!("arn:aws:dynamodb:emulator:000000000000:table/{}", request.table_name),
+                table_name: request.table_name.clone(),
+                table_status: s("ACTIVE"),
+            },
+            hash_key: hash_key,
+            items: BTreeMap::new(),
+        };
+
+        let description = table.describe();
+
+        tables.insert(request.table_name, table);
+
+        to_body(&CreateTableResponse { table_description: description })
+    }
+
+    fn try_describe_table(&self, request: DescribeTableRequest) -> result::Result<String, AWSError> {
+        let tables = self.tables.lock().unwrap();
+        let table = tables.get(&request.table_name).ok_or_else(|| table_not_found(&request.table_name))?;
+
+        to_body(&TableResponse { table: table.describe() })
+    }
+
+    fn try_list_tables(&self, request: ListTablesRequest) -> result::Result<String, AWSError> {
+        let tables = self.tables.lock().unwrap();
+        let limit = request.limit.unwrap_or(100);
+
+        if !(1..=100).contains(&limit) {
+            return Err(validation(&format!("1 validation error detected: Value '{}' at 'limit' failed to satisfy constraint: Member must have value between 1 and 100", limit)));
+        }
+
+        let names: Vec<String> = tables.keys()
+            .filter(|name| request.exclusive_start_table_name.as_ref().map(|start| *name > start).unwrap_or(true))
+            .cloned()
+            .collect();
+
+        let last_evaluated_table_name = if names.len() > limit {
+            Some(names[limit - 1].clone())
+        } else {
+            None
+        };
+
+        to_body(&ListTablesResponse {
+            table_names: names.into_iter().take(limit).collect(),
+            last_evaluated_table_name: last_evaluated_table_name,
+        })
+    }
+}
+
+fn return_values(return_values: Option<String>, old: Option<Item>) -> Option<Item> {
+    match return_values.as_ref().map(|r| &r[..]) {
+        Some("ALL_OLD") => old,
+        _ => None,
+    }
+}
+
+impl DispatchSignedRequest for Emulator {
+    fn dispatch(&self, request: &SignedRequest) -> result::Result<HttpResponse, HttpDispatchError> {
+        let target = request.headers.iter()
+            .find(|(name, _)| name.eq_ignore_ascii_case("x-amz-target"))
+            .and_then(|(_, values)| values.first())
+            .map(|value| String::from_utf8_lossy(value).into_owned())
+            .unwrap_or_default();
+
+        let operation = target.rsplit('.').next().unwrap_or("");
+        let payload = request.payload.as_ref().map(|p| &p[..]).unwrap_or(b"{}");
+
+        let (status, body) = match self.handle(operation, payload) {
+            Ok(body) => (StatusCode::Ok, body),
+            Err(e) => (StatusCode::BadRequest, serde_json::to_string(&e).unwrap_or_default()),
+        };
+
+        Ok(HttpResponse {
+            status: status,
+            body: Box::new(Cursor::new(body.into_bytes())),
+            headers: Default::default(),
+        })
+    }
+}
+
+#[derive(Debug, Clone, PartialEq)]
+enum Token {
+    Word(String),
+    Name(String),
+    Value(String),
+    Op(String),
+    Open,
+    Close,
+}
+
+#[derive(Debug, Clone, PartialEq)]
+enum Operand {
+    Path(String),
+    Value(String),
+}
+
+/// Parsed condition expression.
+#[derive(Debug, Clone, PartialEq)]
+enum Condition {
+    Compare(Operand, String, Operand),
+    Exists(Operand),
+    NotExists(Operand),
+    And(Box<Condition>, Box<Condition>),
+    Or(Box<Condition>, Box<Condition>),
+    Not(Box<Condition>),
+}
+
+fn invalid(expression: &str, reason: &str) -> AWSError {
+    validation(&format!("Invalid ConditionExpression: {}; expression: {}", reason, expression))
+}
+
+fn tokenise(expression: &str) -> result::Result<Vec<Token>, AWSError> {
+    let mut tokens = vec![];
+    let mut chars = expression.chars().peekable();
+
+    while let Some(c) = chars.next() {
+        match c {
+            ' ' | '\t' | '\n' | '\r' => {},
+            '(' => tokens.push(Token::Open),
+            ')' => tokens.push(Token::Close),
+            '=' => tokens.push(Token::Op(s("="))),
+            '<' | '>' => {
+                let mut op = c.to_string();
+
+                if chars.peek() == Some(&'=') || (c == '<' && chars.peek() == Some(&'>')) {
+                    op.push(chars.next().unwrap());
+                }
+
+                tokens.push(Token::Op(op));
+            },
+            c if c == ':' || c == '#' || c.is_alphanumeric() || c == '_' => {
+                let mut word = c.to_string();
+
+                while let Some(&next) = chars.peek() {
+                    if next.is_alphanumeric() || next == '_' {
+                        word.push(next);
+                        chars.next();
+                    } else {
+                        break;
+                    }
+                }
+
+                tokens.push(match c {
+                    ':' => Token::Value(word),
+                    '#' => Token::Name(word),
+                    _ => Token::Word(word),
+                });
+            },
+            c => return Err(invalid(expression, &format!("unexpected character {}", c))),
+        }
+    }
+
+    Ok(tokens)
+}
+
+struct Parser<'a> {
+    expression: &'a str,
+    tokens: Vec<Token>,
+    pos: usize,
+}
+
+impl <'a> Parser<'a> {
+    fn peek(&self) -> Option<&Token> {
+        self.tokens.get(self.pos)
+    }
+
+    fn next(&mut self) -> Option<Token> {
+        let token = self.tokens.get(self.pos).cloned();
+        self.pos += 1;
+        token
+    }
+
+    fn is_keyword(&self, keyword: &str) -> bool {
+        match self.peek() {
+            Some(Token::Word(w)) => w.eq_ignore_ascii_case(keyword),
+            _ => false,
+        }
+    }
+
+    fn expect(&mut self, token: Token) -> result::Result<(), AWSError> {
+        match self.next() {
+            Some(ref t) if *t == token => Ok(()),
+            t => Err(invalid(self.expression, &format!("expected {:?}, found {:?}", token, t))),
+        }
+    }
+
+    fn or(&mut self) -> result::Result<Condition, AWSError> {
+        let mut left = self.and()?;
+
+        while self.is_keyword("OR") {
+            self.next();
+            left = Condition::Or(Box::new(left), Box::new(self.and()?));
+        }
+
+        Ok(left)
+    }
+
+    fn and(&mut self) -> result::Result<Condition, AWSError> {
+        let mut left = self.not()?;
+
+        while self.is_keyword("AND") {
+            self.next();
+            left = Condition::And(Box::new(left), Box::new(self.not()?));
+        }
+
+        Ok(left)
+    }
+
+    fn not(&mut self) -> result::Result<Condition, AWSError> {
+        if self.is_keyword("NOT") {
+            self.next();
+
+            return Ok(Condition::Not(Box::new(self.not()?)));
+        }
+
+        self.primary()
+    }
+
+    fn primary(&mut self) -> result::Result<Condition, AWSError> {
+        if self.peek() == Some(&Token::Open) {
+            self.next();
+            let condition = self.or()?;
+            self.expect(Token::Close)?;
+
+            return Ok(condition);
+        }
+
+        if self.is_keyword("attribute_exists") || self.is_keyword("attribute_not_exists") {
+            let exists = self.is_keyword("attribute_exists");
+            self.next();
+            self.expect(Token::Open)?;
+            let path = self.operand()?;
+            self.expect(Token::Close)?;
+
+            return Ok(if exists { Condition::Exists(path) } else { Condition::NotExists(path) });
+        }
+
+        let left = self.operand()?;
+
+        let op = match self.next() {
+            Some(Token::Op(op)) => op,
+            t => return Err(invalid(self.expression, &format!("expected a comparison, found {:?}", t))),
+        };
+
+        let right = self.operand()?;
+
+        Ok(Condition::Compare(left, op, right))
+    }
+
+    fn operand(&mut self) -> result::Result<Operand, AWSError> {
+        match self.next() {
+            Some(Token::Word(w)) => Ok(Operand::Path(w)),
+            Some(Token::Name(n)) => Ok(Operand::Path(n)),
+            Some(Token::Value(v)) => Ok(Operand::Value(v)),
+            t => Err(invalid(self.expression, &format!("expected an operand, found {:?}", t))),
+        }
+    }
+}
+
+fn compare(left: &Value, right: &Value) -> Option<Ordering> {
+    let number = |v: &Value| v.as_object().and_then(|o| o.get("N")).and_then(|n| n.as_str()).and_then(|n| n.parse::<f64>().ok());
+    let string = |v: &Value| v.as_object().and_then(|o| o.get("S")).and_then(|s| s.as_str()).map(|s| s.to_owned());
+
+    if let (Some(l), Some(r)) = (number(left), number(right)) {
+        l.partial_cmp(&r)
+    } else if let (Some(l), Some(r)) = (string(left), string(right)) {
+        Some(l.cmp(&r))
+    } else if left == right {
+        Some(Ordering::Equal)
+    } else {
+        None
+    }
+}
+
+impl Condition {
+    fn parse(expression: &str) -> result::Result<Condition, AWSError> {
+        let mut parser = Parser {
+            expression: expression,
+            tokens: tokenise(expression)?,
+            pos: 0,
+        };
+
+        let condition = parser.or()?;
+
+        if let Some(t) = parser.next() {
+            return Err(invalid(expression, &format!("unexpected {:?}", t)));
+        }
+
+        Ok(condition)
+    }
+
+    fn resolve<'a>(operand: &Operand, item: Option<&'a Item>, names: &BTreeMap<String, String>, values: &'a Item) -> result::Result<Option<&'a Value>, AWSError> {
+        match *operand {
+            Operand::Path(ref path) => {
+                let name = if path.starts_with('#') {
+                    names.get(path).ok_or_else(|| validation(&format!("An expression attribute name used in the document path is not defined; attribute name: {}", path)))?
+                } else {
+                    path
+                };
+
+                Ok(item.and_then(|i| i.get(name)))
+            },
+            Operand::Value(ref value) => {
+                values.get(value)
+                    .map(Some)
+                    .ok_or_else(|| validation(&format!("An expression attribute value used in expression is not defined; attribute value: {}", value)))
+            },
+        }
+    }
+
+    /// Evaluate against the item currently stored, if any.
+    fn eval(&self, item: Option<&Item>, names: &BTreeMap<String, String>, values: &Item) -> result::Result<bool, AWSError> {
+        Ok(match *self {
+            Condition::Exists(ref path) => Condition::resolve(path, item, names, values)?.is_some(),
+            Condition::NotExists(ref path) => Condition::resolve(path, item, names, values)?.is_none(),
+            Condition::And(ref l, ref r) => l.eval(item, names, values)? && r.eval(item, names, values)?,
+            Condition::Or(ref l, ref r) => l.eval(item, names, values)? || r.eval(item, names, values)?,
+            Condition::Not(ref c) => !c.eval(item, names, values)?,
+            Condition::Compare(ref l, ref op, ref r) => {
+                let left = Condition::resolve(l, item, names, values)?;
+                let right = Condition::resolve(r, item, names, values)?;
+
+                match (left, right) {
+                    (Some(left), Some(right)) => {
+                        let ordering = compare(left, right);
+
+                        match op.as_str() {
+                            "=" => ordering == Some(Ordering::Equal),
+                            "<>" => ordering != Some(Ordering::Equal),
+                            "<" => ordering == Some(Ordering::Less),
+                            "<=" => ordering == Some(Ordering::Less) || ordering == Some(Ordering::Equal),
+                            ">" => ordering == Some(Ordering::Greater),
+                            ">=" => ordering == Some(Ordering::Greater) || ordering == Some(Ordering::Equal),
+                            _ => return Err(validation(&format!("Invalid ConditionExpression: unknown operator {}", op))),
+                        }
+                    },
+                    _ => false,
+                }
+            },
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::env;
+    use std::time::Duration;
+    use std::collections::BTreeMap;
+    use rusoto::{DefaultCredentialsProvider, Region};
+    use rusoto::dynamodb::DynamoDbClient;
+    use serde_json;
+    use serde_json::Value;
+    use aws::counter::Counter;
+    use aws::queue::Queue;
+    use aws::dynamodb;
+    use aws::store::DynamoDbStore;
+    use conformance;
+    use string::*;
+    use ::*;
+    use super::*;
+
+    fn client(emulator: &Emulator) -> DynamoDbClient<DefaultCredentialsProvider, Emulator> {
+        if env::var("AWS_ACCESS_KEY_ID").is_err() {
+            env::set_var("AWS_ACCESS_KEY_ID", "emulator");
+            env::set_var("AWS_SECRET_ACCESS_KEY", "emulator");
+        }
+
+        DynamoDbClient::new(emulator.clone(), DefaultCredentialsProvider::new().expect("credentials"), Region::EuWest1)
+    }
+
+    fn value(json: &str) -> Value {
+        serde_json::from_str(json).expect("json")
+    }
+
+    fn item(id: &str, version: u64) -> Item {
+        let mut item = BTreeMap::new();
+        item.insert(s("ID"), value(&format!(r#"{{"S": "{}"}}"#, id)));
+        item.insert(s("Version"), value(&format!(r#"{{"N": "{}"}}"#, version)));
+        item
+    }
+
+    fn eval(expression: &str, item: Option<&Item>) -> bool {
+        let mut names = BTreeMap::new();
+        names.insert(s("#version"), s("Version"));
+
+        let mut values = BTreeMap::new();
+        values.insert(s(":one"), value(r#"{"N": "1"}"#));
+        values.insert(s(":ten"), value(r#"{"N": "10"}"#));
+        values.insert(s(":foo"), value(r#"{"S": "foo"}"#));
+
+        Condition::parse(expression).expect("parse").eval(item, &names, &values).expect("eval")
+    }
+
+    #[test]
+    pub fn test_condition_expressions() {
+        let item = item("foo", 2);
+
+        assert!(eval("attribute_exists(ID)", Some(&item)));
+        assert!(!eval("attribute_exists(ID)", None));
+        assert!(eval("attribute_not_exists(ID)", None));
+        assert!(eval("ID = :foo AND #version > :one", Some(&item)));
+        assert!(!eval("#version = :one", Some(&item)));
+        assert!(eval("#version <> :one", Some(&item)));
+        assert!(eval("#version < :ten", Some(&item)));
+        assert!(eval("#version >= :one AND #version <= :ten", Some(&item)));
+        assert!(eval("attribute_not_exists(ID) OR #version = :one OR ID = :foo", Some(&item)));
+        assert!(eval("NOT (#version = :one) and (ID = :foo)", Some(&item)));
+        assert!(!eval("#version = :one", None));
+
+        assert!(Condition::parse("#version =").is_err());
+        assert!(Condition::parse("attribute_exists(ID").is_err());
+        assert!(Condition::parse("ID = :foo :foo").is_err());
+    }
+
+    #[test]
+    pub fn test_tables() {
+        let emulator = Emulator::new();
+        let client = client(&emulator);
+
+        assert_eq!(dynamodb::list_tables(&client).expect("list"), Vec::<String>::new());
+        assert!(dynamodb::describe_table(&client, "Counters").is_err());
+
+        let table = dynamodb::create_table_if_needed(&client, "Counters", 1, 1).expect("create");
+        assert_eq!(table.table_status, Some(s("ACTIVE")));
+        assert!(dynamodb::create_table(&client, "Counters", 1, 1).is_err());
+
+        dynamodb::create_table(&client, "Queues", 1, 1).expect("create");
+        assert_eq!(dynamodb::list_tables(&client).expect("list"), vec![s("Counters"), s("Queues")]);
+
+        assert!(emulator.put_item("Counters", item("foo", 1)));
+        assert!(!emulator.put_item("Locks", item("foo", 1)));
+        assert_eq!(emulator.get_item("Counters", "foo").and_then(|i| i.get("Version").cloned()), Some(value(r#"{"N": "1"}"#)));
+
+        let payload = br#"{"ExclusiveStartTableName": "Counters", "Limit": 1}"#;
+        let response: Value = serde_json::from_str(&emulator.handle("ListTables", payload).expect("list")).expect("json");
+        assert_eq!(response, value(r#"{"TableNames": ["Queues"]}"#));
+
+        for payload in &[&br#"{"Limit": 0}"#[..], &br#"{"Limit": 101}"#[..]] {
+            match emulator.handle("ListTables", payload) {
+                Err(ref e) if e.typ.ends_with("#ValidationException") => {},
+                r => panic!("expected ValidationException, got {:?}", r),
+            }
+        }
+    }
+
+    #[test]
+    pub fn test_interleaved_write() {
+        let emulator = Emulator::with_table("Counters");
+        let c = Counter::new(DynamoDbStore::new(client(&emulator), "Counters"), "foo", Duration::from_millis(1));
+        let other = Counter::new(DynamoDbStore::new(client(&emulator), "Counters"), "foo", Duration::from_millis(1));
+
+        assert_eq!(c.next_value().expect("next"), 1);
+
+        emulator.interleave(move |_| {
+            assert_eq!(other.next_value().expect("next"), 2);
+        });
+
+        assert_eq!(c.next_value().expect("next"), 3);
+        assert_eq!(c.get_value().expect("get"), 3);
+    }
+
+    #[test]
+    pub fn test_conformance() {
+        conformance::check_counter(|| Counter::new(DynamoDbStore::new(client(&Emulator::with_table("Counters")), "Counters"), "foo", Duration::from_millis(1)));
+        conformance::check_queue(|| Queue::new(DynamoDbStore::new(client(&Emulator::with_table("Counters")), "Counters"), "foo", Duration::from_millis(1)));
+    }
+}
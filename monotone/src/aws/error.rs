@@ -30,29 +30,9 @@ error_chain! {
             display("table not found: {}", t)
         }
 
-        ConditionalUpdateFailed {
-            description("conditional update failed")
-            display("conditional update failed")
-        }
-
-        UnrecognisedCounterType {
-            description("unrecognised counter type")
-            display("unrecognised counter type")
-        }
-
-        UnrecognisedQueueType {
-            description("unrecognised counter type")
-            display("unrecognised counter type")
-        }
-
         MissingAttribute {
             description("missing attribute")
             display("missing attribute")
         }
-
-        TicketNotFound(process_id: String) {
-            description("ticket not found")
-            display("ticket not found")
-        }
     }
 }
\ No newline at end of file
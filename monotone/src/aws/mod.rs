@@ -1,9 +1,13 @@
 pub mod counter;
 pub mod dynamodb;
+pub mod emulator;
 pub mod error;
+#[cfg(feature = "async")]
+pub mod nonblocking;
 pub mod queue;
+pub mod store;
 
-#[derive(Serialize, Deserialize)]
+#[derive(Serialize, Deserialize, Debug, Clone)]
 pub struct AWSError {
     #[serde(rename="__type")]
     pub typ: String,
@@ -0,0 +1,8 @@
+use store;
+use super::store::DynamoDbStore;
+
+/// Async view of a DynamoDB `Counter`.
+pub type AsyncCounter<P,D> = store::nonblocking::AsyncCounter<DynamoDbStore<P,D>>;
+
+/// Async view of a DynamoDB `Queue`.
+pub type AsyncQueue<P,D> = store::nonblocking::AsyncQueue<DynamoDbStore<P,D>>;
@@ -0,0 +1,130 @@
+use std::default::Default;
+use std::collections::HashMap;
+use serde_json;
+use rusoto::{ProvideAwsCredentials, DispatchSignedRequest};
+use rusoto::dynamodb::*;
+use string::*;
+use store::*;
+use super::*;
+use super::error::*;
+
+/// `VersionedStore` keeping each row as an item of a DynamoDB table keyed on `ID`.
+pub struct DynamoDbStore<P,D> where P: ProvideAwsCredentials, D: DispatchSignedRequest {
+    pub client: DynamoDbClient<P,D>,
+    pub table_name: String,
+}
+
+impl <P,D> DynamoDbStore<P,D> where P: ProvideAwsCredentials, D: DispatchSignedRequest {
+    pub fn new<S>(client: DynamoDbClient<P,D>, table_name: S) -> DynamoDbStore<P,D> where S: Into<String> {
+        DynamoDbStore {
+            client: client,
+            table_name: table_name.into(),
+        }
+    }
+}
+
+impl <P,D> VersionedStore for DynamoDbStore<P,D> where P: ProvideAwsCredentials, D: DispatchSignedRequest {
+    type Error = Error;
+
+    fn get(&self, id: &str) -> Result<Option<Row>> {
+        let mut key = HashMap::new();
+        key.insert(s("ID"), AttributeValue { s: Some(s(id)), ..Default::default() });
+
+        let get_item_input = GetItemInput {
+            consistent_read: Some(true),
+            key: key,
+            table_name: self.table_name.clone(),
+            ..Default::default()
+        };
+
+        let item = self.client.get_item(&get_item_input)?;
+
+        if let Some(item) = item.item {
+            debug!("table={} id={} : {:?}", self.table_name, id, item);
+
+            let typ = item.get("Type").ok_or(ErrorKind::MissingAttribute)?;
+            let version = item.get("Version").ok_or(ErrorKind::MissingAttribute)?;
+            let value = item.get("Value").ok_or(ErrorKind::MissingAttribute)?;
+            let items = if let Some(items) = item.get("Items") {
+                items.ss.clone().ok_or(ErrorKind::MissingAttribute)?
+            } else {
+                vec![]
+            };
+
+            Ok(Some(Row {
+                typ: typ.s.as_ref().ok_or(ErrorKind::MissingAttribute)?.to_owned(),
+                version: version.n.as_ref().ok_or(ErrorKind::MissingAttribute)?.parse()?,
+                value: value.n.as_ref().ok_or(ErrorKind::MissingAttribute)?.parse()?,
+                items: items,
+            }))
+        } else {
+            debug!("empty table={} id={}", self.table_name, id);
+
+            Ok(None)
+        }
+    }
+
+    fn put(&self, id: &str, row: &Row) -> Result<bool> {
+        let mut item = HashMap::new();
+        item.insert(s("ID"), AttributeValue { s: Some(s(id)), ..Default::default() });
+        item.insert(s("Version"), AttributeValue { n: Some(format!("{}", row.version + 1)), ..Default::default() });
+        item.insert(s("Type"), AttributeValue { s: Some(row.typ.clone()), ..Default::default() });
+        item.insert(s("Value"), AttributeValue { n: Some(format!("{}", row.value)), ..Default::default() });
+
+        if !row.items.is_empty() {
+            item.insert(s("Items"), AttributeValue { ss: Some(row.items.clone()), ..Default::default() });
+        }
+
+        let (condition_expression, expression_values) = if row.version == 0 {
+            (s("attribute_not_exists(Version)"), None)
+        } else {
+            let mut expression_values = HashMap::new();
+            expression_values.insert(s(":version"), AttributeValue { n: Some(format!("{}", row.version)), ..Default::default() });
+
+            (s("Version = :version"), Some(expression_values))
+        };
+
+        let put_item_input = PutItemInput {
+            item: item,
+            condition_expression: Some(condition_expression),
+            expression_attribute_values: expression_values,
+            table_name: self.table_name.clone(),
+            ..Default::default()
+        };
+
+        match self.client.put_item(&put_item_input) {
+            Err(PutItemError::Unknown(json)) => {
+                let maybe_value = serde_json::from_str::<AWSError>(&json);
+
+                if let Ok(value) = maybe_value {
+                    if value.message.starts_with("The conditional request failed") {
+                        return Ok(false);
+                    }
+                }
+
+                bail!(ErrorKind::PutItem(PutItemError::Unknown(json)));
+            },
+            Err(e) => {
+                bail!(ErrorKind::PutItem(e));
+            },
+            Ok(_) => {
+                Ok(true)
+            }
+        }
+    }
+
+    fn delete(&self, id: &str) -> Result<()> {
+        let mut key = HashMap::new();
+        key.insert(s("ID"), AttributeValue { s: Some(s(id)), ..Default::default() });
+
+        let delete_item_input = DeleteItemInput {
+            key: key,
+            table_name: self.table_name.clone(),
+            ..Default::default()
+        };
+
+        self.client.delete_item(&delete_item_input)?;
+
+        Ok(())
+    }
+}
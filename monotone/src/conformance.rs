@@ -0,0 +1,215 @@
+//! Behaviour every `MonotonicCounter` and `MonotonicQueue` implementation must share.
+//!
+//! Each check takes a factory returning a new, empty counter or queue, and panics if the
+//! implementation behaves differently. Call `check_counter` and `check_queue` from a test
+//! to run them all against a backend:
+//!
+//! ```ignore
+//! #[test]
+//! pub fn test_conformance() {
+//!     conformance::check_counter(|| Counter::new(MemoryStore::new(), "foo", Duration::from_millis(1)));
+//!     conformance::check_queue(|| Queue::new(MemoryStore::new(), "foo", Duration::from_millis(1)));
+//! }
+//! ```
+
+use std::fmt::Debug;
+use std::result;
+use std::thread;
+use std::time::Duration;
+use std::collections::BTreeMap;
+use ::*;
+use ::error::*;
+use string::*;
+
+fn ok<T, E>(result: result::Result<T, E>, what: &str) -> T where E: Into<Error> {
+    match result {
+        Ok(value) => value,
+        Err(e) => panic!("{} failed: {}", what, e.into()),
+    }
+}
+
+fn err<T, E>(result: result::Result<T, E>, what: &str) -> ErrorKind where T: Debug, E: Into<Error> {
+    match result {
+        Ok(value) => panic!("{} should have failed, got {:?}", what, value),
+        Err(e) => {
+            let Error(kind, _) = e.into();
+
+            kind
+        }
+    }
+}
+
+fn assert_not_found<T, E>(result: result::Result<T, E>, process_id: &str, what: &str) where T: Debug, E: Into<Error> {
+    match err(result, what) {
+        ErrorKind::NotFound(ref p) if p == process_id => {},
+        kind => panic!("{} should have failed with NotFound({}), got {:?}", what, process_id, kind),
+    }
+}
+
+fn tags(role: &str) -> BTreeMap<String, String> {
+    let mut tags = BTreeMap::new();
+    tags.insert(s("role"), s(role));
+    tags
+}
+
+/// Run every counter check.
+pub fn check_counter<C, F>(factory: F) where C: MonotonicCounter, F: Fn() -> C {
+    check_counter_starts_at_zero(&factory);
+    check_counter_next_values(&factory);
+    check_counter_compare_and_set(&factory);
+    check_counter_advance_to(&factory);
+}
+
+/// A new counter reads 0 and its first value is 1.
+pub fn check_counter_starts_at_zero<C, F>(factory: &F) where C: MonotonicCounter, F: Fn() -> C {
+    let c = factory();
+
+    assert_eq!(ok(c.get_value(), "get_value"), 0, "new counter");
+    assert_eq!(ok(c.next_value(), "next_value"), 1, "first value");
+    assert_eq!(ok(c.next_value(), "next_value"), 2, "second value");
+    assert_eq!(ok(c.get_value(), "get_value"), 2, "value after two next_value");
+}
+
+/// Ranges are half open and follow on from each other. Reserving no values returns an empty range.
+pub fn check_counter_next_values<C, F>(factory: &F) where C: MonotonicCounter, F: Fn() -> C {
+    let c = factory();
+
+    assert_eq!(ok(c.next_values(0), "next_values(0)"), 1..1, "empty range on new counter");
+    assert_eq!(ok(c.next_values(3), "next_values(3)"), 1..4, "first range");
+    assert_eq!(ok(c.next_values(0), "next_values(0)"), 4..4, "empty range");
+    assert_eq!(ok(c.next_values(2), "next_values(2)"), 4..6, "second range");
+    assert_eq!(ok(c.get_value(), "get_value"), 5, "value after ranges");
+    assert_eq!(ok(c.next_value(), "next_value"), 6, "value after ranges");
+}
+
+/// Compare and set only writes when the value matches, and never moves the counter backwards.
+pub fn check_counter_compare_and_set<C, F>(factory: &F) where C: MonotonicCounter, F: Fn() -> C {
+    let c = factory();
+
+    assert!(ok(c.compare_and_set(0, 5), "compare_and_set(0, 5)"), "set from initial value");
+    assert!(!ok(c.compare_and_set(0, 6), "compare_and_set(0, 6)"), "set from stale value");
+    assert_eq!(ok(c.get_value(), "get_value"), 5, "value after compare_and_set");
+    assert!(ok(c.compare_and_set(5, 5), "compare_and_set(5, 5)"), "set to same value");
+
+    match err(c.compare_and_set(5, 4), "compare_and_set(5, 4)") {
+        ErrorKind::WouldDecrease(5, 4) => {},
+        kind => panic!("compare_and_set(5, 4) should have failed with WouldDecrease(5, 4), got {:?}", kind),
+    }
+
+    assert_eq!(ok(c.get_value(), "get_value"), 5, "value after failed compare_and_set");
+}
+
+/// Advancing raises the value but never lowers it.
+pub fn check_counter_advance_to<C, F>(factory: &F) where C: MonotonicCounter, F: Fn() -> C {
+    let c = factory();
+
+    assert_eq!(ok(c.advance_to(10), "advance_to(10)"), 10, "advance new counter");
+    assert_eq!(ok(c.advance_to(3), "advance_to(3)"), 10, "advance to lower value");
+    assert_eq!(ok(c.next_value(), "next_value"), 11, "value after advance");
+}
+
+/// Run every queue check.
+pub fn check_queue<Q, F>(factory: F) where Q: MonotonicQueue, F: Fn() -> Q {
+    check_queue_empty(&factory);
+    check_queue_join(&factory);
+    check_queue_join_is_idempotent(&factory);
+    check_queue_leave(&factory);
+    check_queue_heartbeat(&factory);
+    check_queue_lease_expires(&factory);
+}
+
+/// A new queue has fencing token 0 and no tickets. Reading, leaving or renewing a missing
+/// ticket fails with `NotFound`.
+pub fn check_queue_empty<Q, F>(factory: &F) where Q: MonotonicQueue, F: Fn() -> Q {
+    let q = factory();
+
+    assert_eq!(ok(q.get_tickets(), "get_tickets"), (0, vec![]), "new queue");
+    assert_not_found(q.get_ticket("foo"), "foo", "get_ticket on new queue");
+    assert_not_found(q.leave_queue("foo"), "foo", "leave_queue on new queue");
+    assert_not_found(q.heartbeat("foo"), "foo", "heartbeat on new queue");
+    assert_eq!(ok(q.get_tickets(), "get_tickets"), (0, vec![]), "queue after failed calls");
+}
+
+/// Each join takes the next fencing token, the next ticket counter starting from 1,
+/// and the position at the back of the queue.
+pub fn check_queue_join<Q, F>(factory: &F) where Q: MonotonicQueue, F: Fn() -> Q {
+    let q = factory();
+
+    let first = Ticket::new(s("foo"), 1, 0, tags("first"));
+    let second = Ticket::new(s("bar"), 2, 1, BTreeMap::new());
+
+    assert_eq!(ok(q.join_queue(s("foo"), tags("first"), None), "join foo"), (1, first.clone()), "first join");
+    assert_eq!(ok(q.join_queue(s("bar"), None, None), "join bar"), (2, second.clone()), "second join");
+
+    assert_eq!(ok(q.get_ticket("foo"), "get_ticket foo"), (2, first.clone()), "first ticket");
+    assert_eq!(ok(q.get_ticket("bar"), "get_ticket bar"), (2, second.clone()), "second ticket");
+    assert_eq!(ok(q.get_tickets(), "get_tickets"), (2, vec![first, second]), "tickets in queue order");
+}
+
+/// Joining again returns the existing ticket, with its original tags, without a write.
+pub fn check_queue_join_is_idempotent<Q, F>(factory: &F) where Q: MonotonicQueue, F: Fn() -> Q {
+    let q = factory();
+
+    let (ft, ticket) = ok(q.join_queue(s("foo"), tags("first"), None), "join foo");
+    assert_eq!(ok(q.join_queue(s("foo"), tags("second"), None), "join foo again"), (ft, ticket), "second join of same process");
+    assert_eq!(ok(q.get_tickets(), "get_tickets").1.len(), 1, "tickets after joining twice");
+}
+
+/// Leaving takes the next fencing token and moves the tickets behind forward. Leaving again
+/// fails with `NotFound`, and rejoining takes a new counter at the back of the queue.
+pub fn check_queue_leave<Q, F>(factory: &F) where Q: MonotonicQueue, F: Fn() -> Q {
+    let q = factory();
+
+    ok(q.join_queue(s("foo"), None, None), "join foo");
+    ok(q.join_queue(s("bar"), None, None), "join bar");
+    ok(q.join_queue(s("baz"), None, None), "join baz");
+
+    assert_eq!(ok(q.leave_queue("foo"), "leave foo"), 4, "fencing token after leave");
+    assert_eq!(ok(q.get_tickets(), "get_tickets"), (4, vec![
+        Ticket::new(s("bar"), 2, 0, BTreeMap::new()),
+        Ticket::new(s("baz"), 3, 1, BTreeMap::new()),
+    ]), "tickets after head leaves");
+
+    assert_eq!(ok(q.leave_queue("baz"), "leave baz"), 5, "fencing token after leave");
+    assert_eq!(ok(q.get_ticket("bar"), "get_ticket bar"), (5, Ticket::new(s("bar"), 2, 0, BTreeMap::new())), "ticket after tail leaves");
+
+    assert_not_found(q.leave_queue("foo"), "foo", "second leave");
+    assert_not_found(q.get_ticket("foo"), "foo", "get_ticket after leave");
+
+    assert_eq!(ok(q.join_queue(s("foo"), None, None), "rejoin foo"), (6, Ticket::new(s("foo"), 4, 1, BTreeMap::new())), "rejoin");
+}
+
+/// Renewing a lease takes the next fencing token. Renewing a ticket without a lease doesn't write.
+pub fn check_queue_heartbeat<Q, F>(factory: &F) where Q: MonotonicQueue, F: Fn() -> Q {
+    let q = factory();
+
+    let (_, unleased) = ok(q.join_queue(s("foo"), None, None), "join foo");
+    let (_, leased) = ok(q.join_queue(s("bar"), None, Duration::from_secs(60)), "join bar");
+
+    assert_eq!(ok(q.heartbeat("foo"), "heartbeat foo"), (2, unleased), "heartbeat without lease");
+    assert_eq!(ok(q.heartbeat("bar"), "heartbeat bar"), (3, leased), "heartbeat with lease");
+}
+
+/// A ticket whose lease runs out disappears from reads and the tickets behind move forward.
+/// Removing the expired ticket takes the next fencing token before the tickets behind are
+/// reported further forward, so the expired holder's token is never valid at the new head.
+pub fn check_queue_lease_expires<Q, F>(factory: &F) where Q: MonotonicQueue, F: Fn() -> Q {
+    let q = factory();
+
+    ok(q.join_queue(s("foo"), None, Duration::from_millis(50)), "join foo");
+    let (held, _) = ok(q.join_queue(s("bar"), None, None), "join bar");
+
+    thread::sleep(Duration::from_millis(100));
+
+    let behind = Ticket::new(s("bar"), 2, 0, BTreeMap::new());
+
+    let (ft, ticket) = ok(q.get_ticket("bar"), "get_ticket bar");
+    assert!(ft > held, "fencing token {} after lease expires should be above {}", ft, held);
+    assert_eq!(ticket, behind, "ticket behind expired ticket");
+
+    assert_not_found(q.get_ticket("foo"), "foo", "get_ticket after lease expires");
+    assert_not_found(q.heartbeat("foo"), "foo", "heartbeat after lease expires");
+    assert_eq!(ok(q.get_tickets(), "get_tickets"), (ft, vec![behind]), "tickets after lease expires");
+
+    assert_eq!(ok(q.join_queue(s("foo"), None, None), "rejoin foo"), (ft + 1, Ticket::new(s("foo"), 3, 1, BTreeMap::new())), "rejoin after lease expires");
+}
@@ -0,0 +1,118 @@
+use std::result;
+use std::time::Duration;
+use std::collections::BTreeMap;
+
+use ::*;
+
+/// Leader election on top of a queue: the process holding the first ticket is the leader.
+///
+/// Always check the fencing token returned by `leader` before acting as leader,
+/// since leadership may have changed since it was last read.
+pub struct Election<Q> where Q: MonotonicQueue {
+    pub queue: Q,
+    pub process_id: String,
+    pub tags: BTreeMap<String, String>,
+    pub lease: Option<Duration>,
+}
+
+impl <Q> Election<Q> where Q: MonotonicQueue {
+    pub fn new<S, T, L>(queue: Q, process_id: S, tags: T, lease: L) -> Election<Q>
+            where S: Into<String>, T: Into<Option<BTreeMap<String, String>>>, L: Into<Option<Duration>> {
+        Election {
+            queue: queue,
+            process_id: process_id.into(),
+            tags: tags.into().unwrap_or(BTreeMap::new()),
+            lease: lease.into(),
+        }
+    }
+
+    /// Join the queue as a candidate. Campaigning again while still queued is a no-op.
+    pub fn campaign(&self) -> result::Result<(FencingToken, Ticket), Q::Error> {
+        self.queue.join_queue(self.process_id.clone(), self.tags.clone(), self.lease)
+    }
+
+    /// Renew the candidate's lease.
+    pub fn heartbeat(&self) -> result::Result<(FencingToken, Ticket), Q::Error> {
+        self.queue.heartbeat(&self.process_id)
+    }
+
+    pub fn is_leader(&self) -> result::Result<bool, Q::Error> {
+        let leader = self.leader()?;
+
+        Ok(leader.map(|(_ft, ticket)| ticket.process_id == self.process_id).unwrap_or(false))
+    }
+
+    /// The ticket at the head of the queue, if any, and the fencing token it was read at.
+    pub fn leader(&self) -> result::Result<Option<(FencingToken, Ticket)>, Q::Error> {
+        let (ft, tickets) = self.queue.get_tickets()?;
+
+        Ok(tickets.into_iter().next().map(|ticket| (ft, ticket)))
+    }
+
+    pub fn resign(&self) -> result::Result<FencingToken, Q::Error> {
+        self.queue.leave_queue(&self.process_id)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use local::queue::Queue;
+    use super::*;
+
+    #[test]
+    pub fn test_election_no_candidates() {
+        let e = Election::new(Queue::new(), "foo", None, None);
+
+        assert_eq!(e.leader().expect("leader"), None);
+        assert!(!e.is_leader().expect("is leader"));
+    }
+
+    #[test]
+    pub fn test_election_campaign() {
+        let e = Election::new(Queue::new(), "foo", None, None);
+
+        let (ft, ticket) = e.campaign().expect("campaign");
+        assert_eq!(ft, 1);
+        assert_eq!(ticket.position, 0);
+
+        assert!(e.is_leader().expect("is leader"));
+        assert_eq!(e.leader().expect("leader"), Some((1, ticket)));
+    }
+
+    #[test]
+    pub fn test_election_follower() {
+        let q = Queue::new();
+        let foo = Election::new(q.clone(), "foo", None, None);
+        let bar = Election::new(q.clone(), "bar", None, None);
+
+        let (_ft, foo_ticket) = foo.campaign().expect("campaign");
+        let (ft, bar_ticket) = bar.campaign().expect("campaign");
+        assert_eq!(ft, 2);
+        assert_eq!(bar_ticket.position, 1);
+
+        assert!(foo.is_leader().expect("is leader"));
+        assert!(!bar.is_leader().expect("is leader"));
+        assert_eq!(bar.leader().expect("leader"), Some((2, foo_ticket)));
+    }
+
+    #[test]
+    pub fn test_election_resign() {
+        let q = Queue::new();
+        let foo = Election::new(q.clone(), "foo", None, None);
+        let bar = Election::new(q.clone(), "bar", None, None);
+
+        foo.campaign().expect("campaign");
+        bar.campaign().expect("campaign");
+
+        let ft = foo.resign().expect("resign");
+        assert_eq!(ft, 3);
+
+        assert!(!foo.is_leader().expect("is leader"));
+        assert!(bar.is_leader().expect("is leader"));
+
+        let (ft, leader) = foo.leader().expect("leader").expect("some leader");
+        assert_eq!(ft, 3);
+        assert_eq!(&leader.process_id, "bar");
+        assert_eq!(leader.position, 0);
+    }
+}
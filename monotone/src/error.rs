@@ -1,8 +1,69 @@
+//! Errors returned by every `MonotonicCounter` and `MonotonicQueue` implementation.
+//!
+//! Backends have their own error types for failures talking to their storage. These are
+//! converted into one of the kinds below at the trait boundary, with the backend error
+//! kept as the cause, so generic code can match on `ErrorKind` whichever backend it runs over.
+
 error_chain! {
     errors {
         NotFound(process_id: String) {
             description("ticket not found")
             display("ticket not found for process_id {}", process_id)
         }
+
+        WouldDecrease(from: u64, to: u64) {
+            description("counter would decrease")
+            display("counter would decrease from {} to {}", from, to)
+        }
+
+        Overflow(value: u64, count: u64) {
+            description("counter would overflow")
+            display("counter would overflow reserving {} values after {}", count, value)
+        }
+
+        Conflict {
+            description("conflicting writes")
+            display("gave up after conflicting writes")
+        }
+
+        RetriesExhausted(conflicts: u32) {
+            description("gave up retrying conflicting writes")
+            display("gave up after {} conflicting writes", conflicts)
+        }
+
+        WrongType {
+            description("id is in use by a different type")
+            display("id is in use by a different type")
+        }
+
+        Corrupt {
+            description("stored data is corrupt")
+            display("stored data is corrupt")
+        }
+
+        Backend(message: String) {
+            description("backend error")
+            display("backend error: {}", message)
+        }
+
+        Timeout {
+            description("timed out")
+            display("timed out")
+        }
+
+        Exhausted(min: u64, max: u64) {
+            description("all ids allocated")
+            display("all ids in range {}..{} are allocated", min, max)
+        }
+
+        NotAllocated(process_id: String) {
+            description("no id allocated")
+            display("no id allocated for process_id {}", process_id)
+        }
+
+        NoPermits {
+            description("semaphore has no permits")
+            display("a semaphore needs at least one permit")
+        }
     }
-}
\ No newline at end of file
+}
@@ -0,0 +1,307 @@
+//! Wrapper that makes another counter or queue misbehave on purpose, for chaos testing.
+//!
+//! `FaultInjector` passes each call through to the counter or queue it wraps, except when its
+//! `FaultSchedule` says to inject a fault instead. The schedule draws from a random number
+//! generator seeded from `FaultSchedule::seed`, so the same seed and the same sequence of calls
+//! inject the same faults every run:
+//!
+//! ```ignore
+//! let q = FaultInjector::new(Queue::new(), FaultSchedule {
+//!     conflict: 0.1,
+//!     lost_response: 0.05,
+//!     ..FaultSchedule::new(42)
+//! });
+//! ```
+//!
+//! Faults can also be queued with `FaultInjector::inject` to make a particular call fail.
+
+use std::result;
+use std::thread;
+use std::ops::Range;
+use std::time::Duration;
+use std::sync::Mutex;
+use std::collections::{BTreeMap, VecDeque};
+use rand::{Rng, SeedableRng, XorShiftRng};
+use ::*;
+use ::error::*;
+use time::*;
+
+/// Something that can go wrong with a call.
+#[derive(Debug, Clone, PartialEq)]
+pub enum Fault {
+    /// Fail with `ErrorKind::Conflict`, as though another writer got in first, without making the call.
+    Conflict,
+    /// Wait this long before making the call.
+    Latency(Duration),
+    /// Fail with `ErrorKind::Backend`, as though the storage was briefly unavailable, without making the call.
+    Transient,
+    /// Make the call, then fail with `ErrorKind::Timeout` whatever the result, as though the
+    /// write succeeded but the response was lost on its way back.
+    LostResponse,
+}
+
+/// How often to inject each kind of fault.
+///
+/// Each call injects at most one fault. The rates are probabilities between 0 and 1 and should
+/// add up to no more than 1. Latency faults wait anywhere up to `max_latency`.
+#[derive(Debug, Clone, PartialEq)]
+pub struct FaultSchedule {
+    pub seed: u32,
+    pub conflict: f64,
+    pub transient: f64,
+    pub lost_response: f64,
+    pub latency: f64,
+    pub max_latency: Duration,
+}
+
+impl FaultSchedule {
+    /// A schedule that injects no faults until some rates are set.
+    pub fn new(seed: u32) -> FaultSchedule {
+        FaultSchedule {
+            seed: seed,
+            conflict: 0.0,
+            transient: 0.0,
+            lost_response: 0.0,
+            latency: 0.0,
+            max_latency: Duration::from_millis(100),
+        }
+    }
+
+    fn rng(&self) -> XorShiftRng {
+        XorShiftRng::from_seed([0x193a_6754, 0xa8a7_d469, 0x9783_0e05, self.seed ^ 0x113b_a7bb])
+    }
+
+    fn next_fault<R>(&self, rng: &mut R) -> Option<Fault> where R: Rng {
+        let roll = rng.next_f64();
+        let millis = rng.gen_range(0, duration_millis(self.max_latency) + 1);
+
+        let mut threshold = self.conflict;
+        if roll < threshold {
+            return Some(Fault::Conflict);
+        }
+
+        threshold += self.transient;
+        if roll < threshold {
+            return Some(Fault::Transient);
+        }
+
+        threshold += self.lost_response;
+        if roll < threshold {
+            return Some(Fault::LostResponse);
+        }
+
+        threshold += self.latency;
+        if roll < threshold {
+            return Some(Fault::Latency(Duration::from_millis(millis)));
+        }
+
+        None
+    }
+}
+
+/// `MonotonicCounter` or `MonotonicQueue` that injects faults into calls to the one it wraps.
+pub struct FaultInjector<T> {
+    pub inner: T,
+    pub schedule: FaultSchedule,
+    rng: Mutex<XorShiftRng>,
+    queued: Mutex<VecDeque<Fault>>,
+    injected: Mutex<Vec<(&'static str, Fault)>>,
+}
+
+impl <T> FaultInjector<T> {
+    pub fn new(inner: T, schedule: FaultSchedule) -> FaultInjector<T> {
+        FaultInjector {
+            inner: inner,
+            rng: Mutex::new(schedule.rng()),
+            schedule: schedule,
+            queued: Mutex::new(VecDeque::new()),
+            injected: Mutex::new(vec![]),
+        }
+    }
+
+    /// Inject `fault` into the next call, ahead of the schedule. Queued faults are injected in order.
+    pub fn inject(&self, fault: Fault) {
+        self.queued.lock().unwrap().push_back(fault);
+    }
+
+    /// The faults injected so far, with the name of the method each was injected into.
+    pub fn injected(&self) -> Vec<(&'static str, Fault)> {
+        self.injected.lock().unwrap().clone()
+    }
+
+    fn next_fault(&self) -> Option<Fault> {
+        // Draw from the schedule even when a fault is queued, so queuing faults
+        // doesn't change which faults the schedule injects into later calls.
+        let scheduled = self.schedule.next_fault(&mut *self.rng.lock().unwrap());
+
+        self.queued.lock().unwrap().pop_front().or(scheduled)
+    }
+
+    fn call<R, E, F>(&self, method: &'static str, f: F) -> Result<R> where E: Into<Error>, F: FnOnce(&T) -> result::Result<R, E> {
+        let fault = self.next_fault();
+
+        if let Some(ref fault) = fault {
+            debug!("injecting {:?} into {}", fault, method);
+            self.injected.lock().unwrap().push((method, fault.clone()));
+        }
+
+        match fault {
+            Some(Fault::Conflict) => bail!(ErrorKind::Conflict),
+            Some(Fault::Transient) => bail!(ErrorKind::Backend(format!("injected transient error in {}", method))),
+            Some(Fault::LostResponse) => {
+                let _ = f(&self.inner);
+                bail!(ErrorKind::Timeout)
+            },
+            Some(Fault::Latency(delay)) => {
+                thread::sleep(delay);
+                f(&self.inner).map_err(Into::into)
+            },
+            None => f(&self.inner).map_err(Into::into),
+        }
+    }
+}
+
+impl <C> MonotonicCounter for FaultInjector<C> where C: MonotonicCounter {
+    type Error = Error;
+
+    fn get_value(&self) -> Result<u64> {
+        self.call("get_value", |c| c.get_value())
+    }
+
+    fn next_value(&self) -> Result<u64> {
+        self.call("next_value", |c| c.next_value())
+    }
+
+    fn next_values(&self, count: u64) -> Result<Range<u64>> {
+        self.call("next_values", |c| c.next_values(count))
+    }
+
+    fn compare_and_set(&self, expected: u64, new: u64) -> Result<bool> {
+        self.call("compare_and_set", |c| c.compare_and_set(expected, new))
+    }
+
+    fn advance_to(&self, min_value: u64) -> Result<u64> {
+        self.call("advance_to", |c| c.advance_to(min_value))
+    }
+}
+
+impl <Q> MonotonicQueue for FaultInjector<Q> where Q: MonotonicQueue {
+    type Error = Error;
+
+    fn join_queue<T, L>(&self, process_id: String, tags: T, lease: L) -> Result<(FencingToken, Ticket)>
+            where T: Into<Option<BTreeMap<String, String>>>, L: Into<Option<Duration>> {
+        self.call("join_queue", |q| q.join_queue(process_id, tags, lease))
+    }
+
+    fn leave_queue(&self, process_id: &str) -> Result<FencingToken> {
+        self.call("leave_queue", |q| q.leave_queue(process_id))
+    }
+
+    fn heartbeat(&self, process_id: &str) -> Result<(FencingToken, Ticket)> {
+        self.call("heartbeat", |q| q.heartbeat(process_id))
+    }
+
+    fn get_ticket(&self, process_id: &str) -> Result<(FencingToken, Ticket)> {
+        self.call("get_ticket", |q| q.get_ticket(process_id))
+    }
+
+    fn get_tickets(&self) -> Result<(FencingToken, Vec<Ticket>)> {
+        self.call("get_tickets", |q| q.get_tickets())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::time::Duration;
+    use local::counter::Counter;
+    use local::queue::Queue;
+    use conformance;
+    use string::*;
+    use ::*;
+    use super::*;
+
+    fn schedule(seed: u32) -> FaultSchedule {
+        FaultSchedule {
+            conflict: 0.2,
+            transient: 0.2,
+            lost_response: 0.2,
+            latency: 0.2,
+            max_latency: Duration::from_millis(1),
+            ..FaultSchedule::new(seed)
+        }
+    }
+
+    #[test]
+    pub fn test_same_seed_same_faults() {
+        let run = |seed| {
+            let c = FaultInjector::new(Counter::new(), schedule(seed));
+
+            let results: Vec<_> = (0..50).map(|_| c.next_value().ok()).collect();
+
+            (results, c.injected())
+        };
+
+        let (results, injected) = run(7);
+
+        assert_eq!(run(7), (results.clone(), injected.clone()));
+        assert_ne!(run(8).1, injected);
+        assert!(injected.iter().any(|(_, f)| *f == Fault::Conflict));
+        assert!(injected.iter().any(|(_, f)| *f == Fault::LostResponse));
+        assert!(results.iter().any(|r| r.is_some()));
+    }
+
+    #[test]
+    pub fn test_injected_faults() {
+        let c = FaultInjector::new(Counter::new(), FaultSchedule::new(0));
+
+        c.inject(Fault::Conflict);
+        c.inject(Fault::Transient);
+        c.inject(Fault::LostResponse);
+        c.inject(Fault::Latency(Duration::from_millis(1)));
+
+        match c.next_value() {
+            Err(Error(ErrorKind::Conflict, _)) => {},
+            r => panic!("expected Conflict, got {:?}", r),
+        }
+
+        match c.next_value() {
+            Err(Error(ErrorKind::Backend(_), _)) => {},
+            r => panic!("expected Backend, got {:?}", r),
+        }
+
+        match c.next_value() {
+            Err(Error(ErrorKind::Timeout, _)) => {},
+            r => panic!("expected Timeout, got {:?}", r),
+        }
+
+        // The lost response's write went through, and the conflict's and transient error's didn't.
+        assert_eq!(c.next_value().expect("next"), 2);
+        assert_eq!(c.get_value().expect("get"), 2);
+
+        assert_eq!(c.injected(), vec![
+            ("next_value", Fault::Conflict),
+            ("next_value", Fault::Transient),
+            ("next_value", Fault::LostResponse),
+            ("next_value", Fault::Latency(Duration::from_millis(1))),
+        ]);
+    }
+
+    #[test]
+    pub fn test_lost_join_response() {
+        let q = FaultInjector::new(Queue::new(), FaultSchedule::new(0));
+
+        q.inject(Fault::LostResponse);
+        assert!(q.join_queue(s("foo"), None, None).is_err());
+
+        // Joining again finds the ticket the lost response was for.
+        let (ft, ticket) = q.join_queue(s("foo"), None, None).expect("join");
+        assert_eq!(ft, 1);
+        assert_eq!(ticket.counter, 1);
+    }
+
+    #[test]
+    pub fn test_conformance_without_faults() {
+        conformance::check_counter(|| FaultInjector::new(Counter::new(), FaultSchedule::new(0)));
+        conformance::check_queue(|| FaultInjector::new(Queue::new(), FaultSchedule::new(0)));
+    }
+}
@@ -0,0 +1,7 @@
+use store;
+use super::store::FileStore;
+
+pub use store::counter::{CounterRow, COUNTER_TYPE};
+
+/// Counter kept in a state file.
+pub type Counter = store::counter::Counter<FileStore>;
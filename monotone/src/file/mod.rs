@@ -0,0 +1,6 @@
+//! Counters and queues kept in a state file, for coordinating processes on a single host.
+
+pub mod counter;
+pub mod error;
+pub mod queue;
+pub mod store;
@@ -0,0 +1,7 @@
+use store;
+use super::store::FileStore;
+
+pub use store::queue::{QueuePosition, QueueRow, QUEUE_TYPE};
+
+/// Queue kept in a state file.
+pub type Queue = store::queue::Queue<FileStore>;
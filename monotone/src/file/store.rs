@@ -0,0 +1,232 @@
+use std::io::{Read, Write};
+use std::fs::{File, OpenOptions};
+use std::path::{Path, PathBuf};
+use std::collections::BTreeMap;
+use fs2::FileExt;
+use serde_json;
+use store::*;
+use super::error::*;
+
+/// `VersionedStore` keeping every row in one JSON state file.
+///
+/// Readers and writers take an advisory lock on a `.lock` file next to the state file.
+/// Writes go to a `.tmp` file which is synced and renamed over the state file,
+/// so the state file is always either the old or the new version, even after a crash.
+#[derive(Debug, Clone)]
+pub struct FileStore {
+    pub path: PathBuf,
+}
+
+impl FileStore {
+    pub fn new<P>(path: P) -> FileStore where P: Into<PathBuf> {
+        FileStore {
+            path: path.into(),
+        }
+    }
+
+    fn sibling(&self, extension: &str) -> PathBuf {
+        let mut name = self.path.file_name().map(|n| n.to_owned()).unwrap_or_default();
+        name.push(extension);
+
+        self.path.with_file_name(name)
+    }
+
+    fn lock_file(&self) -> Result<File> {
+        let file = OpenOptions::new().read(true).write(true).create(true).truncate(false).open(self.sibling(".lock"))?;
+
+        Ok(file)
+    }
+
+    fn read_rows(&self) -> Result<BTreeMap<String, Row>> {
+        if !self.path.exists() {
+            return Ok(BTreeMap::new());
+        }
+
+        let mut contents = String::new();
+        File::open(&self.path)?.read_to_string(&mut contents)?;
+
+        if contents.is_empty() {
+            return Ok(BTreeMap::new());
+        }
+
+        Ok(serde_json::from_str(&contents)?)
+    }
+
+    fn write_rows(&self, rows: &BTreeMap<String, Row>) -> Result<()> {
+        let tmp_path = self.sibling(".tmp");
+
+        {
+            let mut tmp = File::create(&tmp_path)?;
+            tmp.write_all(serde_json::to_string(rows)?.as_bytes())?;
+            tmp.sync_all()?;
+        }
+
+        ::std::fs::rename(&tmp_path, &self.path)?;
+
+        sync_dir(self.path.parent())
+    }
+
+    /// Run `f` on the rows while holding the exclusive lock, writing them back if it returns true.
+    fn update<T, F>(&self, f: F) -> Result<T> where F: FnOnce(&mut BTreeMap<String, Row>) -> (bool, T) {
+        let lock = self.lock_file()?;
+        lock.lock_exclusive()?;
+
+        let mut rows = self.read_rows()?;
+        let (changed, result) = f(&mut rows);
+
+        if changed {
+            self.write_rows(&rows)?;
+        }
+
+        lock.unlock()?;
+
+        Ok(result)
+    }
+}
+
+#[cfg(unix)]
+fn sync_dir(dir: Option<&Path>) -> Result<()> {
+    let dir = match dir {
+        Some(dir) if dir != Path::new("") => dir,
+        _ => Path::new("."),
+    };
+
+    File::open(dir)?.sync_all()?;
+
+    Ok(())
+}
+
+#[cfg(not(unix))]
+fn sync_dir(_dir: Option<&Path>) -> Result<()> {
+    Ok(())
+}
+
+impl VersionedStore for FileStore {
+    type Error = Error;
+
+    fn get(&self, id: &str) -> Result<Option<Row>> {
+        let lock = self.lock_file()?;
+        lock.lock_shared()?;
+
+        let rows = self.read_rows()?;
+
+        lock.unlock()?;
+
+        Ok(rows.get(id).cloned())
+    }
+
+    fn put(&self, id: &str, row: &Row) -> Result<bool> {
+        self.update(|rows| {
+            let version = rows.get(id).map(|r| r.version).unwrap_or(0);
+
+            if version != row.version {
+                return (false, false);
+            }
+
+            let mut row = row.clone();
+            row.version += 1;
+
+            rows.insert(id.to_owned(), row);
+
+            (true, true)
+        })
+    }
+
+    fn delete(&self, id: &str) -> Result<()> {
+        self.update(|rows| (rows.remove(id).is_some(), ()))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::env;
+    use std::fs;
+    use std::thread;
+    use std::time::Duration;
+    use rand;
+    use ::*;
+    use string::*;
+    use store::*;
+    use file::counter::Counter;
+    use file::queue::Queue;
+    use super::*;
+
+    fn temp_path() -> PathBuf {
+        env::temp_dir().join(format!("monotone-test-{}.json", rand::random::<u64>()))
+    }
+
+    fn cleanup(store: &FileStore) {
+        for path in &[store.path.clone(), store.sibling(".lock")] {
+            fs::remove_file(path).ok();
+        }
+    }
+
+    #[test]
+    pub fn test_file_store_put_if_version() {
+        let store = FileStore::new(temp_path());
+        assert_eq!(store.get("foo").expect("get"), None);
+
+        assert!(store.put("foo", &Row::new("COUNTER", 0, 1, vec![])).expect("put"));
+        assert!(!store.put("foo", &Row::new("COUNTER", 0, 2, vec![])).expect("put"));
+        assert_eq!(FileStore::new(store.path.clone()).get("foo").expect("get"), Some(Row::new("COUNTER", 1, 1, vec![])));
+
+        store.delete("foo").expect("delete");
+        assert_eq!(store.get("foo").expect("get"), None);
+
+        cleanup(&store);
+    }
+
+    #[test]
+    pub fn test_file_counter_shared_between_handles() {
+        let store = FileStore::new(temp_path());
+
+        let handles: Vec<_> = (0..4).map(|_| {
+            let counter = Counter::new(FileStore::new(store.path.clone()), "foo", Duration::from_millis(1));
+
+            thread::spawn(move || {
+                (0..10).map(|_| counter.next_value().expect("next")).collect::<Vec<_>>()
+            })
+        }).collect();
+
+        let mut values: Vec<u64> = handles.into_iter().flat_map(|h| h.join().expect("join")).collect();
+        values.sort();
+
+        assert_eq!(values, (1..41).collect::<Vec<_>>());
+
+        cleanup(&store);
+    }
+
+    #[test]
+    pub fn test_file_queue() {
+        let store = FileStore::new(temp_path());
+        let q = Queue::new(store.clone(), "foo", Duration::from_millis(1));
+        let c = Counter::new(store.clone(), "bar", Duration::from_millis(1));
+
+        let (ft, tok) = q.join_queue(s("foo"), None, None).expect("join");
+        assert_eq!(ft, 1);
+        assert_eq!(tok.position, 0);
+        assert_eq!(c.next_value().expect("next"), 1);
+
+        let q2 = Queue::new(FileStore::new(store.path.clone()), "foo", Duration::from_millis(1));
+        let (_ft, tok) = q2.join_queue(s("bar"), None, None).expect("join");
+        assert_eq!(tok.position, 1);
+
+        q.leave_queue("foo").expect("leave");
+        let (ft, tok) = q2.get_ticket("bar").expect("get");
+        assert_eq!(ft, 3);
+        assert_eq!(tok.position, 0);
+
+        cleanup(&store);
+    }
+
+    #[test]
+    pub fn test_file_conformance() {
+        let store = FileStore::new(temp_path());
+        let id = || format!("foo-{}", rand::random::<u64>());
+
+        conformance::check_counter(|| Counter::new(store.clone(), id(), Duration::from_millis(1)));
+        conformance::check_queue(|| Queue::new(store.clone(), id(), Duration::from_millis(1)));
+
+        cleanup(&store);
+    }
+}
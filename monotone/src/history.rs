@@ -0,0 +1,517 @@
+//! Records the calls made to a counter and a queue from many threads, and checks that every
+//! result could have come from making the calls one at a time.
+//!
+//! Wrap the counter and queue under test in `Recorder`s sharing a `History`, run the clients,
+//! then call `History::check`:
+//!
+//! ```ignore
+//! let history = History::new();
+//! let counter = Recorder::new(counter, history.clone());
+//! let queue = Recorder::new(queue, history.clone());
+//!
+//! // ... call counter and queue from several threads ...
+//!
+//! if let Err(violation) = history.check() {
+//!     panic!("{}", violation);
+//! }
+//! ```
+//!
+//! The check searches for an order of the calls, consistent with when each started and
+//! finished, in which replaying them against a `u64` counter and a `local::queue::QueueInner`
+//! gives the results that were recorded. A duplicate counter value or a reordered queue has
+//! no such order. Calls that failed with anything other than `NotFound`, `WouldDecrease` or
+//! `Overflow` may or may not have taken effect, so the check tries both.
+//!
+//! Leases aren't modelled, so record runs without them. Searching is exponential in the
+//! number of overlapping calls, so keep histories to a few hundred calls.
+
+use std::cmp;
+use std::fmt;
+use std::result;
+use std::thread;
+use std::ops::Range;
+use std::time::Duration;
+use std::sync::{Arc, Mutex};
+use std::collections::{BTreeMap, HashSet};
+use ::*;
+use ::error::*;
+use local::queue::QueueInner;
+
+/// A call to a counter or queue.
+#[derive(Debug, Clone, PartialEq)]
+pub enum Operation {
+    GetValue,
+    NextValue,
+    NextValues(u64),
+    CompareAndSet(u64, u64),
+    AdvanceTo(u64),
+    JoinQueue(String, BTreeMap<String, String>),
+    LeaveQueue(String),
+    Heartbeat(String),
+    GetTicket(String),
+    GetTickets,
+}
+
+/// What a call returned.
+#[derive(Debug, Clone, PartialEq)]
+pub enum Outcome {
+    Value(u64),
+    Values(Range<u64>),
+    Set(bool),
+    Ticket(FencingToken, Ticket),
+    Left(FencingToken),
+    Tickets(FencingToken, Vec<Ticket>),
+    NotFound,
+    WouldDecrease,
+    Overflow,
+}
+
+/// A call in a history.
+///
+/// `invoked` and `completed` are positions in the order calls started and finished across all
+/// threads. Calls that failed ambiguously, or never returned, have no `completed` or `outcome`.
+#[derive(Debug, Clone, PartialEq)]
+pub struct Entry {
+    pub thread: String,
+    pub operation: Operation,
+    pub invoked: u64,
+    pub completed: Option<u64>,
+    pub outcome: Option<Outcome>,
+}
+
+impl Entry {
+    pub fn new<S>(thread: S, operation: Operation, invoked: u64, completed: Option<u64>, outcome: Option<Outcome>) -> Entry where S: Into<String> {
+        Entry {
+            thread: thread.into(),
+            operation: operation,
+            invoked: invoked,
+            completed: completed,
+            outcome: outcome,
+        }
+    }
+
+    /// Whether leaving this call out of a history can't change what later calls return.
+    fn is_read_only(&self) -> bool {
+        match (&self.operation, &self.outcome) {
+            (_, &Some(Outcome::NotFound)) |
+            (_, &Some(Outcome::WouldDecrease)) |
+            (_, &Some(Outcome::Overflow)) |
+            (_, &Some(Outcome::Set(false))) |
+            (&Operation::GetValue, _) |
+            (&Operation::GetTicket(_), _) |
+            (&Operation::GetTickets, _) => self.outcome.is_some(),
+            _ => false,
+        }
+    }
+}
+
+impl fmt::Display for Entry {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match (self.completed, &self.outcome) {
+            (Some(completed), Some(outcome)) => write!(f, "[{}, {}] {}: {:?} -> {:?}", self.invoked, completed, self.thread, self.operation, outcome),
+            _ => write!(f, "[{}, ?] {}: {:?} -> ?", self.invoked, self.thread, self.operation),
+        }
+    }
+}
+
+/// A history with no order of calls that explains every result.
+#[derive(Debug, Clone, PartialEq)]
+pub struct Violation {
+    /// The shortest prefix of the history that can't be explained, less any reads
+    /// that aren't needed to show it.
+    pub history: Vec<Entry>,
+}
+
+impl fmt::Display for Violation {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "history is not linearizable:")?;
+
+        for entry in &self.history {
+            write!(f, "\n  {}", entry)?;
+        }
+
+        Ok(())
+    }
+}
+
+/// Sequential counter and queue the history is replayed against.
+#[derive(Debug, Clone, PartialEq, Eq, Hash, Default)]
+struct Model {
+    counter: u64,
+    queue: QueueInner,
+}
+
+impl Model {
+    fn next_values(&mut self, count: u64) -> Option<Range<u64>> {
+        let end = self.counter.checked_add(count).and_then(|v| v.checked_add(1))?;
+        let start = self.counter + 1;
+        self.counter = end - 1;
+
+        Some(start..end)
+    }
+
+    fn apply(&mut self, operation: &Operation) -> Outcome {
+        let outcome = match *operation {
+            Operation::GetValue => Ok(Outcome::Value(self.counter)),
+            Operation::NextValue => Ok(self.next_values(1).map(|r| Outcome::Value(r.start)).unwrap_or(Outcome::Overflow)),
+            Operation::NextValues(count) => Ok(self.next_values(count).map(Outcome::Values).unwrap_or(Outcome::Overflow)),
+            Operation::CompareAndSet(expected, new) => {
+                if new < expected {
+                    Ok(Outcome::WouldDecrease)
+                } else if self.counter == expected {
+                    self.counter = new;
+                    Ok(Outcome::Set(true))
+                } else {
+                    Ok(Outcome::Set(false))
+                }
+            },
+            Operation::AdvanceTo(min_value) => {
+                self.counter = cmp::max(self.counter, min_value);
+                Ok(Outcome::Value(self.counter))
+            },
+            Operation::JoinQueue(ref process_id, ref tags) => {
+                self.queue.join_queue(process_id.clone(), tags.clone(), None).map(|(ft, t)| Outcome::Ticket(ft, t))
+            },
+            Operation::LeaveQueue(ref process_id) => self.queue.leave_queue(process_id).map(Outcome::Left),
+            Operation::Heartbeat(ref process_id) => self.queue.heartbeat(process_id).map(|(ft, t)| Outcome::Ticket(ft, t)),
+            Operation::GetTicket(ref process_id) => self.queue.get_ticket(process_id).map(|(ft, t)| Outcome::Ticket(ft, t)),
+            Operation::GetTickets => self.queue.get_tickets().map(|(ft, t)| Outcome::Tickets(ft, t)),
+        };
+
+        outcome.unwrap_or(Outcome::NotFound)
+    }
+}
+
+fn linearize(entries: &[Entry], done: &mut Vec<bool>, model: &Model, seen: &mut HashSet<(Vec<bool>, Model)>) -> bool {
+    // Calls that never completed can be left out, as though they never took effect.
+    if entries.iter().zip(done.iter()).all(|(e, &d)| d || e.completed.is_none()) {
+        return true;
+    }
+
+    if !seen.insert((done.clone(), model.clone())) {
+        return false;
+    }
+
+    // Only calls that started before the first remaining call finished can go next.
+    let horizon = entries.iter()
+        .zip(done.iter())
+        .filter(|&(_, &d)| !d)
+        .filter_map(|(e, _)| e.completed)
+        .min()
+        .unwrap_or(u64::MAX);
+
+    for (i, entry) in entries.iter().enumerate() {
+        if done[i] || entry.invoked > horizon {
+            continue;
+        }
+
+        let mut next = model.clone();
+        let outcome = next.apply(&entry.operation);
+
+        if entry.outcome.as_ref().map(|o| *o == outcome).unwrap_or(true) {
+            done[i] = true;
+
+            if linearize(entries, done, &next, seen) {
+                return true;
+            }
+
+            done[i] = false;
+        }
+    }
+
+    false
+}
+
+fn is_linearizable(entries: &[Entry]) -> bool {
+    linearize(entries, &mut vec![false; entries.len()], &Model::default(), &mut HashSet::new())
+}
+
+/// The calls started before `time`, with those that hadn't finished by then left ambiguous.
+fn prefix(entries: &[Entry], time: u64) -> Vec<Entry> {
+    entries.iter()
+        .filter(|e| e.invoked < time)
+        .map(|e| {
+            if e.completed.map(|c| c > time).unwrap_or(false) {
+                Entry { completed: None, outcome: None, ..e.clone() }
+            } else {
+                e.clone()
+            }
+        })
+        .collect()
+}
+
+/// Check that `entries` could have come from making the calls one at a time.
+pub fn check(entries: &[Entry]) -> result::Result<(), Violation> {
+    if is_linearizable(entries) {
+        return Ok(());
+    }
+
+    // Any prefix of a history is a history, and once a prefix can't be explained no longer
+    // one can be, so the first completion that can't be explained ends the counterexample.
+    let mut completions: Vec<u64> = entries.iter().filter_map(|e| e.completed).collect();
+    completions.sort();
+
+    let mut history = completions.into_iter()
+        .map(|time| prefix(entries, time))
+        .find(|prefix| !is_linearizable(prefix))
+        .unwrap_or_else(|| entries.to_vec());
+
+    let mut i = 0;
+    while i < history.len() {
+        if history[i].is_read_only() {
+            let mut shorter = history.clone();
+            shorter.remove(i);
+
+            if !is_linearizable(&shorter) {
+                history = shorter;
+                continue;
+            }
+        }
+
+        i += 1;
+    }
+
+    Err(Violation { history: history })
+}
+
+#[derive(Debug, Default)]
+struct HistoryInner {
+    clock: u64,
+    entries: Vec<Entry>,
+}
+
+/// Calls recorded from any number of threads. Clones share the same history.
+#[derive(Debug, Clone, Default)]
+pub struct History {
+    inner: Arc<Mutex<HistoryInner>>,
+}
+
+impl History {
+    pub fn new() -> History {
+        History::default()
+    }
+
+    /// The calls recorded so far, in the order they started.
+    pub fn entries(&self) -> Vec<Entry> {
+        self.inner.lock().unwrap().entries.clone()
+    }
+
+    /// Check the calls recorded so far. Calls still in progress are treated as ambiguous.
+    pub fn check(&self) -> result::Result<(), Violation> {
+        check(&self.entries())
+    }
+
+    fn invoke(&self, operation: Operation) -> usize {
+        let mut inner = self.inner.lock().unwrap();
+        let thread = thread::current();
+        let thread = thread.name().map(|n| n.to_owned()).unwrap_or_else(|| format!("{:?}", thread.id()));
+
+        inner.clock += 1;
+        let invoked = inner.clock;
+        inner.entries.push(Entry::new(thread, operation, invoked, None, None));
+
+        inner.entries.len() - 1
+    }
+
+    fn complete(&self, index: usize, outcome: Option<Outcome>) {
+        let mut inner = self.inner.lock().unwrap();
+
+        inner.clock += 1;
+
+        if outcome.is_some() {
+            inner.entries[index].completed = Some(inner.clock);
+            inner.entries[index].outcome = outcome;
+        }
+    }
+}
+
+/// `MonotonicCounter` or `MonotonicQueue` that records every call to the one it wraps.
+pub struct Recorder<T> {
+    pub inner: T,
+    pub history: History,
+}
+
+impl <T> Recorder<T> {
+    pub fn new(inner: T, history: History) -> Recorder<T> {
+        Recorder {
+            inner: inner,
+            history: history,
+        }
+    }
+
+    fn record<R, E, F, O>(&self, operation: Operation, f: F, outcome: O) -> Result<R>
+            where E: Into<Error>, F: FnOnce(&T) -> result::Result<R, E>, O: FnOnce(&R) -> Outcome {
+        let index = self.history.invoke(operation);
+
+        let result = f(&self.inner).map_err(Into::into);
+
+        let recorded = match result {
+            Ok(ref r) => Some(outcome(r)),
+            Err(Error(ErrorKind::NotFound(_), _)) => Some(Outcome::NotFound),
+            Err(Error(ErrorKind::WouldDecrease(_, _), _)) => Some(Outcome::WouldDecrease),
+            Err(Error(ErrorKind::Overflow(_, _), _)) => Some(Outcome::Overflow),
+            Err(_) => None,
+        };
+
+        self.history.complete(index, recorded);
+
+        result
+    }
+}
+
+impl <C> MonotonicCounter for Recorder<C> where C: MonotonicCounter {
+    type Error = Error;
+
+    fn get_value(&self) -> Result<u64> {
+        self.record(Operation::GetValue, |c| c.get_value(), |&v| Outcome::Value(v))
+    }
+
+    fn next_value(&self) -> Result<u64> {
+        self.record(Operation::NextValue, |c| c.next_value(), |&v| Outcome::Value(v))
+    }
+
+    fn next_values(&self, count: u64) -> Result<Range<u64>> {
+        self.record(Operation::NextValues(count), |c| c.next_values(count), |r| Outcome::Values(r.clone()))
+    }
+
+    fn compare_and_set(&self, expected: u64, new: u64) -> Result<bool> {
+        self.record(Operation::CompareAndSet(expected, new), |c| c.compare_and_set(expected, new), |&set| Outcome::Set(set))
+    }
+
+    fn advance_to(&self, min_value: u64) -> Result<u64> {
+        self.record(Operation::AdvanceTo(min_value), |c| c.advance_to(min_value), |&v| Outcome::Value(v))
+    }
+}
+
+impl <Q> MonotonicQueue for Recorder<Q> where Q: MonotonicQueue {
+    type Error = Error;
+
+    fn join_queue<T, L>(&self, process_id: String, tags: T, lease: L) -> Result<(FencingToken, Ticket)>
+            where T: Into<Option<BTreeMap<String, String>>>, L: Into<Option<Duration>> {
+        let tags = tags.into().unwrap_or_default();
+
+        self.record(Operation::JoinQueue(process_id.clone(), tags.clone()), |q| q.join_queue(process_id, tags, lease), |&(ft, ref t)| Outcome::Ticket(ft, t.clone()))
+    }
+
+    fn leave_queue(&self, process_id: &str) -> Result<FencingToken> {
+        self.record(Operation::LeaveQueue(process_id.to_owned()), |q| q.leave_queue(process_id), |&ft| Outcome::Left(ft))
+    }
+
+    fn heartbeat(&self, process_id: &str) -> Result<(FencingToken, Ticket)> {
+        self.record(Operation::Heartbeat(process_id.to_owned()), |q| q.heartbeat(process_id), |&(ft, ref t)| Outcome::Ticket(ft, t.clone()))
+    }
+
+    fn get_ticket(&self, process_id: &str) -> Result<(FencingToken, Ticket)> {
+        self.record(Operation::GetTicket(process_id.to_owned()), |q| q.get_ticket(process_id), |&(ft, ref t)| Outcome::Ticket(ft, t.clone()))
+    }
+
+    fn get_tickets(&self) -> Result<(FencingToken, Vec<Ticket>)> {
+        self.record(Operation::GetTickets, |q| q.get_tickets(), |&(ft, ref t)| Outcome::Tickets(ft, t.clone()))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::thread;
+    use std::sync::Arc;
+    use std::collections::BTreeMap;
+    use local::counter::Counter;
+    use local::queue::Queue;
+    use fault::*;
+    use string::*;
+    use ::*;
+    use super::*;
+
+    fn ticket(process_id: &str, counter: u64, position: usize) -> Ticket {
+        Ticket::new(s(process_id), counter, position, BTreeMap::new())
+    }
+
+    fn join(process_id: &str) -> Operation {
+        Operation::JoinQueue(s(process_id), BTreeMap::new())
+    }
+
+    #[test]
+    pub fn test_concurrent_clients() {
+        let history = History::new();
+        let counter = Arc::new(Recorder::new(Counter::new(), history.clone()));
+        let queue = Arc::new(Recorder::new(Queue::new(), history.clone()));
+
+        let threads: Vec<_> = (0..4).map(|n| {
+            let counter = counter.clone();
+            let queue = queue.clone();
+
+            thread::spawn(move || {
+                let process_id = format!("client-{}", n);
+
+                for _ in 0..3 {
+                    counter.next_value().expect("next");
+                    queue.join_queue(process_id.clone(), None, None).expect("join");
+                    queue.get_tickets().expect("get all");
+                    queue.leave_queue(&process_id).expect("leave");
+                }
+            })
+        }).collect();
+
+        for t in threads {
+            t.join().expect("join thread");
+        }
+
+        assert_eq!(history.entries().len(), 48);
+        history.check().expect("linearizable");
+    }
+
+    #[test]
+    pub fn test_ambiguous_failures() {
+        let history = History::new();
+        let counter = Recorder::new(FaultInjector::new(Counter::new(), FaultSchedule::new(0)), history.clone());
+
+        counter.inner.inject(Fault::LostResponse);
+        counter.inner.inject(Fault::Conflict);
+
+        assert!(counter.next_value().is_err());
+        assert!(counter.next_value().is_err());
+        assert_eq!(counter.next_value().expect("next"), 2);
+
+        history.check().expect("linearizable");
+    }
+
+    #[test]
+    pub fn test_duplicate_value() {
+        let history = vec![
+            Entry::new("a", Operation::GetValue, 1, Some(2), Some(Outcome::Value(0))),
+            Entry::new("a", Operation::NextValue, 3, Some(4), Some(Outcome::Value(1))),
+            Entry::new("b", Operation::NextValue, 5, Some(6), Some(Outcome::Value(1))),
+            Entry::new("b", Operation::NextValue, 7, Some(8), Some(Outcome::Value(2))),
+        ];
+
+        let violation = check(&history).expect_err("duplicate value");
+        assert_eq!(violation.history, history[1..3].to_vec());
+    }
+
+    #[test]
+    pub fn test_overlapping_calls() {
+        // Either order of the overlapping joins explains the results.
+        let history = vec![
+            Entry::new("a", join("foo"), 1, Some(4), Some(Outcome::Ticket(2, ticket("foo", 2, 1)))),
+            Entry::new("b", join("bar"), 2, Some(3), Some(Outcome::Ticket(1, ticket("bar", 1, 0)))),
+            Entry::new("c", Operation::GetTickets, 5, None, None),
+        ];
+
+        check(&history).expect("linearizable");
+    }
+
+    #[test]
+    pub fn test_reordered_queue() {
+        let history = vec![
+            Entry::new("a", join("foo"), 1, Some(2), Some(Outcome::Ticket(1, ticket("foo", 1, 0)))),
+            Entry::new("b", Operation::GetTicket(s("baz")), 3, Some(4), Some(Outcome::NotFound)),
+            Entry::new("b", join("bar"), 5, Some(6), Some(Outcome::Ticket(2, ticket("bar", 2, 1)))),
+            Entry::new("c", Operation::GetTickets, 7, Some(8), Some(Outcome::Tickets(2, vec![ticket("bar", 2, 0), ticket("foo", 1, 1)]))),
+            Entry::new("a", Operation::LeaveQueue(s("foo")), 9, Some(10), Some(Outcome::Left(3))),
+        ];
+
+        let violation = check(&history).expect_err("reordered");
+        assert_eq!(violation.history, vec![history[0].clone(), history[2].clone(), history[3].clone()]);
+        assert!(violation.to_string().contains("GetTickets"));
+    }
+}
@@ -4,6 +4,8 @@
 #[macro_use]
 extern crate error_chain;
 extern crate rand;
+extern crate fs2;
+extern crate serde;
 #[macro_use]
 extern crate serde_derive;
 extern crate serde_json;
@@ -11,28 +13,88 @@ extern crate serde_json;
 extern crate log;
 #[cfg(feature = "aws")]
 extern crate rusoto;
+#[cfg(feature = "aws")]
+extern crate hyper;
+#[cfg(feature = "async")]
+extern crate futures;
+#[cfg(feature = "async")]
+extern crate futures_cpupool;
+#[cfg(feature = "async")]
+extern crate tokio_timer;
+#[cfg(feature = "sqlite")]
+extern crate rusqlite;
+#[cfg(feature = "postgres")]
+extern crate postgres as postgres_client;
+#[cfg(feature = "redis")]
+extern crate redis as redis_client;
 
+pub mod allocator;
+pub mod conformance;
+pub mod election;
 pub mod error;
+pub mod fault;
+pub mod file;
+pub mod history;
 pub mod local;
+pub mod lock;
+#[cfg(feature = "async")]
+pub mod nonblocking;
+pub mod observer;
 #[cfg(feature = "aws")]
 pub mod aws;
+#[cfg(feature = "postgres")]
+pub mod postgres;
+#[cfg(feature = "redis")]
+pub mod redis;
+pub mod retry;
+pub mod semaphore;
+#[cfg(unix)]
+pub mod socket;
+#[cfg(feature = "sqlite")]
+pub mod sqlite;
+pub mod store;
 pub mod string;
 pub mod time;
+pub mod watch;
 
+use std::cmp;
 use std::result;
+use std::thread;
+use std::ops::Range;
+use std::time::{Duration, Instant};
 use std::collections::BTreeMap;
+use retry::*;
 
 pub trait MonotonicCounter {
-    type Error;
+    /// Every implementation in this crate uses `error::Error`, so errors can be handled
+    /// the same way whichever backend is in use.
+    type Error: Into<error::Error>;
 
     fn get_value(&self) -> result::Result<u64, Self::Error>;
 
     fn next_value(&self) -> result::Result<u64, Self::Error>;
+
+    /// Atomically reserve the next `count` values, returning them as a range.
+    fn next_values(&self, count: u64) -> result::Result<Range<u64>, Self::Error>;
+
+    /// Set the value to `new` if it is currently `expected`, returning whether it was set.
+    /// Fails without writing if `new` is less than `expected`.
+    fn compare_and_set(&self, expected: u64, new: u64) -> result::Result<bool, Self::Error>;
+
+    /// Raise the value to at least `min_value`, returning the resulting value.
+    /// A counter already at or above `min_value` is left unchanged.
+    fn advance_to(&self, min_value: u64) -> result::Result<u64, Self::Error>;
 }
 
 pub type FencingToken = u64;
 
-#[derive(Debug, Clone, PartialEq, PartialOrd)]
+/// Time between polls in `MonotonicQueue::wait_for_position`, before jitter.
+pub const WAIT_RETRY_MILLIS: u64 = 100;
+
+/// Most jitter added to each wait in `MonotonicQueue::wait_for_position`.
+pub const WAIT_JITTER_MILLIS: u64 = 100;
+
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq, PartialOrd)]
 pub struct Ticket {
     pub process_id: String,
     pub counter: u64,
@@ -52,20 +114,149 @@ impl Ticket {
 }
 
 pub trait MonotonicQueue {
-    type Error;
+    /// Every implementation in this crate uses `error::Error`, so errors can be handled
+    /// the same way whichever backend is in use.
+    type Error: Into<error::Error>;
 
-    fn join_queue<T>(&self, process_id: String, tags: T) -> result::Result<(FencingToken, Ticket), Self::Error> where T: Into<Option<BTreeMap<String, String>>>;
+    /// Join the queue, or return the existing ticket if the process is already in it.
+    ///
+    /// If a lease is given the ticket expires unless renewed with `heartbeat` before
+    /// the lease runs out. The next call to find expired tickets removes them and takes a new
+    /// fencing token, so the tickets behind are never reported further forward under a token
+    /// the expired holders were given.
+    fn join_queue<T, L>(&self, process_id: String, tags: T, lease: L) -> result::Result<(FencingToken, Ticket), Self::Error>
+        where T: Into<Option<BTreeMap<String, String>>>, L: Into<Option<Duration>>;
 
     fn leave_queue(&self, process_id: &str) -> result::Result<FencingToken, Self::Error>;
 
+    /// Renew the lease on the process's ticket. Tickets without a lease are returned unchanged.
+    fn heartbeat(&self, process_id: &str) -> result::Result<(FencingToken, Ticket), Self::Error>;
+
     fn get_ticket(&self, process_id: &str) -> result::Result<(FencingToken, Ticket), Self::Error>;
 
     fn get_tickets(&self) -> result::Result<(FencingToken, Vec<Ticket>), Self::Error>;
+
+    /// Block until the process's ticket is at `position` or nearer the head of the queue,
+    /// where 0 is the head, returning the ticket. Returns `None` if `timeout` elapses first.
+    ///
+    /// The ticket is polled with `get_ticket`, as every `heartbeat` of a leased ticket takes a new
+    /// fencing token. If the ticket has a `lease` it is renewed with `heartbeat` on the first poll and
+    /// then once half the lease has passed since the last renewal.
+    /// Waits between polls are jittered so that waiting processes don't poll in step.
+    fn wait_for_position<L, T>(&self, process_id: &str, position: usize, lease: L, timeout: T) -> result::Result<Option<(FencingToken, Ticket)>, Self::Error>
+            where L: Into<Option<Duration>>, T: Into<Option<Duration>> {
+        let poll = RetryPolicy {
+            jitter: JitterStrategy::Bounded(WAIT_JITTER_MILLIS),
+            ..RetryPolicy::new(Duration::from_millis(WAIT_RETRY_MILLIS))
+        };
+
+        self.wait_for_position_with(process_id, position, lease, timeout, None, &poll)
+    }
+
+    /// `wait_for_position` for a ticket last joined or renewed at `renewed`, waiting
+    /// `poll.jittered_backoff(n)` after the nth poll. The lease is first renewed once half of it
+    /// has passed since `renewed`, or on the first poll if `renewed` is `None`. A wait is cut
+    /// short when the lease is due to be renewed, so a long poll can't let the ticket expire.
+    fn wait_for_position_with<L, T>(&self, process_id: &str, position: usize, lease: L, timeout: T, renewed: Option<Instant>, poll: &RetryPolicy) -> result::Result<Option<(FencingToken, Ticket)>, Self::Error>
+            where L: Into<Option<Duration>>, T: Into<Option<Duration>> {
+        let lease = lease.into();
+        let deadline = timeout.into().map(|t| Instant::now() + t);
+        let mut renewed = renewed;
+        let mut polls = 0;
+
+        loop {
+            let renew = lease.map(|l| renewed.map(|r| r.elapsed() >= l / 2).unwrap_or(true)).unwrap_or(false);
+
+            let (ft, ticket) = if renew {
+                renewed = Some(Instant::now());
+
+                self.heartbeat(process_id)?
+            } else {
+                self.get_ticket(process_id)?
+            };
+
+            if ticket.position <= position {
+                return Ok(Some((ft, ticket)));
+            }
+
+            polls += 1;
+
+            let mut wait = poll.jittered_backoff(polls);
+
+            if let (Some(lease), Some(renewed)) = (lease, renewed) {
+                wait = cmp::min(wait, (renewed + lease / 2).saturating_duration_since(Instant::now()));
+            }
+
+            if let Some(deadline) = deadline {
+                let now = Instant::now();
+
+                if now >= deadline {
+                    return Ok(None);
+                }
+
+                wait = cmp::min(wait, deadline - now);
+            }
+
+            thread::sleep(wait);
+        }
+    }
 }
 
 #[cfg(test)]
 mod tests {
+    use std::thread;
+    use std::time::{Duration, Instant};
+    use local::queue::Queue;
+    use string::*;
+    use super::*;
+
     #[test]
     fn it_works() {
     }
+
+    #[test]
+    pub fn test_wait_for_position() {
+        let q = Queue::new();
+        q.join_queue(s("foo"), None, None).expect("join");
+        q.join_queue(s("bar"), None, None).expect("join");
+
+        let (_ft, ticket) = q.wait_for_position("bar", 1, None, None).expect("wait").expect("ticket");
+        assert_eq!(ticket.position, 1);
+
+        let start = Instant::now();
+        assert_eq!(q.wait_for_position("bar", 0, None, Duration::from_millis(150)).expect("wait"), None);
+        assert!(start.elapsed() >= Duration::from_millis(150));
+
+        let q2 = q.clone();
+        let handle = thread::spawn(move || {
+            thread::sleep(Duration::from_millis(50));
+            q2.leave_queue("foo").expect("leave");
+        });
+
+        let (ft, ticket) = q.wait_for_position("bar", 0, None, Duration::from_secs(10)).expect("wait").expect("ticket");
+        assert_eq!(ft, 3);
+        assert_eq!(ticket.position, 0);
+
+        handle.join().expect("join");
+
+        assert!(q.wait_for_position("baz", 0, None, None).is_err());
+    }
+
+    #[test]
+    pub fn test_wait_for_position_renews_lease() {
+        let q = Queue::new();
+        q.join_queue(s("foo"), None, None).expect("join");
+        q.join_queue(s("bar"), None, Duration::from_secs(1)).expect("join");
+
+        // Renewed on the first poll, then only read until half the lease has passed.
+        assert_eq!(q.wait_for_position("bar", 0, Duration::from_secs(1), Duration::from_millis(400)).expect("wait"), None);
+
+        let (ft, tickets) = q.get_tickets().expect("get all");
+        assert_eq!(ft, 3);
+        assert_eq!(tickets.len(), 2);
+
+        // Without a lease the ticket is only read.
+        assert_eq!(q.wait_for_position("bar", 0, None, Duration::from_millis(100)).expect("wait"), None);
+        assert_eq!(q.get_tickets().expect("get all").0, ft);
+    }
 }
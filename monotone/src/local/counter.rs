@@ -1,3 +1,4 @@
+use std::ops::Range;
 use std::sync::{Arc, Mutex};
 
 use ::*;
@@ -26,9 +27,42 @@ impl MonotonicCounter for Counter {
     }
 
     fn next_value(&self) -> Result<u64> {
+        self.next_values(1).map(|values| values.start)
+    }
+
+    fn next_values(&self, count: u64) -> Result<Range<u64>> {
+        let mut counter = self.counter.lock().unwrap();
+
+        // The range is half open, so its end has to fit as well as the last value.
+        let end = counter.checked_add(count).and_then(|v| v.checked_add(1)).ok_or(ErrorKind::Overflow(*counter, count))?;
+        let start = *counter + 1;
+        *counter = end - 1;
+
+        Ok(start..end)
+    }
+
+    fn compare_and_set(&self, expected: u64, new: u64) -> Result<bool> {
+        if new < expected {
+            bail!(ErrorKind::WouldDecrease(expected, new));
+        }
+
+        let mut counter = self.counter.lock().unwrap();
+
+        if *counter != expected {
+            return Ok(false);
+        }
+
+        *counter = new;
+
+        Ok(true)
+    }
+
+    fn advance_to(&self, min_value: u64) -> Result<u64> {
         let mut counter = self.counter.lock().unwrap();
 
-        *counter += 1;
+        if *counter < min_value {
+            *counter = min_value;
+        }
 
         Ok(*counter)
     }
@@ -54,4 +88,56 @@ mod tests {
         assert_eq!(2, c.next_value().expect("next"));
         assert_eq!(2, c.get_value().expect("get"));
     }
-}
\ No newline at end of file
+
+    #[test]
+    pub fn test_counter_next_values() {
+        let c = Counter::new();
+        assert_eq!(1..11, c.next_values(10).expect("next values"));
+        assert_eq!(10, c.get_value().expect("get"));
+
+        assert_eq!(11, c.next_value().expect("next"));
+        assert_eq!(12..12, c.next_values(0).expect("next values"));
+        assert_eq!(11, c.get_value().expect("get"));
+    }
+
+    #[test]
+    pub fn test_counter_compare_and_set() {
+        let c = Counter::new();
+        assert!(c.compare_and_set(0, 5000).expect("cas"));
+        assert_eq!(5000, c.get_value().expect("get"));
+
+        assert!(!c.compare_and_set(0, 6000).expect("cas"));
+        assert_eq!(5000, c.get_value().expect("get"));
+
+        assert!(c.compare_and_set(5000, 4000).is_err());
+        assert_eq!(5001, c.next_value().expect("next"));
+    }
+
+    #[test]
+    pub fn test_counter_advance_to() {
+        let c = Counter::new();
+        assert_eq!(5000, c.advance_to(5000).expect("advance"));
+        assert_eq!(5000, c.advance_to(10).expect("advance"));
+        assert_eq!(5000, c.get_value().expect("get"));
+        assert_eq!(5001, c.next_value().expect("next"));
+    }
+
+    #[test]
+    pub fn test_counter_overflow() {
+        let c = Counter::new();
+        assert_eq!(u64::MAX - 1, c.advance_to(u64::MAX - 1).expect("advance"));
+
+        match c.next_value() {
+            Err(Error(ErrorKind::Overflow(value, 1), _)) => assert_eq!(value, u64::MAX - 1),
+            r => panic!("expected Overflow, got {:?}", r),
+        }
+
+        assert!(c.next_values(u64::MAX).is_err());
+        assert_eq!(u64::MAX - 1, c.get_value().expect("get"));
+    }
+
+    #[test]
+    pub fn test_counter_conformance() {
+        conformance::check_counter(Counter::new);
+    }
+}
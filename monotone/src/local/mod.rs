@@ -1,2 +1,4 @@
 pub mod counter;
 pub mod queue;
+#[cfg(feature = "async")]
+pub mod nonblocking;
@@ -0,0 +1,118 @@
+use std::ops::Range;
+use std::time::Duration;
+use std::collections::BTreeMap;
+use futures::future;
+use ::*;
+use ::error::*;
+use nonblocking::*;
+use super::counter::Counter;
+use super::queue::Queue;
+
+/// Async view of a local `Counter`. Local updates never conflict, so every future is already resolved.
+#[derive(Debug, Clone)]
+pub struct AsyncCounter {
+    pub counter: Counter,
+}
+
+impl AsyncCounter {
+    pub fn new(counter: Counter) -> AsyncCounter {
+        AsyncCounter {
+            counter: counter,
+        }
+    }
+}
+
+impl AsyncMonotonicCounter for AsyncCounter {
+    type Error = Error;
+
+    fn get_value(&self) -> BoxFuture<u64, Error> {
+        Box::new(future::result(self.counter.get_value()))
+    }
+
+    fn next_value(&self) -> BoxFuture<u64, Error> {
+        Box::new(future::result(self.counter.next_value()))
+    }
+
+    fn next_values(&self, count: u64) -> BoxFuture<Range<u64>, Error> {
+        Box::new(future::result(self.counter.next_values(count)))
+    }
+
+    fn compare_and_set(&self, expected: u64, new: u64) -> BoxFuture<bool, Error> {
+        Box::new(future::result(self.counter.compare_and_set(expected, new)))
+    }
+
+    fn advance_to(&self, min_value: u64) -> BoxFuture<u64, Error> {
+        Box::new(future::result(self.counter.advance_to(min_value)))
+    }
+}
+
+/// Async view of a local `Queue`.
+#[derive(Debug, Clone)]
+pub struct AsyncQueue {
+    pub queue: Queue,
+}
+
+impl AsyncQueue {
+    pub fn new(queue: Queue) -> AsyncQueue {
+        AsyncQueue {
+            queue: queue,
+        }
+    }
+}
+
+impl AsyncMonotonicQueue for AsyncQueue {
+    type Error = Error;
+
+    fn join_queue<T, L>(&self, process_id: String, tags: T, lease: L) -> BoxFuture<(FencingToken, Ticket), Error>
+            where T: Into<Option<BTreeMap<String, String>>>, L: Into<Option<Duration>> {
+        Box::new(future::result(self.queue.join_queue(process_id, tags, lease)))
+    }
+
+    fn leave_queue(&self, process_id: &str) -> BoxFuture<FencingToken, Error> {
+        Box::new(future::result(self.queue.leave_queue(process_id)))
+    }
+
+    fn heartbeat(&self, process_id: &str) -> BoxFuture<(FencingToken, Ticket), Error> {
+        Box::new(future::result(self.queue.heartbeat(process_id)))
+    }
+
+    fn get_ticket(&self, process_id: &str) -> BoxFuture<(FencingToken, Ticket), Error> {
+        Box::new(future::result(self.queue.get_ticket(process_id)))
+    }
+
+    fn get_tickets(&self) -> BoxFuture<(FencingToken, Vec<Ticket>), Error> {
+        Box::new(future::result(self.queue.get_tickets()))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use futures::Future;
+    use string::*;
+    use nonblocking::*;
+    use super::*;
+
+    #[test]
+    pub fn test_async_counter_next() {
+        let c = AsyncCounter::new(Counter::new());
+        assert_eq!(1, c.next_value().wait().expect("next"));
+        assert_eq!(2..12, c.next_values(10).wait().expect("next values"));
+        assert_eq!(11, c.get_value().wait().expect("get"));
+    }
+
+    #[test]
+    pub fn test_async_queue_join_leave() {
+        let q = AsyncQueue::new(Queue::new());
+        let (ft, tok) = q.join_queue(s("foo"), None, None).wait().expect("join");
+        assert_eq!(ft, 1);
+        assert_eq!(tok.position, 0);
+
+        let (_ft, tok2) = q.join_queue(s("bar"), None, None).wait().expect("join");
+        assert_eq!(tok2.position, 1);
+
+        q.leave_queue("foo").wait().expect("leave");
+
+        let (_ft, tok2) = q.get_ticket("bar").wait().expect("get");
+        assert_eq!(tok2.position, 0);
+    }
+}
@@ -1,28 +1,46 @@
-use std::sync::{Arc, Mutex};
+use std::sync::{Arc, Mutex, Condvar};
+use std::time::{Duration, Instant};
 use std::collections::BTreeMap;
 
 use ::*;
 use ::error::*;
+use watch::*;
 
-#[derive(Debug)]
+#[derive(Debug, Clone, PartialEq, Eq, Hash)]
 struct QueueTicket {
     pub process_id: String,
     pub counter: u64,
     pub tags: BTreeMap<String, String>,
+    pub lease: Option<Duration>,
+    pub expires: Option<Instant>,
 }
 
 impl QueueTicket {
-    pub fn new(process_id: String, counter: u64, tags: BTreeMap<String, String>) -> QueueTicket {
+    pub fn new(process_id: String, counter: u64, tags: BTreeMap<String, String>, lease: Option<Duration>) -> QueueTicket {
         QueueTicket {
             process_id: process_id,
             counter: counter,
             tags: tags,
+            lease: lease,
+            expires: lease.map(|l| Instant::now() + l),
         }
     }
+
+    pub fn is_expired(&self, now: Instant) -> bool {
+        self.expires.map(|e| e <= now).unwrap_or(false)
+    }
+
+    pub fn renew(&mut self) {
+        self.expires = self.lease.map(|l| Instant::now() + l);
+    }
 }
 
-#[derive(Debug)]
-struct QueueInner {
+/// State of a `Queue`, changed one call at a time under the queue's lock.
+///
+/// `history` replays recorded calls against this to check a queue behaves as though calls
+/// were made one at a time.
+#[derive(Debug, Clone, PartialEq, Eq, Hash, Default)]
+pub struct QueueInner {
     items: Vec<QueueTicket>,
     version: u64,
     counter: u64,
@@ -37,25 +55,42 @@ impl QueueInner {
         }
     }
 
-    fn join_queue<T>(&mut self, process_id: String, tags: T) -> Result<(u64, Ticket)> where T: Into<Option<BTreeMap<String, String>>> {
+    /// Remove expired tickets, taking the next fencing token if there were any, so the tickets
+    /// behind never move forward under a fencing token the expired holders were given.
+    fn expire(&mut self) {
+        let now = Instant::now();
+        let before = self.items.len();
+
+        self.items.retain(|t| !t.is_expired(now));
+
+        if self.items.len() != before {
+            self.version += 1;
+        }
+    }
+
+    pub fn join_queue<T, L>(&mut self, process_id: String, tags: T, lease: L) -> Result<(u64, Ticket)>
+            where T: Into<Option<BTreeMap<String, String>>>, L: Into<Option<Duration>> {
         if let Ok((ft, ticket)) = self.get_ticket(&process_id) {
             return Ok((ft, ticket));
         }
 
         self.version += 1;
 
+        self.counter += 1;
+
         let position = self.items.len();
         let counter = self.counter;
         let tags = tags.into().unwrap_or(BTreeMap::new());
-        let ticket = QueueTicket::new(process_id.clone(), counter, tags.clone());
+        let ticket = QueueTicket::new(process_id.clone(), counter, tags.clone(), lease.into());
 
         self.items.push(ticket);
-        self.counter += 1;
 
         Ok((self.version, Ticket::new(process_id, counter, position, tags)))
     }
 
-    fn leave_queue(&mut self, process_id: &str) -> Result<u64> {
+    pub fn leave_queue(&mut self, process_id: &str) -> Result<u64> {
+        self.expire();
+
         if let Some(pos) = self.items.iter().position(|t| t.process_id == process_id) {
             self.version += 1;
 
@@ -67,7 +102,25 @@ impl QueueInner {
         Ok(self.version)
     }
 
-    fn get_ticket(&self, process_id: &str) -> Result<(u64, Ticket)> {
+    pub fn heartbeat(&mut self, process_id: &str) -> Result<(u64, Ticket)> {
+        self.expire();
+
+        if let Some(pos) = self.items.iter().position(|t| t.process_id == process_id) {
+            if self.items[pos].lease.is_some() {
+                self.version += 1;
+
+                self.items[pos].renew();
+            }
+        } else {
+            bail!(ErrorKind::NotFound(process_id.to_owned()));
+        }
+
+        self.get_ticket(process_id)
+    }
+
+    pub fn get_ticket(&mut self, process_id: &str) -> Result<(u64, Ticket)> {
+        self.expire();
+
         self.items
             .iter()
             .enumerate()
@@ -76,7 +129,19 @@ impl QueueInner {
             .ok_or_else(|| ErrorKind::NotFound(process_id.to_owned()).into())
     }
 
-    fn get_tickets(&self) -> Result<(u64, Vec<Ticket>)> {
+    fn snapshot(&mut self) -> Snapshot {
+        self.expire();
+
+        Snapshot::new(self.version, self.items
+            .iter()
+            .enumerate()
+            .map(|(position,t)| WatchedTicket::new(Ticket::new(t.process_id.clone(), t.counter, position, t.tags.clone()), t.expires))
+            .collect())
+    }
+
+    pub fn get_tickets(&mut self) -> Result<(u64, Vec<Ticket>)> {
+        self.expire();
+
         Ok((self.version, self.items
             .iter()
             .enumerate()
@@ -85,15 +150,52 @@ impl QueueInner {
     }
 }
 
-#[derive(Debug)]
+#[derive(Debug, Clone)]
 pub struct Queue {
-    items: Arc<Mutex<QueueInner>>
+    items: Arc<Mutex<QueueInner>>,
+    changed: Arc<Condvar>,
 }
 
 impl Queue {
     pub fn new() -> Queue {
         Queue {
-            items: Arc::new(Mutex::new(QueueInner::new()))
+            items: Arc::new(Mutex::new(QueueInner::new())),
+            changed: Arc::new(Condvar::new()),
+        }
+    }
+}
+
+/// Watch on a `Queue`, woken by the queue's condition variable whenever it is written,
+/// or when the next lease runs out.
+pub struct Watch {
+    queue: Queue,
+    watcher: Watcher,
+}
+
+impl Iterator for Watch {
+    type Item = Result<QueueEvent>;
+
+    fn next(&mut self) -> Option<Result<QueueEvent>> {
+        let mut inner = self.queue.items.lock().unwrap();
+
+        loop {
+            if let Some(event) = self.watcher.pop() {
+                return Some(Ok(event));
+            }
+
+            if self.watcher.update(inner.snapshot()) {
+                continue;
+            }
+
+            inner = match self.watcher.next_expiry() {
+                Some(expires) => {
+                    let now = Instant::now();
+                    let timeout = if expires > now { expires - now } else { Duration::from_millis(0) };
+
+                    self.queue.changed.wait_timeout(inner, timeout).unwrap().0
+                },
+                None => self.queue.changed.wait(inner).unwrap(),
+            };
         }
     }
 }
@@ -101,36 +203,69 @@ impl Queue {
 impl MonotonicQueue for Queue {
     type Error = Error;
     
-    fn join_queue<T>(&self, process_id: String, tags: T) -> Result<(u64, Ticket)> where T: Into<Option<BTreeMap<String, String>>> {
+    fn join_queue<T, L>(&self, process_id: String, tags: T, lease: L) -> Result<(u64, Ticket)>
+            where T: Into<Option<BTreeMap<String, String>>>, L: Into<Option<Duration>> {
         let mut inner = self.items.lock().unwrap();
 
-        inner.join_queue(process_id, tags)
+        let result = inner.join_queue(process_id, tags, lease);
+
+        self.changed.notify_all();
+
+        result
     }
 
     fn leave_queue(&self, process_id: &str) -> Result<u64> {
         let mut inner = self.items.lock().unwrap();
 
-        inner.leave_queue(process_id)
+        let result = inner.leave_queue(process_id);
+
+        self.changed.notify_all();
+
+        result
+    }
+
+    fn heartbeat(&self, process_id: &str) -> Result<(u64, Ticket)> {
+        let mut inner = self.items.lock().unwrap();
+
+        let result = inner.heartbeat(process_id);
+
+        self.changed.notify_all();
+
+        result
     }
 
     fn get_ticket(&self, process_id: &str) -> Result<(u64, Ticket)> {
-        let inner = self.items.lock().unwrap();
+        let mut inner = self.items.lock().unwrap();
 
         inner.get_ticket(process_id)
     }
 
     fn get_tickets(&self) -> Result<(u64, Vec<Ticket>)> {
-        let inner = self.items.lock().unwrap();
+        let mut inner = self.items.lock().unwrap();
 
         inner.get_tickets()
     }
 }
 
 
+impl WatchableQueue for Queue {
+    fn watch<'a>(&'a self) -> Box<dyn Iterator<Item=Result<QueueEvent>> + 'a> {
+        Box::new(Watch {
+            queue: self.clone(),
+            watcher: Watcher::new(),
+        })
+    }
+}
+
 #[cfg(test)]
 mod tests {
+    use std::thread;
+    use std::sync::mpsc;
+    use std::time::Duration;
+    use std::collections::BTreeMap;
     use ::*;
     use string::*;
+    use watch::*;
     use super::*;
 
     /*
@@ -152,10 +287,10 @@ mod tests {
     #[test]
     pub fn test_queue_row_get() {
         let q = Queue::new();
-        let (ft, tok) = q.join_queue(s("foo"), None).expect("join");
+        let (ft, tok) = q.join_queue(s("foo"), None, None).expect("join");
         assert_eq!(ft, 1);
         assert_eq!(&tok.process_id, "foo");
-        assert_eq!(tok.counter, 0);
+        assert_eq!(tok.counter, 1);
         assert_eq!(tok.position, 0);
 
         let (ft, tok2) = q.get_ticket("foo").expect("get");
@@ -174,10 +309,10 @@ mod tests {
     #[test]
     pub fn test_queue_row_get_all() {
         let q = Queue::new();
-        let (ft, tok) = q.join_queue(s("foo"), None).expect("join");
+        let (ft, tok) = q.join_queue(s("foo"), None, None).expect("join");
         assert_eq!(ft, 1);
         assert_eq!(&tok.process_id, "foo");
-        assert_eq!(tok.counter, 0);
+        assert_eq!(tok.counter, 1);
         assert_eq!(tok.position, 0);
 
         let (ft, toks) = q.get_tickets().expect("get all");
@@ -188,23 +323,23 @@ mod tests {
     #[test]
     pub fn test_queue_no_row_join() {
         let q = Queue::new();
-        let (ft, tok) = q.join_queue(s("foo"), None).expect("join");
+        let (ft, tok) = q.join_queue(s("foo"), None, None).expect("join");
         assert_eq!(ft, 1);
         assert_eq!(&tok.process_id, "foo");
-        assert_eq!(tok.counter, 0);
+        assert_eq!(tok.counter, 1);
         assert_eq!(tok.position, 0);
     }
 
     #[test]
     pub fn test_queue_same_row_join() {
         let q = Queue::new();
-        let (ft, tok) = q.join_queue(s("foo"), None).expect("join");
+        let (ft, tok) = q.join_queue(s("foo"), None, None).expect("join");
         assert_eq!(ft, 1);
         assert_eq!(&tok.process_id, "foo");
-        assert_eq!(tok.counter, 0);
+        assert_eq!(tok.counter, 1);
         assert_eq!(tok.position, 0);
 
-        let (ft, tok2) = q.join_queue(s("foo"), None).expect("join");
+        let (ft, tok2) = q.join_queue(s("foo"), None, None).expect("join");
         assert_eq!(ft, 1);
         assert_eq!(tok2, tok);
     }
@@ -212,16 +347,16 @@ mod tests {
     #[test]
     pub fn test_queue_different_row_join() {
         let q = Queue::new();
-        let (ft, tok) = q.join_queue(s("foo"), None).expect("join");
+        let (ft, tok) = q.join_queue(s("foo"), None, None).expect("join");
         assert_eq!(ft, 1);
         assert_eq!(&tok.process_id, "foo");
-        assert_eq!(tok.counter, 0);
+        assert_eq!(tok.counter, 1);
         assert_eq!(tok.position, 0);
 
-        let (ft, tok) = q.join_queue(s("bar"), None).expect("join");
+        let (ft, tok) = q.join_queue(s("bar"), None, None).expect("join");
         assert_eq!(ft, 2);
         assert_eq!(&tok.process_id, "bar");
-        assert_eq!(tok.counter, 1);
+        assert_eq!(tok.counter, 2);
         assert_eq!(tok.position, 1);
     }
 
@@ -234,7 +369,7 @@ mod tests {
     #[test]
     pub fn test_queue_row_leave() {
         let q = Queue::new();
-        let (ft, _tok) = q.join_queue(s("foo"), None).expect("join");
+        let (ft, _tok) = q.join_queue(s("foo"), None, None).expect("join");
         assert_eq!(ft, 1);
 
         let ft = q.leave_queue("foo").expect("leave");
@@ -244,11 +379,11 @@ mod tests {
     #[test]
     pub fn test_queue_get_after_leave() {
         let q = Queue::new();
-        let (ft, tok) = q.join_queue(s("foo"), None).expect("join");
+        let (ft, tok) = q.join_queue(s("foo"), None, None).expect("join");
         assert_eq!(ft, 1);
         assert_eq!(tok.position, 0);
   
-        let (ft, tok) = q.join_queue(s("bar"), None).expect("join");
+        let (ft, tok) = q.join_queue(s("bar"), None, None).expect("join");
         assert_eq!(ft, 2);
         assert_eq!(tok.position, 1);
 
@@ -259,6 +394,120 @@ mod tests {
         assert_eq!(ft, 3);
         assert_eq!(tok.position, 0);
         assert_eq!(&tok.process_id, "bar");
+        assert_eq!(tok.counter, 2);
+    }
+
+    #[test]
+    pub fn test_queue_lease_expires() {
+        let q = Queue::new();
+        let (ft, _tok) = q.join_queue(s("foo"), None, Duration::from_millis(10)).expect("join");
+        assert_eq!(ft, 1);
+
+        let (ft, tok) = q.join_queue(s("bar"), None, None).expect("join");
+        assert_eq!(ft, 2);
+        assert_eq!(tok.position, 1);
+
+        thread::sleep(Duration::from_millis(20));
+
+        assert!(q.get_ticket("foo").is_err());
+
+        // foo's expiry takes a fencing token before bar is reported at the head.
+        let (ft, tok) = q.get_ticket("bar").expect("get");
+        assert_eq!(ft, 3);
+        assert_eq!(tok.position, 0);
+
+        let (ft, toks) = q.get_tickets().expect("get all");
+        assert_eq!(ft, 3);
+        assert_eq!(toks, vec![tok]);
+    }
+
+    #[test]
+    pub fn test_queue_lease_heartbeat() {
+        let q = Queue::new();
+        let (ft, tok) = q.join_queue(s("foo"), None, Duration::from_millis(50)).expect("join");
+        assert_eq!(ft, 1);
+
+        thread::sleep(Duration::from_millis(30));
+
+        let (ft, tok2) = q.heartbeat("foo").expect("heartbeat");
+        assert_eq!(ft, 2);
+        assert_eq!(tok2, tok);
+
+        thread::sleep(Duration::from_millis(30));
+
+        let (ft, tok3) = q.get_ticket("foo").expect("get");
+        assert_eq!(ft, 2);
+        assert_eq!(tok3, tok);
+    }
+
+    #[test]
+    pub fn test_queue_no_lease_heartbeat() {
+        let q = Queue::new();
+        let (ft, tok) = q.join_queue(s("foo"), None, None).expect("join");
+        assert_eq!(ft, 1);
+
+        let (ft, tok2) = q.heartbeat("foo").expect("heartbeat");
+        assert_eq!(ft, 1);
+        assert_eq!(tok2, tok);
+    }
+
+    #[test]
+    pub fn test_queue_expired_heartbeat() {
+        let q = Queue::new();
+        q.join_queue(s("foo"), None, Duration::from_millis(10)).expect("join");
+
+        thread::sleep(Duration::from_millis(20));
+
+        assert!(q.heartbeat("foo").is_err());
+    }
+
+    #[test]
+    pub fn test_queue_expired_rejoin() {
+        let q = Queue::new();
+        let (_ft, tok) = q.join_queue(s("foo"), None, Duration::from_millis(10)).expect("join");
         assert_eq!(tok.counter, 1);
+
+        thread::sleep(Duration::from_millis(20));
+
+        let (ft, tok) = q.join_queue(s("foo"), None, None).expect("join");
+        assert_eq!(ft, 3);
+        assert_eq!(tok.counter, 2);
+        assert_eq!(tok.position, 0);
+    }
+
+    #[test]
+    pub fn test_queue_watch() {
+        let q = Queue::new();
+        q.join_queue(s("foo"), None, None).expect("join");
+
+        let (tx, rx) = mpsc::channel();
+        let q2 = q.clone();
+
+        thread::spawn(move || {
+            for event in q2.watch() {
+                if tx.send(event.expect("event")).is_err() {
+                    break;
+                }
+            }
+        });
+
+        let foo = Ticket::new(s("foo"), 1, 0, BTreeMap::new());
+        assert_eq!(rx.recv().expect("recv"), QueueEvent::Joined { fencing_token: 1, ticket: foo.clone() });
+
+        let (_ft, bar) = q.join_queue(s("bar"), None, Duration::from_millis(50)).expect("join");
+        assert_eq!(rx.recv().expect("recv"), QueueEvent::Joined { fencing_token: 2, ticket: bar.clone() });
+
+        q.leave_queue("foo").expect("leave");
+        assert_eq!(rx.recv().expect("recv"), QueueEvent::Left { fencing_token: 3, ticket: foo });
+
+        let (_ft, bar) = q.get_ticket("bar").expect("get");
+        assert_eq!(rx.recv().expect("recv"), QueueEvent::PositionChanged { fencing_token: 3, ticket: bar.clone(), previous_position: 1 });
+
+        assert_eq!(rx.recv().expect("recv"), QueueEvent::Expired { fencing_token: 4, ticket: bar });
     }
-}
\ No newline at end of file
+
+    #[test]
+    pub fn test_queue_conformance() {
+        conformance::check_queue(Queue::new);
+    }
+}
@@ -0,0 +1,183 @@
+use std::result;
+use std::time::Duration;
+
+use ::*;
+use semaphore::*;
+
+/// Mutual exclusion on top of a queue: the process holding the first ticket owns the lock.
+/// This is a `Semaphore` with a single permit.
+///
+/// Pass the fencing token from the guard to any system the lock protects,
+/// so writes from a process that has lost the lock can be rejected.
+pub struct Lock<Q> where Q: MonotonicQueue {
+    pub semaphore: Semaphore<Q>,
+}
+
+pub struct LockGuard<'a, Q> where Q: 'a + MonotonicQueue {
+    permit: Permit<'a, Q>,
+}
+
+impl <Q> Lock<Q> where Q: MonotonicQueue {
+    pub fn new<S, L>(queue: Q, process_id: S, lease: L, retry_time: Duration) -> Lock<Q> where S: Into<String>, L: Into<Option<Duration>> {
+        Lock {
+            semaphore: Semaphore {
+                queue: queue,
+                process_id: process_id.into(),
+                permits: 1,
+                lease: lease.into(),
+                retry_time: retry_time,
+                jitter_millis: 100,
+            },
+        }
+    }
+
+    /// Block until the lock is acquired.
+    pub fn lock<'a>(&'a self) -> result::Result<LockGuard<'a, Q>, Q::Error> {
+        self.semaphore.acquire().map(LockGuard::new)
+    }
+
+    /// Acquire the lock if it is free, without waiting.
+    pub fn try_lock<'a>(&'a self) -> result::Result<Option<LockGuard<'a, Q>>, Q::Error> {
+        self.semaphore.try_acquire().map(|permit| permit.map(LockGuard::new))
+    }
+
+    /// Wait up to `timeout` for the lock, giving up our place in the queue if it is not acquired.
+    pub fn lock_for<'a>(&'a self, timeout: Duration) -> result::Result<Option<LockGuard<'a, Q>>, Q::Error> {
+        self.semaphore.acquire_for(timeout).map(|permit| permit.map(LockGuard::new))
+    }
+}
+
+impl <'a, Q> LockGuard<'a, Q> where Q: 'a + MonotonicQueue {
+    fn new(permit: Permit<'a, Q>) -> LockGuard<'a, Q> {
+        LockGuard {
+            permit: permit,
+        }
+    }
+
+    /// The fencing token at which the lock was acquired.
+    pub fn fencing_token(&self) -> FencingToken {
+        self.permit.fencing_token()
+    }
+
+    pub fn ticket(&self) -> &Ticket {
+        self.permit.ticket()
+    }
+
+    /// Renew the lease on the lock, returning the new fencing token.
+    pub fn heartbeat(&mut self) -> result::Result<FencingToken, Q::Error> {
+        self.permit.heartbeat()
+    }
+
+    /// Release the lock, returning the fencing token of the release.
+    pub fn unlock(self) -> result::Result<FencingToken, Q::Error> {
+        self.permit.release()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::thread;
+    use std::time::Duration;
+    use local::queue::Queue;
+    use super::*;
+
+    fn retry_time() -> Duration {
+        Duration::from_millis(10)
+    }
+
+    #[test]
+    pub fn test_lock_uncontended() {
+        let l = Lock::new(Queue::new(), "foo", None, retry_time());
+
+        let guard = l.lock().expect("lock");
+        assert_eq!(guard.fencing_token(), 1);
+        assert_eq!(guard.ticket().position, 0);
+
+        let ft = guard.unlock().expect("unlock");
+        assert_eq!(ft, 2);
+
+        let (ft, tickets) = l.semaphore.queue.get_tickets().expect("get all");
+        assert_eq!(ft, 2);
+        assert_eq!(tickets, vec![]);
+    }
+
+    #[test]
+    pub fn test_lock_try_lock_contended() {
+        let q = Queue::new();
+        let foo = Lock::new(q.clone(), "foo", None, retry_time());
+        let bar = Lock::new(q.clone(), "bar", None, retry_time());
+
+        let guard = foo.try_lock().expect("try lock").expect("acquired");
+        assert_eq!(guard.fencing_token(), 1);
+
+        assert!(bar.try_lock().expect("try lock").is_none());
+
+        let (ft, tickets) = q.get_tickets().expect("get all");
+        assert_eq!(ft, 3);
+        assert_eq!(tickets.len(), 1);
+        assert_eq!(&tickets[0].process_id, "foo");
+    }
+
+    #[test]
+    pub fn test_lock_for_timeout() {
+        let q = Queue::new();
+        let foo = Lock::new(q.clone(), "foo", None, retry_time());
+        let bar = Lock::new(q.clone(), "bar", None, retry_time());
+
+        let _guard = foo.lock().expect("lock");
+
+        assert!(bar.lock_for(Duration::from_millis(50)).expect("lock for").is_none());
+        assert!(q.get_ticket("bar").is_err());
+    }
+
+    #[test]
+    pub fn test_lock_drop_releases() {
+        let q = Queue::new();
+        let foo = Lock::new(q.clone(), "foo", None, retry_time());
+        let bar = Lock::new(q.clone(), "bar", None, retry_time());
+
+        {
+            let _guard = foo.lock().expect("lock");
+            assert!(bar.try_lock().expect("try lock").is_none());
+        }
+
+        let guard = bar.try_lock().expect("try lock").expect("acquired");
+        assert_eq!(guard.fencing_token(), 5);
+    }
+
+    #[test]
+    pub fn test_lock_waiter_leaves_fencing_token() {
+        let q = Queue::new();
+        let foo = Lock::new(q.clone(), "foo", Duration::from_secs(60), retry_time());
+        let bar = Lock::new(q.clone(), "bar", Duration::from_secs(60), retry_time());
+
+        let guard = foo.lock().expect("lock");
+        assert_eq!(guard.fencing_token(), 1);
+
+        // The fencing token moves once for bar joining and once for it giving up and leaving,
+        // but not for each time bar polls for the lock in between.
+        assert!(bar.lock_for(Duration::from_millis(100)).expect("lock for").is_none());
+        assert_eq!(q.get_ticket("foo").expect("get").0, 3);
+    }
+
+    #[test]
+    pub fn test_lock_waits_for_release() {
+        let q = Queue::new();
+        let foo = Lock::new(q.clone(), "foo", None, retry_time());
+        let bar_queue = q.clone();
+
+        let guard = foo.lock().expect("lock");
+
+        let waiter = thread::spawn(move || {
+            let bar = Lock::new(bar_queue, "bar", None, retry_time());
+            let guard = bar.lock().expect("lock");
+            guard.fencing_token()
+        });
+
+        thread::sleep(Duration::from_millis(50));
+        let released = guard.unlock().expect("unlock");
+
+        let acquired = waiter.join().expect("join");
+        assert!(acquired >= released);
+    }
+}
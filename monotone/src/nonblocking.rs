@@ -0,0 +1,202 @@
+//! Futures-based counterparts of `MonotonicCounter` and `MonotonicQueue`.
+//!
+//! Conflicting writes are retried after a jittered delay on a `tokio_timer::Timer`
+//! rather than by putting the calling thread to sleep.
+
+use std::sync::Arc;
+use std::ops::Range;
+use std::time::{Duration, Instant};
+use std::collections::BTreeMap;
+use futures::{future, Future};
+use futures::future::Loop;
+use tokio_timer::Timer;
+use ::*;
+use retry::*;
+
+pub type BoxFuture<T, E> = Box<dyn Future<Item=T, Error=E> + Send>;
+
+pub trait AsyncMonotonicCounter {
+    /// Every implementation in this crate uses `error::Error`, so errors can be handled
+    /// the same way whichever backend is in use.
+    type Error: Into<error::Error>;
+
+    fn get_value(&self) -> BoxFuture<u64, Self::Error>;
+
+    fn next_value(&self) -> BoxFuture<u64, Self::Error>;
+
+    /// Atomically reserve the next `count` values, returning them as a range.
+    fn next_values(&self, count: u64) -> BoxFuture<Range<u64>, Self::Error>;
+
+    /// Set the value to `new` if it is currently `expected`, resolving to whether it was set.
+    fn compare_and_set(&self, expected: u64, new: u64) -> BoxFuture<bool, Self::Error>;
+
+    /// Raise the value to at least `min_value`, resolving to the resulting value.
+    fn advance_to(&self, min_value: u64) -> BoxFuture<u64, Self::Error>;
+}
+
+pub trait AsyncMonotonicQueue {
+    /// Every implementation in this crate uses `error::Error`, so errors can be handled
+    /// the same way whichever backend is in use.
+    type Error: Into<error::Error>;
+
+    fn join_queue<T, L>(&self, process_id: String, tags: T, lease: L) -> BoxFuture<(FencingToken, Ticket), Self::Error>
+        where T: Into<Option<BTreeMap<String, String>>>, L: Into<Option<Duration>>;
+
+    fn leave_queue(&self, process_id: &str) -> BoxFuture<FencingToken, Self::Error>;
+
+    /// Renew the lease on the process's ticket.
+    fn heartbeat(&self, process_id: &str) -> BoxFuture<(FencingToken, Ticket), Self::Error>;
+
+    fn get_ticket(&self, process_id: &str) -> BoxFuture<(FencingToken, Ticket), Self::Error>;
+
+    fn get_tickets(&self) -> BoxFuture<(FencingToken, Vec<Ticket>), Self::Error>;
+}
+
+/// Run `attempt` until it resolves to anything other than a conflict, waiting on `timer`
+/// between attempts for as long as `policy` says. Fails with `exhausted(&policy, conflicts)` if `policy` gives up.
+///
+/// `on_conflict` is called with the number of conflicts so far and the delay before the
+/// next attempt, or `None` if giving up. The policy's deadline counts from when the returned
+/// future is first polled, not from when it is built.
+pub fn retry<T, E, F, R, C>(timer: Timer, policy: RetryPolicy, is_conflict: fn(&E) -> bool, exhausted: fn(&RetryPolicy, u32) -> E, on_conflict: C, attempt: F) -> BoxFuture<T, E>
+        where F: Fn() -> R + Send + 'static,
+              R: Future<Item=T, Error=E> + Send + 'static,
+              C: Fn(u32, Option<Duration>) + Send + Sync + 'static,
+              T: Send + 'static,
+              E: Send + 'static {
+    let on_conflict = Arc::new(on_conflict);
+
+    Box::new(future::lazy(move || {
+        let started = Instant::now();
+
+        future::loop_fn(0, move |conflicts| {
+            let timer = timer.clone();
+            let policy = policy.clone();
+            let on_conflict = on_conflict.clone();
+
+            attempt().then(move |result| -> BoxFuture<Loop<T, u32>, E> {
+                match result {
+                    Err(ref e) if is_conflict(e) => {
+                        let conflicts = conflicts + 1;
+                        let delay = policy.next_delay(conflicts, started);
+
+                        on_conflict(conflicts, delay);
+
+                        match delay {
+                            Some(delay) => {
+                                // try again
+                                info!("transient error updating");
+                                Box::new(timer.sleep(delay).then(move |result| {
+                                    if let Err(e) = result {
+                                        warn!("error waiting to retry: {}", e);
+                                    }
+
+                                    Ok(Loop::Continue(conflicts))
+                                }))
+                            },
+                            None => {
+                                Box::new(future::err(exhausted(&policy, conflicts)))
+                            }
+                        }
+                    },
+                    result => {
+                        Box::new(future::result(result.map(Loop::Break)))
+                    }
+                }
+            })
+        })
+    }))
+}
+
+#[cfg(test)]
+mod tests {
+    use std::thread;
+    use std::sync::{Arc, Mutex};
+    use std::sync::atomic::{AtomicUsize, Ordering};
+    use std::time::Duration;
+    use futures::{future, Future};
+    use tokio_timer::Timer;
+    use super::*;
+
+    fn is_conflict(e: &String) -> bool {
+        e == "conflict"
+    }
+
+    fn exhausted(_policy: &RetryPolicy, conflicts: u32) -> String {
+        format!("gave up after {}", conflicts)
+    }
+
+    fn policy() -> RetryPolicy {
+        RetryPolicy::new(Duration::from_millis(1))
+    }
+
+    #[test]
+    pub fn test_retry_until_no_conflict() {
+        let attempts = Arc::new(AtomicUsize::new(0));
+        let counter = attempts.clone();
+
+        let result = retry(Timer::default(), policy(), is_conflict, exhausted, |_, _| {}, move || {
+            if counter.fetch_add(1, Ordering::SeqCst) < 3 {
+                future::err(String::from("conflict"))
+            } else {
+                future::ok(42)
+            }
+        }).wait();
+
+        assert_eq!(result, Ok(42));
+        assert_eq!(attempts.load(Ordering::SeqCst), 4);
+    }
+
+    #[test]
+    pub fn test_retry_stops_on_other_errors() {
+        let result: Result<u64, String> = retry(Timer::default(), policy(), is_conflict, exhausted, |_, _| {}, || {
+            future::err(String::from("broken"))
+        }).wait();
+
+        assert_eq!(result, Err(String::from("broken")));
+    }
+
+    #[test]
+    pub fn test_retry_gives_up() {
+        let policy = RetryPolicy {
+            max_attempts: Some(2),
+            ..policy()
+        };
+
+        let conflicts = Arc::new(Mutex::new(vec![]));
+        let seen = conflicts.clone();
+
+        let result: Result<u64, String> = retry(Timer::default(), policy, is_conflict, exhausted, move |n, delay| seen.lock().unwrap().push((n, delay.is_some())), || {
+            future::err(String::from("conflict"))
+        }).wait();
+
+        assert_eq!(result, Err(String::from("gave up after 2")));
+        assert_eq!(*conflicts.lock().unwrap(), vec![(1, true), (2, false)]);
+    }
+
+    #[test]
+    pub fn test_retry_deadline_starts_on_first_poll() {
+        let policy = RetryPolicy {
+            deadline: Some(Duration::from_millis(50)),
+            jitter: JitterStrategy::None,
+            ..policy()
+        };
+
+        let attempts = Arc::new(AtomicUsize::new(0));
+        let counter = attempts.clone();
+
+        let f = retry(Timer::default(), policy, is_conflict, exhausted, |_, _| {}, move || {
+            if counter.fetch_add(1, Ordering::SeqCst) < 1 {
+                future::err(String::from("conflict"))
+            } else {
+                future::ok(42)
+            }
+        });
+
+        // Built long before it is run, which mustn't count against the deadline.
+        thread::sleep(Duration::from_millis(100));
+
+        assert_eq!(f.wait(), Ok(42));
+        assert_eq!(attempts.load(Ordering::SeqCst), 2);
+    }
+}
@@ -0,0 +1,244 @@
+//! Hooks for watching how store-backed counters and queues retry conflicting writes.
+//!
+//! Every call on a store-backed `Counter` or `Queue` is reported to its `observer`: each attempt,
+//! each conflict, each wait before trying again, and how long the call took overall when it
+//! succeeds or fails. Reads are made once, so only report their attempt and result, except that
+//! a queue read that finds expired tickets writes them back as a retried `prune` operation.
+//! The `local` and `socket` counters and queues never retry and have no observer. `Metrics` is an `Observer` that keeps counts and latency histograms for
+//! each counter and queue ID:
+//!
+//! ```ignore
+//! let metrics = Metrics::new();
+//!
+//! let mut counter = Counter::new(store, "foo", Duration::from_millis(10));
+//! counter.observer = Arc::new(metrics.clone());
+//!
+//! counter.next_value()?;
+//!
+//! let foo = metrics.get("foo").unwrap();
+//! println!("{} conflicts, p99 {:?}", foo.conflicts, foo.latency.percentile(99.0));
+//! ```
+
+use std::cmp;
+use std::error;
+use std::sync::{Arc, Mutex};
+use std::time::Duration;
+use std::collections::BTreeMap;
+use time::*;
+
+/// Upper bounds of the buckets in a latency `Histogram`, in microseconds.
+/// Calls slower than the last bound are counted in one more bucket.
+pub const LATENCY_BUCKETS_MICROS: [u64; 16] = [
+    100, 250, 500,
+    1_000, 2_500, 5_000,
+    10_000, 25_000, 50_000,
+    100_000, 250_000, 500_000,
+    1_000_000, 2_500_000, 5_000_000,
+    10_000_000,
+];
+
+/// Told about each step of every call. `id` is the counter or queue ID and `operation` the
+/// method called, such as `"next_values"` or `"join_queue"`.
+///
+/// Methods are called on the thread making the call, between attempts, so they should be quick.
+pub trait Observer: Send + Sync {
+    /// An attempt is about to start.
+    fn attempt(&self, _id: &str, _operation: &str) {}
+
+    /// An attempt lost a race with a concurrent write. `conflicts` counts conflicts so far in this call.
+    fn conflict(&self, _id: &str, _operation: &str, _conflicts: u32) {}
+
+    /// Waiting `delay` before the next attempt.
+    fn backoff(&self, _id: &str, _operation: &str, _delay: Duration) {}
+
+    /// The call succeeded, `elapsed` after the first attempt started.
+    fn success(&self, _id: &str, _operation: &str, _elapsed: Duration) {}
+
+    /// The call failed, `elapsed` after the first attempt started.
+    fn error(&self, _id: &str, _operation: &str, _elapsed: Duration, _error: &dyn error::Error) {}
+}
+
+/// Observer that ignores everything. The default for store-backed counters and queues.
+#[derive(Debug, Clone, Copy, Default)]
+pub struct NoObserver;
+
+impl Observer for NoObserver {}
+
+/// Counts of calls falling into each of the `LATENCY_BUCKETS_MICROS` buckets.
+#[derive(Debug, Clone, PartialEq)]
+pub struct Histogram {
+    pub buckets: Vec<u64>,
+    pub count: u64,
+    pub total: Duration,
+    pub max: Duration,
+}
+
+impl Default for Histogram {
+    fn default() -> Histogram {
+        Histogram {
+            buckets: vec![0; LATENCY_BUCKETS_MICROS.len() + 1],
+            count: 0,
+            total: Duration::from_millis(0),
+            max: Duration::from_millis(0),
+        }
+    }
+}
+
+impl Histogram {
+    pub fn new() -> Histogram {
+        Histogram::default()
+    }
+
+    pub fn record(&mut self, latency: Duration) {
+        let micros = duration_micros(latency);
+        let bucket = LATENCY_BUCKETS_MICROS.iter()
+            .position(|&bound| micros <= bound)
+            .unwrap_or(LATENCY_BUCKETS_MICROS.len());
+
+        self.buckets[bucket] += 1;
+        self.count += 1;
+        self.total += latency;
+        self.max = cmp::max(self.max, latency);
+    }
+
+    pub fn mean(&self) -> Option<Duration> {
+        if self.count == 0 {
+            return None;
+        }
+
+        Some(Duration::from_micros(duration_micros(self.total) / self.count))
+    }
+
+    /// The upper bound of the bucket holding the `percentile`th latency, or the slowest
+    /// latency if that's lower. `None` if nothing has been recorded.
+    pub fn percentile(&self, percentile: f64) -> Option<Duration> {
+        if self.count == 0 {
+            return None;
+        }
+
+        let rank = cmp::max(1, (percentile / 100.0 * self.count as f64).ceil() as u64);
+        let mut seen = 0;
+
+        for (bucket, &count) in self.buckets.iter().enumerate() {
+            seen += count;
+
+            if seen >= rank {
+                let bound = LATENCY_BUCKETS_MICROS.get(bucket).map(|&b| Duration::from_micros(b)).unwrap_or(self.max);
+
+                return Some(cmp::min(bound, self.max));
+            }
+        }
+
+        Some(self.max)
+    }
+}
+
+/// What `Metrics` has seen of one counter or queue.
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct IdMetrics {
+    pub attempts: u64,
+    pub conflicts: u64,
+    pub backoffs: u64,
+    pub backoff_time: Duration,
+    pub successes: u64,
+    pub errors: u64,
+    /// How long calls took, successful or not, from the first attempt.
+    pub latency: Histogram,
+    /// Calls made, by operation.
+    pub operations: BTreeMap<String, u64>,
+}
+
+/// In-memory `Observer` aggregating what happens to each counter and queue ID.
+/// Clones share the same metrics, so keep one to read them and give others to counters and queues.
+#[derive(Debug, Clone, Default)]
+pub struct Metrics {
+    ids: Arc<Mutex<BTreeMap<String, IdMetrics>>>,
+}
+
+impl Metrics {
+    pub fn new() -> Metrics {
+        Metrics::default()
+    }
+
+    pub fn get(&self, id: &str) -> Option<IdMetrics> {
+        self.ids.lock().unwrap().get(id).cloned()
+    }
+
+    /// Metrics for every ID seen so far.
+    pub fn snapshot(&self) -> BTreeMap<String, IdMetrics> {
+        self.ids.lock().unwrap().clone()
+    }
+
+    pub fn reset(&self) {
+        self.ids.lock().unwrap().clear();
+    }
+
+    fn update<F>(&self, id: &str, f: F) where F: FnOnce(&mut IdMetrics) {
+        let mut ids = self.ids.lock().unwrap();
+
+        f(ids.entry(id.to_owned()).or_default());
+    }
+}
+
+impl Observer for Metrics {
+    fn attempt(&self, id: &str, _operation: &str) {
+        self.update(id, |m| m.attempts += 1);
+    }
+
+    fn conflict(&self, id: &str, _operation: &str, _conflicts: u32) {
+        self.update(id, |m| m.conflicts += 1);
+    }
+
+    fn backoff(&self, id: &str, _operation: &str, delay: Duration) {
+        self.update(id, |m| {
+            m.backoffs += 1;
+            m.backoff_time += delay;
+        });
+    }
+
+    fn success(&self, id: &str, operation: &str, elapsed: Duration) {
+        self.update(id, |m| {
+            m.successes += 1;
+            m.latency.record(elapsed);
+            *m.operations.entry(operation.to_owned()).or_insert(0) += 1;
+        });
+    }
+
+    fn error(&self, id: &str, operation: &str, elapsed: Duration, _error: &dyn error::Error) {
+        self.update(id, |m| {
+            m.errors += 1;
+            m.latency.record(elapsed);
+            *m.operations.entry(operation.to_owned()).or_insert(0) += 1;
+        });
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::time::Duration;
+    use super::*;
+
+    #[test]
+    pub fn test_histogram() {
+        let mut h = Histogram::new();
+
+        assert_eq!(h.percentile(50.0), None);
+        assert_eq!(h.mean(), None);
+
+        for millis in 1..101 {
+            h.record(Duration::from_millis(millis));
+        }
+
+        assert_eq!(h.count, 100);
+        assert_eq!(h.max, Duration::from_millis(100));
+        assert_eq!(h.mean(), Some(Duration::from_micros(50_500)));
+        assert_eq!(h.percentile(1.0), Some(Duration::from_millis(1)));
+        assert_eq!(h.percentile(10.0), Some(Duration::from_millis(10)));
+        assert_eq!(h.percentile(50.0), Some(Duration::from_millis(50)));
+        assert_eq!(h.percentile(99.0), Some(Duration::from_millis(100)));
+
+        h.record(Duration::from_secs(60));
+        assert_eq!(h.buckets[LATENCY_BUCKETS_MICROS.len()], 1);
+        assert_eq!(h.percentile(100.0), Some(Duration::from_secs(60)));
+    }
+}
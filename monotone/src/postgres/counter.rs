@@ -0,0 +1,7 @@
+use store;
+use super::store::PostgresStore;
+
+pub use store::counter::{CounterRow, COUNTER_TYPE};
+
+/// Counter kept in a PostgreSQL table.
+pub type Counter = store::counter::Counter<PostgresStore>;
@@ -0,0 +1,9 @@
+use postgres_client;
+use serde_json;
+
+error_chain! {
+    foreign_links {
+        Postgres(postgres_client::Error);
+        Json(serde_json::Error);
+    }
+}
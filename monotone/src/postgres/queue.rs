@@ -0,0 +1,7 @@
+use store;
+use super::store::PostgresStore;
+
+pub use store::queue::{QueuePosition, QueueRow, QUEUE_TYPE};
+
+/// Queue kept in a PostgreSQL table.
+pub type Queue = store::queue::Queue<PostgresStore>;
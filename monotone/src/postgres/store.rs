@@ -0,0 +1,137 @@
+use std::sync::Mutex;
+use postgres_client::{Client, NoTls};
+use serde_json;
+use store::*;
+use super::error::*;
+
+/// `VersionedStore` keeping each row in a PostgreSQL table with `ID`, `Type`, `Version`, `Value`
+/// and `Items` columns. Writes only succeed if `Version` still has the value that was read.
+pub struct PostgresStore {
+    pub client: Mutex<Client>,
+    pub table_name: String,
+}
+
+impl PostgresStore {
+    /// Connect using a connection string such as `postgres://user@localhost/db`, creating the table if needed.
+    pub fn connect<S>(params: &str, table_name: S) -> Result<PostgresStore> where S: Into<String> {
+        let client = Client::connect(params, NoTls)?;
+
+        PostgresStore::new(client, table_name)
+    }
+
+    pub fn new<S>(mut client: Client, table_name: S) -> Result<PostgresStore> where S: Into<String> {
+        let table_name = table_name.into();
+
+        create_table_if_needed(&mut client, &table_name)?;
+
+        Ok(PostgresStore {
+            client: Mutex::new(client),
+            table_name: table_name,
+        })
+    }
+}
+
+fn quote(table_name: &str) -> String {
+    format!("\"{}\"", table_name.replace("\"", "\"\""))
+}
+
+/// The statement `put` runs for a row read at `version`, taking the ID, type, next version, value
+/// and items as `$1` to `$5`. Version 0 inserts the row unless the ID already exists; any other
+/// version updates the row only where the stored version is still `$6`.
+fn put_sql(table_name: &str, version: u64) -> String {
+    if version == 0 {
+        format!("INSERT INTO {} (\"ID\", \"Type\", \"Version\", \"Value\", \"Items\") VALUES ($1, $2, $3, $4, $5) ON CONFLICT (\"ID\") DO NOTHING", quote(table_name))
+    } else {
+        format!("UPDATE {} SET \"Type\" = $2, \"Version\" = $3, \"Value\" = $4, \"Items\" = $5 WHERE \"ID\" = $1 AND \"Version\" = $6", quote(table_name))
+    }
+}
+
+pub fn create_table_if_needed(client: &mut Client, table_name: &str) -> Result<()> {
+    client.batch_execute(&format!("CREATE TABLE IF NOT EXISTS {} (
+            \"ID\" TEXT PRIMARY KEY,
+            \"Type\" TEXT NOT NULL,
+            \"Version\" BIGINT NOT NULL,
+            \"Value\" BIGINT NOT NULL,
+            \"Items\" TEXT NOT NULL
+        )", quote(table_name)))?;
+
+    Ok(())
+}
+
+impl VersionedStore for PostgresStore {
+    type Error = Error;
+
+    fn get(&self, id: &str) -> Result<Option<Row>> {
+        let mut client = self.client.lock().unwrap();
+
+        let sql = format!("SELECT \"Type\", \"Version\", \"Value\", \"Items\" FROM {} WHERE \"ID\" = $1", quote(&self.table_name));
+
+        if let Some(r) = client.query_opt(sql.as_str(), &[&id])? {
+            let version: i64 = r.try_get(1)?;
+            let value: i64 = r.try_get(2)?;
+            let items: String = r.try_get(3)?;
+
+            Ok(Some(Row {
+                typ: r.try_get(0)?,
+                version: version as u64,
+                value: value as u64,
+                items: serde_json::from_str(&items)?,
+            }))
+        } else {
+            Ok(None)
+        }
+    }
+
+    fn put(&self, id: &str, row: &Row) -> Result<bool> {
+        let mut client = self.client.lock().unwrap();
+
+        let items = serde_json::to_string(&row.items)?;
+        let version = row.version as i64;
+        let next_version = version + 1;
+        let value = row.value as i64;
+
+        let sql = put_sql(&self.table_name, row.version);
+
+        let changed = if row.version == 0 {
+            client.execute(sql.as_str(), &[&id, &row.typ, &next_version, &value, &items])?
+        } else {
+            client.execute(sql.as_str(), &[&id, &row.typ, &next_version, &value, &items, &version])?
+        };
+
+        Ok(changed == 1)
+    }
+
+    fn delete(&self, id: &str) -> Result<()> {
+        let mut client = self.client.lock().unwrap();
+
+        let sql = format!("DELETE FROM {} WHERE \"ID\" = $1", quote(&self.table_name));
+
+        client.execute(sql.as_str(), &[&id])?;
+
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    pub fn test_postgres_quote() {
+        assert_eq!(quote("Counters"), "\"Counters\"");
+        assert_eq!(quote("My \"Counters\""), "\"My \"\"Counters\"\"\"");
+    }
+
+    #[test]
+    pub fn test_postgres_put_sql_inserts_at_version_zero() {
+        assert_eq!(put_sql("Counters", 0),
+            "INSERT INTO \"Counters\" (\"ID\", \"Type\", \"Version\", \"Value\", \"Items\") VALUES ($1, $2, $3, $4, $5) ON CONFLICT (\"ID\") DO NOTHING");
+    }
+
+    #[test]
+    pub fn test_postgres_put_sql_updates_matching_version() {
+        assert_eq!(put_sql("Counters", 3),
+            "UPDATE \"Counters\" SET \"Type\" = $2, \"Version\" = $3, \"Value\" = $4, \"Items\" = $5 WHERE \"ID\" = $1 AND \"Version\" = $6");
+        assert_eq!(put_sql("Counters", 1), put_sql("Counters", 3));
+    }
+}
@@ -0,0 +1,7 @@
+use store;
+use super::store::RedisStore;
+
+pub use store::counter::{CounterRow, COUNTER_TYPE};
+
+/// Counter kept in Redis.
+pub type Counter = store::counter::Counter<RedisStore>;
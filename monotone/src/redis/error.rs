@@ -0,0 +1,18 @@
+use std::num;
+use redis_client;
+use serde_json;
+
+error_chain! {
+    foreign_links {
+        Redis(redis_client::RedisError);
+        ParseError(num::ParseIntError);
+        Json(serde_json::Error);
+    }
+
+    errors {
+        MissingField(field: String) {
+            description("missing field")
+            display("missing field: {}", field)
+        }
+    }
+}
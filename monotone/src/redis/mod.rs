@@ -0,0 +1,6 @@
+//! Counters and queues kept in Redis, one hash per ID.
+
+pub mod counter;
+pub mod error;
+pub mod queue;
+pub mod store;
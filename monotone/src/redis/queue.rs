@@ -0,0 +1,7 @@
+use store;
+use super::store::RedisStore;
+
+pub use store::queue::{QueuePosition, QueueRow, QUEUE_TYPE};
+
+/// Queue kept in Redis.
+pub type Queue = store::queue::Queue<RedisStore>;
@@ -0,0 +1,169 @@
+use std::sync::Mutex;
+use std::collections::HashMap;
+use redis_client::{self, Client, Connection};
+use serde_json;
+use store::*;
+use super::error::*;
+
+/// `VersionedStore` keeping each row in a Redis hash, keyed on `key_prefix` followed by the
+/// counter or queue ID, with `Type`, `Version`, `Value` and `Items` fields.
+///
+/// Writes `WATCH` the key, check `Version` and then replace the hash in a `MULTI`/`EXEC`
+/// transaction, which Redis aborts if another client changed the key in the meantime.
+pub struct RedisStore {
+    pub conn: Mutex<Connection>,
+    /// Put in front of every ID to make its key, e.g. `monotone:`, to keep the hashes apart from other keys.
+    pub key_prefix: String,
+}
+
+impl RedisStore {
+    /// Connect using a URL such as `redis://127.0.0.1/`.
+    pub fn open<S>(url: &str, key_prefix: S) -> Result<RedisStore> where S: Into<String> {
+        let client = Client::open(url)?;
+
+        Ok(RedisStore::new(client.get_connection()?, key_prefix))
+    }
+
+    pub fn new<S>(conn: Connection, key_prefix: S) -> RedisStore where S: Into<String> {
+        RedisStore {
+            conn: Mutex::new(conn),
+            key_prefix: key_prefix.into(),
+        }
+    }
+
+    /// The key of the hash holding `id`'s row.
+    pub fn key(&self, id: &str) -> String {
+        key(&self.key_prefix, id)
+    }
+}
+
+/// The key of the hash holding `id`'s row under `key_prefix`.
+pub fn key(key_prefix: &str, id: &str) -> String {
+    format!("{}{}", key_prefix, id)
+}
+
+fn field<'a>(fields: &'a HashMap<String, String>, name: &str) -> Result<&'a String> {
+    fields.get(name).ok_or_else(|| ErrorKind::MissingField(name.to_owned()).into())
+}
+
+/// The row held in a hash's `fields`, or `None` if the hash is empty because there is no row.
+pub fn parse_row(fields: &HashMap<String, String>) -> Result<Option<Row>> {
+    if fields.is_empty() {
+        return Ok(None);
+    }
+
+    Ok(Some(Row {
+        typ: field(fields, "Type")?.clone(),
+        version: field(fields, "Version")?.parse()?,
+        value: field(fields, "Value")?.parse()?,
+        items: serde_json::from_str(field(fields, "Items")?)?,
+    }))
+}
+
+impl VersionedStore for RedisStore {
+    type Error = Error;
+
+    fn get(&self, id: &str) -> Result<Option<Row>> {
+        let mut conn = self.conn.lock().unwrap();
+
+        let fields: HashMap<String, String> = redis_client::cmd("HGETALL").arg(self.key(id)).query(&mut *conn)?;
+
+        parse_row(&fields)
+    }
+
+    fn put(&self, id: &str, row: &Row) -> Result<bool> {
+        let mut conn = self.conn.lock().unwrap();
+        let key = self.key(id);
+
+        redis_client::cmd("WATCH").arg(&key).query::<()>(&mut *conn)?;
+
+        let version: Option<u64> = redis_client::cmd("HGET").arg(&key).arg("Version").query(&mut *conn)?;
+
+        if version.unwrap_or(0) != row.version {
+            redis_client::cmd("UNWATCH").query::<()>(&mut *conn)?;
+
+            return Ok(false);
+        }
+
+        let items = serde_json::to_string(&row.items)?;
+
+        let result: Option<()> = redis_client::pipe()
+            .atomic()
+            .cmd("HMSET").arg(&key)
+                .arg("Type").arg(&row.typ)
+                .arg("Version").arg(row.version + 1)
+                .arg("Value").arg(row.value)
+                .arg("Items").arg(items)
+                .ignore()
+            .query(&mut *conn)?;
+
+        Ok(result.is_some())
+    }
+
+    fn delete(&self, id: &str) -> Result<()> {
+        let mut conn = self.conn.lock().unwrap();
+
+        redis_client::cmd("DEL").arg(self.key(id)).query::<()>(&mut *conn)?;
+
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::collections::HashMap;
+    use string::*;
+    use super::*;
+
+    fn fields(pairs: &[(&str, &str)]) -> HashMap<String, String> {
+        pairs.iter().map(|&(k, v)| (s(k), s(v))).collect()
+    }
+
+    fn queue_fields() -> HashMap<String, String> {
+        fields(&[("Type", "QUEUE"), ("Version", "3"), ("Value", "7"), ("Items", "[\"foo\",\"bar\"]")])
+    }
+
+    #[test]
+    pub fn test_redis_key() {
+        assert_eq!(key("monotone:", "foo"), "monotone:foo");
+        assert_eq!(key("", "foo"), "foo");
+    }
+
+    #[test]
+    pub fn test_redis_parse_row() {
+        assert_eq!(parse_row(&HashMap::new()).expect("parse"), None);
+        assert_eq!(parse_row(&queue_fields()).expect("parse"), Some(Row::new("QUEUE", 3, 7, vec![s("foo"), s("bar")])));
+    }
+
+    #[test]
+    pub fn test_redis_parse_row_missing_field() {
+        for name in &["Type", "Version", "Value", "Items"] {
+            let mut fields = queue_fields();
+            fields.remove(*name);
+
+            match parse_row(&fields) {
+                Err(Error(ErrorKind::MissingField(ref field), _)) if field == name => {},
+                r => panic!("expected MissingField({}), got {:?}", name, r),
+            }
+        }
+    }
+
+    #[test]
+    pub fn test_redis_parse_row_corrupt() {
+        let mut version = queue_fields();
+        version.insert(s("Version"), s("three"));
+
+        match parse_row(&version) {
+            Err(Error(ErrorKind::ParseError(_), _)) => {},
+            r => panic!("expected ParseError, got {:?}", r),
+        }
+
+        let mut items = queue_fields();
+        items.insert(s("Items"), s("[\"foo\""));
+
+        match parse_row(&items) {
+            Err(Error(ErrorKind::Json(_), _)) => {},
+            r => panic!("expected Json, got {:?}", r),
+        }
+    }
+}
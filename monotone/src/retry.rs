@@ -0,0 +1,167 @@
+//! How long to wait between attempts at an optimistic update, and when to give up.
+
+use std::cmp;
+use std::time::{Duration, Instant};
+use rand;
+use rand::distributions::{IndependentSample, Range};
+use time::*;
+
+/// How much randomness to add to each delay, so that competing writers don't retry in step.
+#[derive(Debug, Clone, PartialEq)]
+pub enum JitterStrategy {
+    /// Wait exactly the backoff delay.
+    None,
+    /// Add up to this many milliseconds to the backoff delay.
+    Bounded(u64),
+    /// Wait anywhere between nothing and the backoff delay.
+    Full,
+}
+
+/// Retry policy for conflicting writes.
+///
+/// The delay after the nth conflict is `initial_delay * multiplier^(n-1)`, capped at `max_delay`,
+/// with jitter applied. Retrying stops once `max_attempts` attempts have been made, or if the next
+/// attempt would start after `deadline` has passed since the first.
+#[derive(Debug, Clone, PartialEq)]
+pub struct RetryPolicy {
+    pub max_attempts: Option<u32>,
+    pub initial_delay: Duration,
+    pub multiplier: u32,
+    pub max_delay: Duration,
+    pub deadline: Option<Duration>,
+    pub jitter: JitterStrategy,
+}
+
+impl RetryPolicy {
+    /// Retry forever, waiting `retry_time` plus up to 100ms between attempts.
+    pub fn new(retry_time: Duration) -> RetryPolicy {
+        RetryPolicy {
+            max_attempts: None,
+            initial_delay: retry_time,
+            multiplier: 1,
+            max_delay: retry_time,
+            deadline: None,
+            jitter: JitterStrategy::Bounded(100),
+        }
+    }
+
+    /// Retry forever, doubling the delay from `initial_delay` up to `max_delay`, with full jitter.
+    pub fn exponential(initial_delay: Duration, max_delay: Duration) -> RetryPolicy {
+        RetryPolicy {
+            max_attempts: None,
+            initial_delay: initial_delay,
+            multiplier: 2,
+            max_delay: max_delay,
+            deadline: None,
+            jitter: JitterStrategy::Full,
+        }
+    }
+
+    /// The delay after `conflicts` conflicts in a row, before jitter.
+    pub fn backoff(&self, conflicts: u32) -> Duration {
+        let initial = duration_millis(self.initial_delay);
+        let factor = u64::from(self.multiplier).checked_pow(conflicts.saturating_sub(1)).unwrap_or(u64::MAX);
+        let millis = initial.saturating_mul(factor);
+
+        cmp::min(Duration::from_millis(millis), self.max_delay)
+    }
+
+    /// The delay after `conflicts` conflicts in a row, with jitter.
+    pub fn jittered_backoff(&self, conflicts: u32) -> Duration {
+        let backoff = self.backoff(conflicts);
+
+        match self.jitter {
+            JitterStrategy::None => backoff,
+            JitterStrategy::Bounded(millis) => backoff.jitter(millis),
+            JitterStrategy::Full => {
+                let mut rng = rand::thread_rng();
+                let range = Range::new(0, duration_millis(backoff) + 1);
+
+                Duration::from_millis(range.ind_sample(&mut rng))
+            },
+        }
+    }
+
+    /// Whether `conflicts` conflicts in a row use up `max_attempts`. If `next_delay` gives up
+    /// when this is false, it gave up because the deadline would pass.
+    pub fn out_of_attempts(&self, conflicts: u32) -> bool {
+        self.max_attempts.map(|max| conflicts >= max).unwrap_or(false)
+    }
+
+    /// How long to wait before trying again after `conflicts` conflicts in a row, the first
+    /// attempt having started at `started`. Returns `None` if it is time to give up.
+    pub fn next_delay(&self, conflicts: u32, started: Instant) -> Option<Duration> {
+        if self.out_of_attempts(conflicts) {
+            return None;
+        }
+
+        let delay = self.jittered_backoff(conflicts);
+
+        if let Some(deadline) = self.deadline {
+            if started.elapsed() + delay > deadline {
+                return None;
+            }
+        }
+
+        Some(delay)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::time::{Duration, Instant};
+    use super::*;
+
+    #[test]
+    pub fn test_fixed_backoff() {
+        let p = RetryPolicy::new(Duration::from_millis(100));
+
+        assert_eq!(p.backoff(1), Duration::from_millis(100));
+        assert_eq!(p.backoff(10), Duration::from_millis(100));
+
+        let delay = p.next_delay(1000, Instant::now()).expect("delay");
+        assert!(delay >= Duration::from_millis(100) && delay < Duration::from_millis(200));
+    }
+
+    #[test]
+    pub fn test_exponential_backoff() {
+        let p = RetryPolicy::exponential(Duration::from_millis(10), Duration::from_millis(1000));
+
+        assert_eq!(p.backoff(1), Duration::from_millis(10));
+        assert_eq!(p.backoff(2), Duration::from_millis(20));
+        assert_eq!(p.backoff(4), Duration::from_millis(80));
+        assert_eq!(p.backoff(8), Duration::from_millis(1000));
+        assert_eq!(p.backoff(100), Duration::from_millis(1000));
+
+        for _ in 0..100 {
+            assert!(p.jittered_backoff(3) <= Duration::from_millis(40));
+        }
+    }
+
+    #[test]
+    pub fn test_max_attempts() {
+        let p = RetryPolicy {
+            max_attempts: Some(3),
+            jitter: JitterStrategy::None,
+            ..RetryPolicy::new(Duration::from_millis(1))
+        };
+
+        assert_eq!(p.next_delay(1, Instant::now()), Some(Duration::from_millis(1)));
+        assert_eq!(p.next_delay(2, Instant::now()), Some(Duration::from_millis(1)));
+        assert_eq!(p.next_delay(3, Instant::now()), None);
+        assert!(!p.out_of_attempts(2));
+        assert!(p.out_of_attempts(3));
+    }
+
+    #[test]
+    pub fn test_deadline() {
+        let p = RetryPolicy {
+            deadline: Some(Duration::from_millis(1000)),
+            jitter: JitterStrategy::None,
+            ..RetryPolicy::new(Duration::from_millis(100))
+        };
+
+        assert_eq!(p.next_delay(1, Instant::now()), Some(Duration::from_millis(100)));
+        assert_eq!(p.next_delay(1, Instant::now() - Duration::from_millis(950)), None);
+    }
+}
@@ -0,0 +1,226 @@
+use std::result;
+use std::time::{Duration, Instant};
+
+use ::*;
+use ::error::*;
+use retry::*;
+
+/// Counting semaphore on top of a queue: the first `permits` tickets in the queue hold a permit.
+pub struct Semaphore<Q> where Q: MonotonicQueue {
+    pub queue: Q,
+    pub process_id: String,
+    pub permits: usize,
+    pub lease: Option<Duration>,
+    pub retry_time: Duration,
+    pub jitter_millis: u64,
+}
+
+pub struct Permit<'a, Q> where Q: 'a + MonotonicQueue {
+    semaphore: &'a Semaphore<Q>,
+    fencing_token: FencingToken,
+    ticket: Ticket,
+    held: bool,
+}
+
+impl <Q> Semaphore<Q> where Q: MonotonicQueue {
+    /// Fails with `NoPermits` if `permits` is 0, as no process could ever acquire a permit.
+    pub fn new<S, L>(queue: Q, process_id: S, permits: usize, lease: L, retry_time: Duration) -> Result<Semaphore<Q>> where S: Into<String>, L: Into<Option<Duration>> {
+        if permits == 0 {
+            bail!(ErrorKind::NoPermits);
+        }
+
+        Ok(Semaphore {
+            queue: queue,
+            process_id: process_id.into(),
+            permits: permits,
+            lease: lease.into(),
+            retry_time: retry_time,
+            jitter_millis: 100,
+        })
+    }
+
+    /// Block until a permit is acquired.
+    pub fn acquire<'a>(&'a self) -> result::Result<Permit<'a, Q>, Q::Error> {
+        self.join_and_wait(None).map(|permit| permit.expect("acquire without deadline"))
+    }
+
+    /// Acquire a permit if one is free, without waiting.
+    pub fn try_acquire<'a>(&'a self) -> result::Result<Option<Permit<'a, Q>>, Q::Error> {
+        self.join_and_wait(Some(Duration::from_millis(0)))
+    }
+
+    /// Wait up to `timeout` for a permit, giving up our place in the queue if none is acquired.
+    pub fn acquire_for<'a>(&'a self, timeout: Duration) -> result::Result<Option<Permit<'a, Q>>, Q::Error> {
+        self.join_and_wait(Some(timeout))
+    }
+
+    /// Join the queue and wait with `MonotonicQueue::wait_for_position_with` until our ticket
+    /// holds a permit, polling every `retry_time` plus up to `jitter_millis`.
+    fn join_and_wait<'a>(&'a self, timeout: Option<Duration>) -> result::Result<Option<Permit<'a, Q>>, Q::Error> {
+        let joined = Instant::now();
+        let (ft, ticket) = self.queue.join_queue(self.process_id.clone(), None, self.lease)?;
+
+        let acquired = if ticket.position < self.permits {
+            Some((ft, ticket))
+        } else {
+            let poll = RetryPolicy {
+                jitter: JitterStrategy::Bounded(self.jitter_millis),
+                ..RetryPolicy::new(self.retry_time)
+            };
+
+            self.queue.wait_for_position_with(&self.process_id, self.permits - 1, self.lease, timeout, Some(joined), &poll)?
+        };
+
+        match acquired {
+            Some((ft, ticket)) => Ok(Some(Permit {
+                semaphore: self,
+                fencing_token: ft,
+                ticket: ticket,
+                held: true,
+            })),
+            None => {
+                self.queue.leave_queue(&self.process_id)?;
+
+                Ok(None)
+            },
+        }
+    }
+}
+
+impl <'a, Q> Permit<'a, Q> where Q: 'a + MonotonicQueue {
+    /// The fencing token at which the permit was acquired.
+    pub fn fencing_token(&self) -> FencingToken {
+        self.fencing_token
+    }
+
+    pub fn ticket(&self) -> &Ticket {
+        &self.ticket
+    }
+
+    /// Renew the lease on the permit, returning the new fencing token.
+    pub fn heartbeat(&mut self) -> result::Result<FencingToken, Q::Error> {
+        let (ft, ticket) = self.semaphore.queue.heartbeat(&self.semaphore.process_id)?;
+
+        self.fencing_token = ft;
+        self.ticket = ticket;
+
+        Ok(ft)
+    }
+
+    /// Release the permit, returning the fencing token of the release.
+    pub fn release(mut self) -> result::Result<FencingToken, Q::Error> {
+        self.held = false;
+
+        self.semaphore.queue.leave_queue(&self.semaphore.process_id)
+    }
+}
+
+impl <'a, Q> Drop for Permit<'a, Q> where Q: 'a + MonotonicQueue {
+    fn drop(&mut self) {
+        if self.held && self.semaphore.queue.leave_queue(&self.semaphore.process_id).is_err() {
+            warn!("error releasing permit for process {}", self.semaphore.process_id);
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::thread;
+    use std::time::Duration;
+    use local::queue::Queue;
+    use string::*;
+    use super::*;
+
+    fn retry_time() -> Duration {
+        Duration::from_millis(10)
+    }
+
+    #[test]
+    pub fn test_semaphore_permits() {
+        let q = Queue::new();
+        let foo = Semaphore::new(q.clone(), "foo", 2, None, retry_time()).expect("semaphore");
+        let bar = Semaphore::new(q.clone(), "bar", 2, None, retry_time()).expect("semaphore");
+        let baz = Semaphore::new(q.clone(), "baz", 2, None, retry_time()).expect("semaphore");
+
+        let foo_permit = foo.try_acquire().expect("try acquire").expect("acquired");
+        assert_eq!(foo_permit.fencing_token(), 1);
+        assert_eq!(foo_permit.ticket().position, 0);
+
+        let bar_permit = bar.try_acquire().expect("try acquire").expect("acquired");
+        assert_eq!(bar_permit.fencing_token(), 2);
+        assert_eq!(bar_permit.ticket().position, 1);
+
+        assert!(baz.try_acquire().expect("try acquire").is_none());
+
+        let ft = foo_permit.release().expect("release");
+        assert_eq!(ft, 5);
+
+        let baz_permit = baz.try_acquire().expect("try acquire").expect("acquired");
+        assert_eq!(baz_permit.fencing_token(), 6);
+        assert_eq!(baz_permit.ticket().position, 1);
+    }
+
+    #[test]
+    pub fn test_semaphore_acquire_for_timeout() {
+        let q = Queue::new();
+        let foo = Semaphore::new(q.clone(), "foo", 1, None, retry_time()).expect("semaphore");
+        let bar = Semaphore::new(q.clone(), "bar", 1, None, retry_time()).expect("semaphore");
+
+        let _permit = foo.acquire().expect("acquire");
+
+        assert!(bar.acquire_for(Duration::from_millis(50)).expect("acquire for").is_none());
+        assert!(q.get_ticket("bar").is_err());
+    }
+
+    #[test]
+    pub fn test_semaphore_renews_lease_while_waiting() {
+        let q = Queue::new();
+        let bar = Semaphore::new(q.clone(), "bar", 1, Duration::from_millis(200), retry_time()).expect("semaphore");
+        q.join_queue(s("foo"), None, None).expect("join");
+
+        let foo_queue = q.clone();
+        let releaser = thread::spawn(move || {
+            thread::sleep(Duration::from_millis(500));
+            foo_queue.leave_queue("foo").expect("leave")
+        });
+
+        // bar's ticket outlives its lease by being renewed every half lease, not on every poll.
+        let permit = bar.acquire_for(Duration::from_secs(5)).expect("acquire for").expect("acquired");
+        let released = releaser.join().expect("join");
+        assert!(permit.fencing_token() >= released);
+        assert!(released <= 9, "renewed too often: fencing token {}", released);
+    }
+
+    #[test]
+    pub fn test_semaphore_without_jitter() {
+        let q = Queue::new();
+        let foo = Semaphore::new(q.clone(), "foo", 1, None, retry_time()).expect("semaphore");
+        let mut bar = Semaphore::new(q.clone(), "bar", 1, None, retry_time()).expect("semaphore");
+        bar.jitter_millis = 0;
+
+        let _permit = foo.acquire().expect("acquire");
+
+        assert!(bar.acquire_for(Duration::from_millis(50)).expect("acquire for").is_none());
+    }
+
+    #[test]
+    pub fn test_semaphore_drop_releases() {
+        let q = Queue::new();
+        let foo = Semaphore::new(q.clone(), "foo", 1, None, retry_time()).expect("semaphore");
+
+        {
+            let _permit = foo.acquire().expect("acquire");
+            assert!(q.get_ticket("foo").is_ok());
+        }
+
+        assert!(q.get_ticket("foo").is_err());
+    }
+
+    #[test]
+    pub fn test_semaphore_no_permits() {
+        match Semaphore::new(Queue::new(), "foo", 0, None, retry_time()) {
+            Err(Error(ErrorKind::NoPermits, _)) => {},
+            r => panic!("expected NoPermits, got {:?}", r.map(|s| s.permits)),
+        }
+    }
+}
@@ -0,0 +1,67 @@
+use std::ops::Range;
+use std::path::PathBuf;
+use ::*;
+use super::protocol::*;
+use super::error::*;
+
+/// Client for a counter owned by a `socket::daemon::Daemon`.
+#[derive(Debug, Clone)]
+pub struct Counter {
+    pub path: PathBuf,
+    pub id: String,
+}
+
+impl Counter {
+    /// Counter `id` in the daemon listening at `path`.
+    pub fn new<P, S>(path: P, id: S) -> Counter where P: Into<PathBuf>, S: Into<String> {
+        Counter {
+            path: path.into(),
+            id: id.into(),
+        }
+    }
+
+    fn value(&self, request: Request) -> Result<u64> {
+        match call(&self.path, &request)? {
+            Response::Value(value) => Ok(value),
+            _ => bail!(ErrorKind::UnexpectedResponse),
+        }
+    }
+
+    fn values(&self, request: Request) -> Result<Range<u64>> {
+        match call(&self.path, &request)? {
+            Response::Values(start, end) => Ok(start..end),
+            _ => bail!(ErrorKind::UnexpectedResponse),
+        }
+    }
+
+    fn set(&self, request: Request) -> Result<bool> {
+        match call(&self.path, &request)? {
+            Response::Set(set) => Ok(set),
+            _ => bail!(ErrorKind::UnexpectedResponse),
+        }
+    }
+}
+
+impl MonotonicCounter for Counter {
+    type Error = ::error::Error;
+
+    fn get_value(&self) -> ::error::Result<u64> {
+        Ok(self.value(Request::GetValue { id: self.id.clone() })?)
+    }
+
+    fn next_value(&self) -> ::error::Result<u64> {
+        Ok(self.value(Request::NextValue { id: self.id.clone() })?)
+    }
+
+    fn next_values(&self, count: u64) -> ::error::Result<Range<u64>> {
+        Ok(self.values(Request::NextValues { id: self.id.clone(), count: count })?)
+    }
+
+    fn compare_and_set(&self, expected: u64, new: u64) -> ::error::Result<bool> {
+        Ok(self.set(Request::CompareAndSet { id: self.id.clone(), expected: expected, new: new })?)
+    }
+
+    fn advance_to(&self, min_value: u64) -> ::error::Result<u64> {
+        Ok(self.value(Request::AdvanceTo { id: self.id.clone(), min_value: min_value })?)
+    }
+}
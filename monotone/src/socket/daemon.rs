@@ -0,0 +1,232 @@
+use std::fs;
+use std::thread;
+use std::io::BufReader;
+use std::os::unix::net::{UnixListener, UnixStream};
+use std::path::Path;
+use std::sync::{Arc, Mutex};
+use std::time::Duration;
+use std::collections::HashMap;
+use ::*;
+use local;
+use super::protocol::*;
+use super::error::*;
+
+#[derive(Debug, Clone)]
+enum Entry {
+    Counter(local::counter::Counter),
+    Queue(local::queue::Queue),
+}
+
+/// Owns the counters and queues and answers requests from `socket` clients.
+///
+/// An ID is created on first use as whichever type the first request was for.
+/// Requests for the other type fail with `Conflict`.
+#[derive(Debug, Clone, Default)]
+pub struct Daemon {
+    entries: Arc<Mutex<HashMap<String, Entry>>>,
+}
+
+impl Daemon {
+    pub fn new() -> Daemon {
+        Daemon {
+            entries: Arc::new(Mutex::new(HashMap::new())),
+        }
+    }
+
+    /// Listen on a socket at `path`, serving each connection on its own thread. Never returns unless binding fails.
+    ///
+    /// A socket file left behind by a daemon that is no longer running is replaced.
+    pub fn listen<P>(&self, path: P) -> Result<()> where P: AsRef<Path> {
+        let path = path.as_ref();
+
+        if path.exists() && UnixStream::connect(path).is_err() {
+            info!("removing stale socket {}", path.display());
+            fs::remove_file(path)?;
+        }
+
+        let listener = UnixListener::bind(path)?;
+
+        self.serve(listener)
+    }
+
+    pub fn serve(&self, listener: UnixListener) -> Result<()> {
+        for stream in listener.incoming() {
+            match stream {
+                Ok(stream) => {
+                    let daemon = self.clone();
+
+                    thread::spawn(move || {
+                        if let Err(e) = daemon.serve_connection(stream) {
+                            warn!("error serving connection: {}", e);
+                        }
+                    });
+                },
+                Err(e) => {
+                    warn!("error accepting connection: {}", e);
+                }
+            }
+        }
+
+        Ok(())
+    }
+
+    /// Answer requests on `stream` until the client hangs up.
+    pub fn serve_connection(&self, stream: UnixStream) -> Result<()> {
+        let mut writer = stream.try_clone()?;
+        let mut reader = BufReader::new(stream);
+
+        while let Some(request) = read_message(&mut reader)? {
+            let response = self.handle(request);
+
+            write_message(&mut writer, &response)?;
+        }
+
+        Ok(())
+    }
+
+    pub fn handle(&self, request: Request) -> Response {
+        match self.try_handle(request) {
+            Ok(response) => response,
+            Err(Error(ErrorKind::Conflict(id), _)) => Response::Error(RemoteError::Conflict(id)),
+            Err(e) => Response::Error(RemoteError::Other(e.to_string())),
+        }
+    }
+
+    fn counter(&self, id: &str) -> Result<local::counter::Counter> {
+        let mut entries = self.entries.lock().unwrap();
+
+        match *entries.entry(id.to_owned()).or_insert_with(|| Entry::Counter(local::counter::Counter::new())) {
+            Entry::Counter(ref c) => Ok(c.clone()),
+            _ => bail!(ErrorKind::Conflict(id.to_owned())),
+        }
+    }
+
+    fn queue(&self, id: &str) -> Result<local::queue::Queue> {
+        let mut entries = self.entries.lock().unwrap();
+
+        match *entries.entry(id.to_owned()).or_insert_with(|| Entry::Queue(local::queue::Queue::new())) {
+            Entry::Queue(ref q) => Ok(q.clone()),
+            _ => bail!(ErrorKind::Conflict(id.to_owned())),
+        }
+    }
+
+    fn try_handle(&self, request: Request) -> Result<Response> {
+        let result = match request {
+            Request::GetValue { id } => self.counter(&id)?.get_value().map(Response::Value),
+            Request::NextValue { id } => self.counter(&id)?.next_value().map(Response::Value),
+            Request::NextValues { id, count } => self.counter(&id)?.next_values(count).map(|r| Response::Values(r.start, r.end)),
+            Request::CompareAndSet { id, expected, new } => self.counter(&id)?.compare_and_set(expected, new).map(Response::Set),
+            Request::AdvanceTo { id, min_value } => self.counter(&id)?.advance_to(min_value).map(Response::Value),
+            Request::JoinQueue { id, process_id, tags, lease_millis } => {
+                self.queue(&id)?.join_queue(process_id, tags, lease_millis.map(Duration::from_millis)).map(|(ft, t)| Response::Ticket(ft, t))
+            },
+            Request::LeaveQueue { id, process_id } => self.queue(&id)?.leave_queue(&process_id).map(Response::FencingToken),
+            Request::Heartbeat { id, process_id } => self.queue(&id)?.heartbeat(&process_id).map(|(ft, t)| Response::Ticket(ft, t)),
+            Request::GetTicket { id, process_id } => self.queue(&id)?.get_ticket(&process_id).map(|(ft, t)| Response::Ticket(ft, t)),
+            Request::GetTickets { id } => self.queue(&id)?.get_tickets().map(|(ft, ts)| Response::Tickets(ft, ts)),
+        };
+
+        Ok(match result {
+            Ok(response) => response,
+            Err(::error::Error(::error::ErrorKind::NotFound(process_id), _)) => Response::Error(RemoteError::NotFound(process_id)),
+            Err(::error::Error(::error::ErrorKind::WouldDecrease(from, to), _)) => Response::Error(RemoteError::WouldDecrease(from, to)),
+            Err(::error::Error(::error::ErrorKind::Overflow(value, count), _)) => Response::Error(RemoteError::Overflow(value, count)),
+            Err(e) => Response::Error(RemoteError::Other(e.to_string())),
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::env;
+    use std::fs;
+    use std::thread;
+    use std::path::PathBuf;
+    use std::time::Duration;
+    use rand;
+    use ::*;
+    use string::*;
+    use socket::counter::Counter;
+    use socket::queue::Queue;
+    use super::*;
+
+    fn start_daemon() -> PathBuf {
+        let path = env::temp_dir().join(format!("monotone-test-{}.sock", rand::random::<u64>()));
+        let listener = UnixListener::bind(&path).expect("bind");
+
+        thread::spawn(move || Daemon::new().serve(listener));
+
+        path
+    }
+
+    #[test]
+    pub fn test_socket_counter() {
+        let path = start_daemon();
+        let c = Counter::new(path.clone(), "foo");
+        let c2 = Counter::new(path.clone(), "foo");
+
+        assert_eq!(c.get_value().expect("get"), 0);
+        assert_eq!(c.next_value().expect("next"), 1);
+        assert_eq!(c2.next_values(3).expect("next values"), 2..5);
+        assert!(c2.compare_and_set(4, 10).expect("cas"));
+        assert!(!c.compare_and_set(4, 11).expect("cas"));
+        assert_eq!(c.advance_to(5).expect("advance"), 10);
+
+        match c.compare_and_set(10, 9) {
+            Err(::error::Error(::error::ErrorKind::WouldDecrease(10, 9), _)) => {},
+            r => panic!("expected WouldDecrease, got {:?}", r),
+        }
+
+        assert_eq!(c.advance_to(u64::MAX - 1).expect("advance"), u64::MAX - 1);
+
+        match c.next_value() {
+            Err(::error::Error(::error::ErrorKind::Overflow(_, 1), _)) => {},
+            r => panic!("expected Overflow, got {:?}", r),
+        }
+
+        fs::remove_file(&path).ok();
+    }
+
+    #[test]
+    pub fn test_socket_queue() {
+        let path = start_daemon();
+        let q = Queue::new(path.clone(), "foo");
+        let q2 = Queue::new(path.clone(), "foo");
+
+        let (ft, tok) = q.join_queue(s("foo"), None, Duration::from_secs(30)).expect("join");
+        assert_eq!(ft, 1);
+        assert_eq!(tok.position, 0);
+
+        let (_ft, tok) = q2.join_queue(s("bar"), None, None).expect("join");
+        assert_eq!(tok.position, 1);
+
+        q.leave_queue("foo").expect("leave");
+        let (ft, tickets) = q2.get_tickets().expect("get tickets");
+        assert_eq!(ft, 3);
+        assert_eq!(tickets.len(), 1);
+        assert_eq!(tickets[0].process_id, "bar");
+
+        match q.get_ticket("foo") {
+            Err(::error::Error(::error::ErrorKind::NotFound(ref p), _)) if p == "foo" => {},
+            r => panic!("expected NotFound, got {:?}", r),
+        }
+
+        match Counter::new(path.clone(), "foo").get_value() {
+            Err(::error::Error(::error::ErrorKind::WrongType, _)) => {},
+            r => panic!("expected WrongType, got {:?}", r),
+        }
+
+        fs::remove_file(&path).ok();
+    }
+
+    #[test]
+    pub fn test_socket_conformance() {
+        let path = start_daemon();
+        let id = || format!("foo-{}", rand::random::<u64>());
+
+        conformance::check_counter(|| Counter::new(path.clone(), id()));
+        conformance::check_queue(|| Queue::new(path.clone(), id()));
+
+        fs::remove_file(&path).ok();
+    }
+}
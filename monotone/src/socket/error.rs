@@ -0,0 +1,63 @@
+use std::io;
+use error_chain::State;
+use serde_json;
+
+error_chain! {
+    foreign_links {
+        Io(io::Error);
+        Json(serde_json::Error);
+    }
+
+    errors {
+        NotFound(process_id: String) {
+            description("ticket not found")
+            display("ticket not found for process_id {}", process_id)
+        }
+
+        WouldDecrease(from: u64, to: u64) {
+            description("counter would decrease")
+            display("counter would decrease from {} to {}", from, to)
+        }
+
+        Overflow(value: u64, count: u64) {
+            description("counter would overflow")
+            display("counter would overflow reserving {} values after {}", count, value)
+        }
+
+        Conflict(id: String) {
+            description("id is already in use by a different type")
+            display("id {} is already in use by a different type", id)
+        }
+
+        Remote(message: String) {
+            description("daemon error")
+            display("daemon error: {}", message)
+        }
+
+        UnexpectedResponse {
+            description("unexpected response from daemon")
+            display("unexpected response from daemon")
+        }
+
+        ConnectionClosed {
+            description("daemon closed the connection")
+            display("daemon closed the connection")
+        }
+    }
+}
+
+impl From<Error> for ::error::Error {
+    fn from(e: Error) -> ::error::Error {
+        let kind = match *e.kind() {
+            ErrorKind::NotFound(ref process_id) => ::error::ErrorKind::NotFound(process_id.clone()),
+            ErrorKind::WouldDecrease(from, to) => ::error::ErrorKind::WouldDecrease(from, to),
+            ErrorKind::Overflow(value, count) => ::error::ErrorKind::Overflow(value, count),
+            ErrorKind::Conflict(_) => ::error::ErrorKind::WrongType,
+            ErrorKind::Io(ref io) if io.kind() == io::ErrorKind::TimedOut || io.kind() == io::ErrorKind::WouldBlock => ::error::ErrorKind::Timeout,
+            ErrorKind::Json(_) | ErrorKind::UnexpectedResponse => ::error::ErrorKind::Corrupt,
+            _ => ::error::ErrorKind::Backend(e.iter().last().map(|c| c.to_string()).unwrap_or_default()),
+        };
+
+        ::error::Error(kind, State::new::<::error::Error>(Box::new(e)))
+    }
+}
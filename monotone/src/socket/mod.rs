@@ -0,0 +1,12 @@
+//! Counters and queues owned by a daemon listening on a Unix domain socket,
+//! for sharing the in-memory `local` counters and queues between processes on one host.
+//!
+//! `daemon::Daemon` keeps a `local::counter::Counter` or `local::queue::Queue` per ID.
+//! `counter::Counter` and `queue::Queue` are clients which send each call to the daemon
+//! as a line of JSON and read back a line of JSON in reply.
+
+pub mod counter;
+pub mod daemon;
+pub mod error;
+pub mod protocol;
+pub mod queue;
@@ -0,0 +1,93 @@
+use std::io::{BufRead, BufReader, Write};
+use std::os::unix::net::UnixStream;
+use std::path::Path;
+use std::collections::BTreeMap;
+use serde_json;
+use ::*;
+use super::error::*;
+
+/// A call to the daemon, sent as one line of JSON.
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
+pub enum Request {
+    GetValue { id: String },
+    NextValue { id: String },
+    NextValues { id: String, count: u64 },
+    CompareAndSet { id: String, expected: u64, new: u64 },
+    AdvanceTo { id: String, min_value: u64 },
+    JoinQueue { id: String, process_id: String, tags: BTreeMap<String, String>, lease_millis: Option<u64> },
+    LeaveQueue { id: String, process_id: String },
+    Heartbeat { id: String, process_id: String },
+    GetTicket { id: String, process_id: String },
+    GetTickets { id: String },
+}
+
+/// The daemon's reply to a `Request`, sent as one line of JSON.
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
+pub enum Response {
+    Value(u64),
+    Values(u64, u64),
+    Set(bool),
+    FencingToken(FencingToken),
+    Ticket(FencingToken, Ticket),
+    Tickets(FencingToken, Vec<Ticket>),
+    Error(RemoteError),
+}
+
+/// Errors the client turns back into the matching `ErrorKind`.
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
+pub enum RemoteError {
+    NotFound(String),
+    WouldDecrease(u64, u64),
+    Overflow(u64, u64),
+    Conflict(String),
+    Other(String),
+}
+
+impl From<RemoteError> for Error {
+    fn from(e: RemoteError) -> Error {
+        match e {
+            RemoteError::NotFound(process_id) => ErrorKind::NotFound(process_id).into(),
+            RemoteError::WouldDecrease(from, to) => ErrorKind::WouldDecrease(from, to).into(),
+            RemoteError::Overflow(value, count) => ErrorKind::Overflow(value, count).into(),
+            RemoteError::Conflict(id) => ErrorKind::Conflict(id).into(),
+            RemoteError::Other(message) => ErrorKind::Remote(message).into(),
+        }
+    }
+}
+
+/// Read one line of JSON, returning `None` at end of stream.
+pub fn read_message<R, T>(reader: &mut R) -> Result<Option<T>> where R: BufRead, T: serde::Deserialize {
+    let mut line = String::new();
+
+    if reader.read_line(&mut line)? == 0 {
+        return Ok(None);
+    }
+
+    Ok(Some(serde_json::from_str(&line)?))
+}
+
+/// Write `message` as one line of JSON.
+pub fn write_message<W, T>(writer: &mut W, message: &T) -> Result<()> where W: Write, T: serde::Serialize {
+    let mut line = serde_json::to_string(message)?;
+    line.push('\n');
+
+    writer.write_all(line.as_bytes())?;
+    writer.flush()?;
+
+    Ok(())
+}
+
+/// Connect to the daemon at `path`, send `request` and wait for the response.
+pub fn call(path: &Path, request: &Request) -> Result<Response> {
+    let mut stream = UnixStream::connect(path)?;
+
+    write_message(&mut stream, request)?;
+
+    let mut reader = BufReader::new(stream);
+
+    match read_message(&mut reader)? {
+        Some(Response::Error(e)) => Err(e.into()),
+        Some(response) => Ok(response),
+        None => bail!(ErrorKind::ConnectionClosed),
+    }
+}
@@ -0,0 +1,75 @@
+use std::path::PathBuf;
+use std::time::Duration;
+use std::collections::BTreeMap;
+use ::*;
+use time::*;
+use super::protocol::*;
+use super::error::*;
+
+/// Client for a queue owned by a `socket::daemon::Daemon`.
+#[derive(Debug, Clone)]
+pub struct Queue {
+    pub path: PathBuf,
+    pub id: String,
+}
+
+impl Queue {
+    /// Queue `id` in the daemon listening at `path`.
+    pub fn new<P, S>(path: P, id: S) -> Queue where P: Into<PathBuf>, S: Into<String> {
+        Queue {
+            path: path.into(),
+            id: id.into(),
+        }
+    }
+
+    fn ticket(&self, request: Request) -> Result<(FencingToken, Ticket)> {
+        match call(&self.path, &request)? {
+            Response::Ticket(fencing_token, ticket) => Ok((fencing_token, ticket)),
+            _ => bail!(ErrorKind::UnexpectedResponse),
+        }
+    }
+
+    fn fencing_token(&self, request: Request) -> Result<FencingToken> {
+        match call(&self.path, &request)? {
+            Response::FencingToken(fencing_token) => Ok(fencing_token),
+            _ => bail!(ErrorKind::UnexpectedResponse),
+        }
+    }
+
+    fn tickets(&self, request: Request) -> Result<(FencingToken, Vec<Ticket>)> {
+        match call(&self.path, &request)? {
+            Response::Tickets(fencing_token, tickets) => Ok((fencing_token, tickets)),
+            _ => bail!(ErrorKind::UnexpectedResponse),
+        }
+    }
+}
+
+impl MonotonicQueue for Queue {
+    type Error = ::error::Error;
+
+    fn join_queue<T, L>(&self, process_id: String, tags: T, lease: L) -> ::error::Result<(FencingToken, Ticket)>
+            where T: Into<Option<BTreeMap<String, String>>>, L: Into<Option<Duration>> {
+        Ok(self.ticket(Request::JoinQueue {
+            id: self.id.clone(),
+            process_id: process_id,
+            tags: tags.into().unwrap_or_default(),
+            lease_millis: lease.into().map(duration_millis),
+        })?)
+    }
+
+    fn leave_queue(&self, process_id: &str) -> ::error::Result<FencingToken> {
+        Ok(self.fencing_token(Request::LeaveQueue { id: self.id.clone(), process_id: process_id.to_owned() })?)
+    }
+
+    fn heartbeat(&self, process_id: &str) -> ::error::Result<(FencingToken, Ticket)> {
+        Ok(self.ticket(Request::Heartbeat { id: self.id.clone(), process_id: process_id.to_owned() })?)
+    }
+
+    fn get_ticket(&self, process_id: &str) -> ::error::Result<(FencingToken, Ticket)> {
+        Ok(self.ticket(Request::GetTicket { id: self.id.clone(), process_id: process_id.to_owned() })?)
+    }
+
+    fn get_tickets(&self) -> ::error::Result<(FencingToken, Vec<Ticket>)> {
+        Ok(self.tickets(Request::GetTickets { id: self.id.clone() })?)
+    }
+}
@@ -0,0 +1,7 @@
+use store;
+use super::store::SqliteStore;
+
+pub use store::counter::{CounterRow, COUNTER_TYPE};
+
+/// Counter kept in a SQLite table.
+pub type Counter = store::counter::Counter<SqliteStore>;
@@ -0,0 +1,9 @@
+use rusqlite;
+use serde_json;
+
+error_chain! {
+    foreign_links {
+        Sqlite(rusqlite::Error);
+        Json(serde_json::Error);
+    }
+}
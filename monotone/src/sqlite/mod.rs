@@ -0,0 +1,6 @@
+//! Counters and queues kept in a SQLite table, one row per ID like the DynamoDb table.
+
+pub mod counter;
+pub mod error;
+pub mod queue;
+pub mod store;
@@ -0,0 +1,7 @@
+use store;
+use super::store::SqliteStore;
+
+pub use store::queue::{QueuePosition, QueueRow, QUEUE_TYPE};
+
+/// Queue kept in a SQLite table.
+pub type Queue = store::queue::Queue<SqliteStore>;
@@ -0,0 +1,194 @@
+use std::sync::Mutex;
+use std::path::Path;
+use std::time::Duration;
+use rusqlite::{Connection, OptionalExtension};
+use rusqlite::types::ToSql;
+use serde_json;
+use store::*;
+use super::error::*;
+
+/// `VersionedStore` keeping each row in a SQLite table with `ID`, `Type`, `Version`, `Value`
+/// and `Items` columns. Writes only succeed if `Version` still has the value that was read.
+pub struct SqliteStore {
+    pub conn: Mutex<Connection>,
+    pub table_name: String,
+}
+
+impl SqliteStore {
+    /// Open or create the database at `path`, creating the table if needed.
+    pub fn open<P, S>(path: P, table_name: S) -> Result<SqliteStore> where P: AsRef<Path>, S: Into<String> {
+        let conn = Connection::open(path)?;
+        conn.busy_timeout(Duration::from_secs(5))?;
+
+        SqliteStore::new(conn, table_name)
+    }
+
+    pub fn new<S>(conn: Connection, table_name: S) -> Result<SqliteStore> where S: Into<String> {
+        let table_name = table_name.into();
+
+        create_table_if_needed(&conn, &table_name)?;
+
+        Ok(SqliteStore {
+            conn: Mutex::new(conn),
+            table_name: table_name,
+        })
+    }
+}
+
+fn quote(table_name: &str) -> String {
+    format!("\"{}\"", table_name.replace("\"", "\"\""))
+}
+
+pub fn create_table_if_needed(conn: &Connection, table_name: &str) -> Result<()> {
+    conn.execute_batch(&format!("CREATE TABLE IF NOT EXISTS {} (
+            ID TEXT PRIMARY KEY NOT NULL,
+            Type TEXT NOT NULL,
+            Version INTEGER NOT NULL,
+            Value INTEGER NOT NULL,
+            Items TEXT NOT NULL
+        )", quote(table_name)))?;
+
+    Ok(())
+}
+
+impl VersionedStore for SqliteStore {
+    type Error = Error;
+
+    fn get(&self, id: &str) -> Result<Option<Row>> {
+        let conn = self.conn.lock().unwrap();
+
+        let sql = format!("SELECT Type, Version, Value, Items FROM {} WHERE ID = ?1", quote(&self.table_name));
+
+        let maybe_row = conn.query_row(&sql, &[&id as &dyn ToSql], |r| {
+            Ok((r.get::<_, String>(0)?, r.get::<_, i64>(1)?, r.get::<_, i64>(2)?, r.get::<_, String>(3)?))
+        }).optional()?;
+
+        if let Some((typ, version, value, items)) = maybe_row {
+            Ok(Some(Row {
+                typ: typ,
+                version: version as u64,
+                value: value as u64,
+                items: serde_json::from_str(&items)?,
+            }))
+        } else {
+            Ok(None)
+        }
+    }
+
+    fn put(&self, id: &str, row: &Row) -> Result<bool> {
+        let conn = self.conn.lock().unwrap();
+
+        let items = serde_json::to_string(&row.items)?;
+        let version = row.version as i64;
+        let next_version = version + 1;
+        let value = row.value as i64;
+
+        let changed = if row.version == 0 {
+            let sql = format!("INSERT OR IGNORE INTO {} (ID, Type, Version, Value, Items) VALUES (?1, ?2, ?3, ?4, ?5)", quote(&self.table_name));
+
+            conn.execute(&sql, &[&id as &dyn ToSql, &row.typ, &next_version, &value, &items])?
+        } else {
+            let sql = format!("UPDATE {} SET Type = ?2, Version = ?3, Value = ?4, Items = ?5 WHERE ID = ?1 AND Version = ?6", quote(&self.table_name));
+
+            conn.execute(&sql, &[&id as &dyn ToSql, &row.typ, &next_version, &value, &items, &version])?
+        };
+
+        Ok(changed == 1)
+    }
+
+    fn delete(&self, id: &str) -> Result<()> {
+        let conn = self.conn.lock().unwrap();
+
+        let sql = format!("DELETE FROM {} WHERE ID = ?1", quote(&self.table_name));
+
+        conn.execute(&sql, &[&id as &dyn ToSql])?;
+
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::env;
+    use std::fs;
+    use std::thread;
+    use std::path::PathBuf;
+    use std::time::Duration;
+    use rand;
+    use ::*;
+    use string::*;
+    use store::*;
+    use sqlite::counter::Counter;
+    use sqlite::queue::Queue;
+    use super::*;
+
+    fn temp_path() -> PathBuf {
+        env::temp_dir().join(format!("monotone-test-{}.sqlite", rand::random::<u64>()))
+    }
+
+    #[test]
+    pub fn test_sqlite_store_put_if_version() {
+        let store = SqliteStore::new(Connection::open_in_memory().expect("open"), "Counters").expect("store");
+        assert_eq!(store.get("foo").expect("get"), None);
+
+        assert!(store.put("foo", &Row::new("COUNTER", 0, 1, vec![])).expect("put"));
+        assert!(!store.put("foo", &Row::new("COUNTER", 0, 2, vec![])).expect("put"));
+        assert!(!store.put("foo", &Row::new("COUNTER", 2, 2, vec![])).expect("put"));
+        assert_eq!(store.get("foo").expect("get"), Some(Row::new("COUNTER", 1, 1, vec![])));
+
+        assert!(store.put("foo", &Row::new("QUEUE", 1, 2, vec![s("a"), s("b")])).expect("put"));
+        assert_eq!(store.get("foo").expect("get"), Some(Row::new("QUEUE", 2, 2, vec![s("a"), s("b")])));
+
+        store.delete("foo").expect("delete");
+        assert_eq!(store.get("foo").expect("get"), None);
+    }
+
+    #[test]
+    pub fn test_sqlite_counter_shared_between_connections() {
+        let path = temp_path();
+
+        let handles: Vec<_> = (0..4).map(|_| {
+            let counter = Counter::new(SqliteStore::open(&path, "Counters").expect("store"), "foo", Duration::from_millis(1));
+
+            thread::spawn(move || {
+                (0..10).map(|_| counter.next_value().expect("next")).collect::<Vec<_>>()
+            })
+        }).collect();
+
+        let mut values: Vec<u64> = handles.into_iter().flat_map(|h| h.join().expect("join")).collect();
+        values.sort();
+
+        assert_eq!(values, (1..41).collect::<Vec<_>>());
+
+        fs::remove_file(&path).ok();
+    }
+
+    #[test]
+    pub fn test_sqlite_queue() {
+        let path = temp_path();
+        let q = Queue::new(SqliteStore::open(&path, "Counters").expect("store"), "foo", Duration::from_millis(1));
+        let q2 = Queue::new(SqliteStore::open(&path, "Counters").expect("store"), "foo", Duration::from_millis(1));
+
+        let (ft, tok) = q.join_queue(s("foo"), None, None).expect("join");
+        assert_eq!(ft, 1);
+        assert_eq!(tok.position, 0);
+
+        let (_ft, tok) = q2.join_queue(s("bar"), None, None).expect("join");
+        assert_eq!(tok.position, 1);
+
+        q.leave_queue("foo").expect("leave");
+        let (ft, tok) = q2.get_ticket("bar").expect("get");
+        assert_eq!(ft, 3);
+        assert_eq!(tok.position, 0);
+
+        fs::remove_file(&path).ok();
+    }
+
+    #[test]
+    pub fn test_sqlite_conformance() {
+        let store = || SqliteStore::new(Connection::open_in_memory().expect("open"), "Counters").expect("store");
+
+        conformance::check_counter(|| Counter::new(store(), "foo", Duration::from_millis(1)));
+        conformance::check_queue(|| Queue::new(store(), "foo", Duration::from_millis(1)));
+    }
+}
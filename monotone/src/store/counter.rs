@@ -0,0 +1,369 @@
+use std::sync::Arc;
+use std::ops::Range;
+use std::time::Duration;
+use ::*;
+use retry::*;
+use observer::*;
+use super::*;
+use super::error::*;
+
+pub const COUNTER_TYPE: &'static str = "COUNTER";
+
+/// Counter stored as a single row of a `VersionedStore`.
+pub struct Counter<S> where S: VersionedStore {
+    pub store: S,
+    pub id: String,
+    pub retry_policy: RetryPolicy,
+    /// Told about every attempt, conflict and retry. Ignores everything unless replaced.
+    pub observer: Arc<dyn Observer>,
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct CounterRow {
+    pub id: String,
+    pub version: u64,
+    pub value: u64,
+}
+
+impl CounterRow {
+    pub fn new(id: String) -> CounterRow {
+        CounterRow {
+            id: id,
+            version: 0,
+            value: 0,
+        }
+    }
+}
+
+impl <S> Counter<S> where S: VersionedStore {
+    /// Retry conflicting writes forever, waiting `retry_time` plus up to 100ms between attempts.
+    pub fn new<S1>(store: S, id: S1, retry_time: Duration) -> Counter<S> where S1: Into<String> {
+        Counter::with_retry_policy(store, id, RetryPolicy::new(retry_time))
+    }
+
+    pub fn with_retry_policy<S1>(store: S, id: S1, retry_policy: RetryPolicy) -> Counter<S> where S1: Into<String> {
+        Counter {
+            store: store,
+            id: id.into(),
+            retry_policy: retry_policy,
+            observer: Arc::new(NoObserver),
+        }
+    }
+
+    /// Remove the counter, leaving a tombstone so a counter recreated with
+    /// the same ID continues above the last issued value.
+    pub fn remove(&self) -> Result<()> {
+        self.retry("remove", || self.try_remove())
+    }
+
+    /// Delete the counter's row, tombstone and all. A counter recreated with the same ID starts from 0.
+    pub fn purge(&self) -> Result<()> {
+        self.store.delete(&self.id).chain_err(|| ErrorKind::Backend)
+    }
+
+    pub fn read(&self) -> Result<Option<CounterRow>> {
+        let row = self.store.get(&self.id).chain_err(|| ErrorKind::Backend)?;
+
+        if let Some(row) = row {
+            debug!("counter id={} : {:?}", self.id, row);
+
+            if row.typ != COUNTER_TYPE && row.typ != tombstone_type(COUNTER_TYPE) {
+                bail!(ErrorKind::UnrecognisedType(row.typ));
+            }
+
+            Ok(Some(CounterRow {
+                id: self.id.clone(),
+                version: row.version,
+                value: row.value,
+            }))
+        } else {
+            debug!("empty counter id={}", self.id);
+
+            Ok(None)
+        }
+    }
+
+    pub fn write(&self, counter: CounterRow) -> Result<()> {
+        let row = Row::new(COUNTER_TYPE, counter.version, counter.value, vec![]);
+
+        if !self.store.put(&self.id, &row).chain_err(|| ErrorKind::Backend)? {
+            bail!(ErrorKind::ConditionalUpdateFailed);
+        }
+
+        Ok(())
+    }
+
+    /// Run a single optimistic update attempt until it doesn't conflict with a concurrent write,
+    /// or the retry policy gives up, telling the observer about each step of `operation`.
+    pub fn retry<T, F>(&self, operation: &str, attempt: F) -> Result<T> where F: Fn() -> Result<T> {
+        retry(&self.id, &self.retry_policy, &*self.observer, operation, attempt)
+    }
+
+    /// Run a read once, telling the observer how `operation` went.
+    pub fn observe<T, F>(&self, operation: &str, read: F) -> Result<T> where F: FnOnce() -> Result<T> {
+        observe(&self.id, &*self.observer, operation, read)
+    }
+
+    pub fn try_remove(&self) -> Result<()> {
+        match self.read()? {
+            Some(counter) => write_tombstone(&self.store, &self.id, COUNTER_TYPE, counter.version, counter.value),
+            None => Ok(()),
+        }
+    }
+
+    pub fn try_compare_and_set(&self, expected: u64, new: u64) -> Result<bool> {
+        if new < expected {
+            bail!(ErrorKind::WouldDecrease(expected, new));
+        }
+
+        let mut counter = self.read()?.unwrap_or_else(|| CounterRow::new(self.id.clone()));
+
+        if counter.value != expected {
+            return Ok(false);
+        }
+
+        counter.value = new;
+
+        self.write(counter).map(|()| true)
+    }
+
+    pub fn try_advance_to(&self, min_value: u64) -> Result<u64> {
+        let mut counter = self.read()?.unwrap_or_else(|| CounterRow::new(self.id.clone()));
+
+        if counter.value >= min_value {
+            return Ok(counter.value);
+        }
+
+        counter.value = min_value;
+
+        self.write(counter).map(|()| min_value)
+    }
+
+    pub fn try_next_values(&self, count: u64) -> Result<Range<u64>> {
+        let maybe_counter = self.read()?;
+
+        let mut counter = maybe_counter.unwrap_or_else(|| {
+            debug!("no counter read. creating new..");
+            CounterRow::new(self.id.clone())
+        });
+
+        // The range is half open, so its end has to fit as well as the last value.
+        let end = counter.value.checked_add(count).and_then(|v| v.checked_add(1)).ok_or(ErrorKind::Overflow(counter.value, count))?;
+        let start = counter.value + 1;
+        counter.value = end - 1;
+
+        if count == 0 {
+            return Ok(start..end);
+        }
+
+        self.write(counter).map(|()| start..end)
+    }
+}
+
+impl <S> MonotonicCounter for Counter<S> where S: VersionedStore {
+    type Error = ::error::Error;
+
+    fn get_value(&self) -> ::error::Result<u64> {
+        let counter = self.observe("get_value", || self.read())?;
+
+        Ok(counter.map(|c| c.value).unwrap_or(0))
+    }
+
+    fn compare_and_set(&self, expected: u64, new: u64) -> ::error::Result<bool> {
+        Ok(self.retry("compare_and_set", || self.try_compare_and_set(expected, new))?)
+    }
+
+    fn advance_to(&self, min_value: u64) -> ::error::Result<u64> {
+        Ok(self.retry("advance_to", || self.try_advance_to(min_value))?)
+    }
+
+    fn next_value(&self) -> ::error::Result<u64> {
+        Ok(self.retry("next_value", || self.try_next_values(1)).map(|values| values.start)?)
+    }
+
+    fn next_values(&self, count: u64) -> ::error::Result<Range<u64>> {
+        Ok(self.retry("next_values", || self.try_next_values(count))?)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::sync::Arc;
+    use std::time::Duration;
+    use ::*;
+    use string::*;
+    use store::*;
+    use store::memory::*;
+    use super::*;
+
+    fn counter(store: MemoryStore) -> Counter<MemoryStore> {
+        Counter::new(store, "foo", Duration::from_millis(1))
+    }
+
+    #[test]
+    pub fn test_counter_next() {
+        let c = counter(MemoryStore::new());
+        assert_eq!(0, c.get_value().expect("get"));
+        assert_eq!(1, c.next_value().expect("next"));
+        assert_eq!(2..12, c.next_values(10).expect("next values"));
+        assert_eq!(11, c.get_value().expect("get"));
+    }
+
+    #[test]
+    pub fn test_counter_conflicting_write() {
+        let store = MemoryStore::new();
+        let c = counter(store.clone());
+        assert_eq!(1, c.next_value().expect("next"));
+
+        let row = c.read().expect("read").expect("row");
+        assert_eq!(2, counter(store).next_value().expect("next"));
+
+        assert!(c.write(row).is_err());
+        assert_eq!(3, c.next_value().expect("next"));
+    }
+
+    #[test]
+    pub fn test_counter_compare_and_set_and_advance() {
+        let c = counter(MemoryStore::new());
+        assert!(c.compare_and_set(0, 5000).expect("cas"));
+        assert!(!c.compare_and_set(0, 6000).expect("cas"));
+        assert!(c.compare_and_set(5000, 4000).is_err());
+        assert_eq!(5000, c.advance_to(10).expect("advance"));
+        assert_eq!(6000, c.advance_to(6000).expect("advance"));
+    }
+
+    #[test]
+    pub fn test_counter_remove_and_purge() {
+        let store = MemoryStore::new();
+        let c = counter(store.clone());
+        assert_eq!(1..11, c.next_values(10).expect("next values"));
+
+        c.remove().expect("remove");
+        assert_eq!(Some(s("TOMBSTONE:COUNTER")), store.get("foo").expect("get").map(|r| r.typ));
+        assert_eq!(11, c.next_value().expect("next"));
+
+        c.purge().expect("purge");
+        assert_eq!(1, c.next_value().expect("next"));
+    }
+
+    #[test]
+    pub fn test_counter_overflow() {
+        let c = counter(MemoryStore::new());
+        assert!(c.compare_and_set(0, u64::MAX - 2).expect("cas"));
+        assert_eq!(u64::MAX - 1, c.next_value().expect("next"));
+
+        match c.next_values(1) {
+            Err(::error::Error(::error::ErrorKind::Overflow(value, 1), _)) => assert_eq!(value, u64::MAX - 1),
+            r => panic!("expected Overflow, got {:?}", r),
+        }
+
+        assert_eq!(u64::MAX - 1, c.get_value().expect("get"));
+    }
+
+    #[test]
+    pub fn test_counter_wrong_type() {
+        let store = MemoryStore::new();
+        store.put("foo", &Row::new("QUEUE", 0, 1, vec![])).expect("put");
+
+        assert!(counter(store).next_value().is_err());
+    }
+
+    #[test]
+    pub fn test_counter_removed_queue() {
+        let store = MemoryStore::new();
+        let q = queue::Queue::new(store.clone(), "foo", Duration::from_millis(1));
+        q.join_queue(s("foo"), None, None).expect("join");
+        q.remove().expect("remove");
+
+        match counter(store).next_value() {
+            Err(::error::Error(::error::ErrorKind::WrongType, _)) => {},
+            r => panic!("expected WrongType, got {:?}", r),
+        }
+    }
+
+    /// Store where every write loses to a concurrent writer.
+    struct ConflictingStore;
+
+    impl VersionedStore for ConflictingStore {
+        type Error = Error;
+
+        fn get(&self, _id: &str) -> Result<Option<Row>> {
+            Ok(None)
+        }
+
+        fn put(&self, _id: &str, _row: &Row) -> Result<bool> {
+            Ok(false)
+        }
+
+        fn delete(&self, _id: &str) -> Result<()> {
+            Ok(())
+        }
+    }
+
+    #[test]
+    pub fn test_counter_retries_exhausted() {
+        let policy = RetryPolicy {
+            max_attempts: Some(3),
+            ..RetryPolicy::exponential(Duration::from_millis(1), Duration::from_millis(4))
+        };
+
+        match Counter::with_retry_policy(ConflictingStore, "foo", policy).next_value() {
+            Err(::error::Error(::error::ErrorKind::RetriesExhausted(3), _)) => {},
+            r => panic!("expected RetriesExhausted, got {:?}", r),
+        }
+
+        let policy = RetryPolicy {
+            deadline: Some(Duration::from_millis(50)),
+            ..RetryPolicy::new(Duration::from_millis(10))
+        };
+
+        let c = Counter::with_retry_policy(ConflictingStore, "foo", policy);
+
+        match c.retry("next_values", || c.try_next_values(1)) {
+            Err(Error(ErrorKind::DeadlineExceeded(n), _)) => assert!((1..=5).contains(&n)),
+            r => panic!("expected DeadlineExceeded, got {:?}", r),
+        }
+
+        match c.next_value() {
+            Err(::error::Error(::error::ErrorKind::Timeout, _)) => {},
+            r => panic!("expected Timeout, got {:?}", r),
+        }
+    }
+
+    #[test]
+    pub fn test_counter_observer() {
+        let metrics = Metrics::new();
+
+        let mut c = counter(MemoryStore::new());
+        c.observer = Arc::new(metrics.clone());
+
+        assert_eq!(1, c.next_value().expect("next"));
+        assert_eq!(1, c.get_value().expect("get"));
+
+        let foo = metrics.get("foo").expect("metrics");
+        assert_eq!((foo.attempts, foo.conflicts, foo.successes, foo.errors), (2, 0, 2, 0));
+        assert_eq!(foo.latency.count, 2);
+        assert_eq!(foo.operations.keys().collect::<Vec<_>>(), vec!["get_value", "next_value"]);
+
+        let policy = RetryPolicy {
+            max_attempts: Some(3),
+            jitter: JitterStrategy::None,
+            ..RetryPolicy::new(Duration::from_millis(1))
+        };
+
+        let mut c = Counter::with_retry_policy(ConflictingStore, "bar", policy);
+        c.observer = Arc::new(metrics.clone());
+
+        assert!(c.next_value().is_err());
+
+        let bar = metrics.get("bar").expect("metrics");
+        assert_eq!((bar.attempts, bar.conflicts, bar.backoffs, bar.successes, bar.errors), (3, 3, 2, 0, 1));
+        assert_eq!(bar.backoff_time, Duration::from_millis(2));
+        assert_eq!(metrics.snapshot().len(), 2);
+    }
+
+    #[test]
+    pub fn test_counter_conformance() {
+        conformance::check_counter(|| counter(MemoryStore::new()));
+    }
+}
@@ -0,0 +1,68 @@
+use error_chain::State;
+use serde_json;
+
+error_chain! {
+    foreign_links {
+        Json(serde_json::Error);
+    }
+
+    errors {
+        Backend {
+            description("storage backend error")
+            display("storage backend error")
+        }
+
+        ConditionalUpdateFailed {
+            description("conditional update failed")
+            display("conditional update failed")
+        }
+
+        RetriesExhausted(conflicts: u32) {
+            description("gave up retrying conflicting writes")
+            display("gave up after {} conflicting writes", conflicts)
+        }
+
+        DeadlineExceeded(conflicts: u32) {
+            description("retry deadline passed")
+            display("retry deadline passed after {} conflicting writes", conflicts)
+        }
+
+        UnrecognisedType(typ: String) {
+            description("unrecognised row type")
+            display("unrecognised row type: {}", typ)
+        }
+
+        TicketNotFound(process_id: String) {
+            description("ticket not found")
+            display("ticket not found for process_id {}", process_id)
+        }
+
+        WouldDecrease(from: u64, to: u64) {
+            description("counter would decrease")
+            display("counter would decrease from {} to {}", from, to)
+        }
+
+        Overflow(value: u64, count: u64) {
+            description("counter would overflow")
+            display("counter would overflow reserving {} values after {}", count, value)
+        }
+    }
+}
+
+impl From<Error> for ::error::Error {
+    fn from(e: Error) -> ::error::Error {
+        let kind = match *e.kind() {
+            ErrorKind::TicketNotFound(ref process_id) => ::error::ErrorKind::NotFound(process_id.clone()),
+            ErrorKind::WouldDecrease(from, to) => ::error::ErrorKind::WouldDecrease(from, to),
+            ErrorKind::Overflow(value, count) => ::error::ErrorKind::Overflow(value, count),
+            ErrorKind::ConditionalUpdateFailed => ::error::ErrorKind::Conflict,
+            ErrorKind::RetriesExhausted(conflicts) => ::error::ErrorKind::RetriesExhausted(conflicts),
+            ErrorKind::DeadlineExceeded(_) => ::error::ErrorKind::Timeout,
+            ErrorKind::UnrecognisedType(_) => ::error::ErrorKind::WrongType,
+            ErrorKind::Json(_) => ::error::ErrorKind::Corrupt,
+            _ => ::error::ErrorKind::Backend(e.iter().last().map(|c| c.to_string()).unwrap_or_default()),
+        };
+
+        ::error::Error(kind, State::new::<::error::Error>(Box::new(e)))
+    }
+}
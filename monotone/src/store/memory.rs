@@ -0,0 +1,75 @@
+use std::sync::{Arc, Mutex};
+use std::collections::HashMap;
+use super::*;
+use super::error::*;
+
+/// In-memory `VersionedStore`. Clones share the same rows.
+#[derive(Debug, Clone, Default)]
+pub struct MemoryStore {
+    rows: Arc<Mutex<HashMap<String, Row>>>,
+}
+
+impl MemoryStore {
+    pub fn new() -> MemoryStore {
+        MemoryStore {
+            rows: Arc::new(Mutex::new(HashMap::new()))
+        }
+    }
+}
+
+impl VersionedStore for MemoryStore {
+    type Error = Error;
+
+    fn get(&self, id: &str) -> Result<Option<Row>> {
+        let rows = self.rows.lock().unwrap();
+
+        Ok(rows.get(id).cloned())
+    }
+
+    fn put(&self, id: &str, row: &Row) -> Result<bool> {
+        let mut rows = self.rows.lock().unwrap();
+
+        let version = rows.get(id).map(|r| r.version).unwrap_or(0);
+
+        if version != row.version {
+            return Ok(false);
+        }
+
+        let mut row = row.clone();
+        row.version += 1;
+
+        rows.insert(id.to_owned(), row);
+
+        Ok(true)
+    }
+
+    fn delete(&self, id: &str) -> Result<()> {
+        let mut rows = self.rows.lock().unwrap();
+
+        rows.remove(id);
+
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use store::*;
+    use super::*;
+
+    #[test]
+    pub fn test_memory_store_put_if_version() {
+        let store = MemoryStore::new();
+        assert_eq!(store.get("foo").expect("get"), None);
+
+        assert!(store.put("foo", &Row::new("COUNTER", 0, 1, vec![])).expect("put"));
+        assert!(!store.put("foo", &Row::new("COUNTER", 0, 2, vec![])).expect("put"));
+        assert_eq!(store.get("foo").expect("get"), Some(Row::new("COUNTER", 1, 1, vec![])));
+
+        assert!(store.put("foo", &Row::new("COUNTER", 1, 2, vec![])).expect("put"));
+        assert_eq!(store.get("foo").expect("get"), Some(Row::new("COUNTER", 2, 2, vec![])));
+
+        store.delete("foo").expect("delete");
+        assert_eq!(store.get("foo").expect("get"), None);
+    }
+}
@@ -0,0 +1,159 @@
+//! Counters and queues built on any storage that can do an optimistic conditional write.
+//!
+//! A backend implements `VersionedStore`; `counter::Counter` and `queue::Queue` supply the
+//! read / mutate / conditional write / retry logic on top of it.
+
+use std::error::Error as StdError;
+use std::result;
+use std::thread;
+use std::sync::Arc;
+use std::time::Instant;
+use retry::RetryPolicy;
+use observer::Observer;
+use self::error::ResultExt;
+
+pub mod counter;
+pub mod error;
+pub mod memory;
+#[cfg(feature = "async")]
+pub mod nonblocking;
+pub mod queue;
+
+/// Prefix of the type of a row left behind when a counter or queue is removed.
+///
+/// The tombstone keeps the row's version and value, so a counter or queue
+/// later created with the same ID continues above the last issued value and fencing token.
+pub const TOMBSTONE_PREFIX: &str = "TOMBSTONE:";
+
+/// Type of the tombstone of a row of type `typ`, e.g. `TOMBSTONE:COUNTER`. Only a counter or
+/// queue of the removed row's type may be recreated from it.
+pub fn tombstone_type(typ: &str) -> String {
+    format!("{}{}", TOMBSTONE_PREFIX, typ)
+}
+
+/// Replace the row of type `typ` stored under `id` at `version` with its tombstone, keeping `value`.
+/// Fails with `ConditionalUpdateFailed` if the row has moved on since `version`.
+pub fn write_tombstone<S>(store: &S, id: &str, typ: &str, version: u64, value: u64) -> error::Result<()> where S: VersionedStore {
+    let row = Row::new(tombstone_type(typ), version, value, vec![]);
+
+    if !store.put(id, &row).chain_err(|| error::ErrorKind::Backend)? {
+        bail!(error::ErrorKind::ConditionalUpdateFailed);
+    }
+
+    Ok(())
+}
+
+/// The error to fail with when `policy` gives up after `conflicts` conflicts: `RetriesExhausted`
+/// if they used up its attempts, `DeadlineExceeded` if waiting any longer would pass its deadline.
+pub fn gave_up(policy: &RetryPolicy, conflicts: u32) -> error::Error {
+    if policy.out_of_attempts(conflicts) {
+        error::ErrorKind::RetriesExhausted(conflicts).into()
+    } else {
+        error::ErrorKind::DeadlineExceeded(conflicts).into()
+    }
+}
+
+/// Run a single optimistic update attempt until it doesn't conflict with a concurrent write,
+/// or `policy` gives up, telling `observer` about each step of `operation` on `id`.
+pub fn retry<T, F>(id: &str, policy: &RetryPolicy, observer: &dyn Observer, operation: &str, attempt: F) -> error::Result<T> where F: Fn() -> error::Result<T> {
+    let started = Instant::now();
+    let mut conflicts = 0;
+
+    loop {
+        observer.attempt(id, operation);
+
+        let result = match attempt() {
+            Err(error::Error(error::ErrorKind::ConditionalUpdateFailed, _)) => {
+                conflicts += 1;
+                observer.conflict(id, operation, conflicts);
+
+                match policy.next_delay(conflicts, started) {
+                    Some(delay) => {
+                        // try again
+                        info!("transient error updating {}", id);
+                        observer.backoff(id, operation, delay);
+                        thread::sleep(delay);
+                        continue;
+                    },
+                    None => Err(gave_up(policy, conflicts)),
+                }
+            },
+            result => result,
+        };
+
+        match result {
+            Ok(_) => observer.success(id, operation, started.elapsed()),
+            Err(ref e) => observer.error(id, operation, started.elapsed(), e),
+        }
+
+        return result;
+    }
+}
+
+/// Run a read once, without retrying, telling `observer` about the attempt and how `operation` on `id` went.
+pub fn observe<T, F>(id: &str, observer: &dyn Observer, operation: &str, read: F) -> error::Result<T> where F: FnOnce() -> error::Result<T> {
+    let started = Instant::now();
+
+    observer.attempt(id, operation);
+
+    let result = read();
+
+    match result {
+        Ok(_) => observer.success(id, operation, started.elapsed()),
+        Err(ref e) => observer.error(id, operation, started.elapsed(), e),
+    }
+
+    result
+}
+
+/// A stored counter or queue.
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
+pub struct Row {
+    pub typ: String,
+    pub version: u64,
+    pub value: u64,
+    pub items: Vec<String>,
+}
+
+impl Row {
+    pub fn new<S>(typ: S, version: u64, value: u64, items: Vec<String>) -> Row where S: Into<String> {
+        Row {
+            typ: typ.into(),
+            version: version,
+            value: value,
+            items: items,
+        }
+    }
+}
+
+pub trait VersionedStore {
+    type Error: StdError + Send + 'static;
+
+    /// Read the row stored under `id`, if any.
+    fn get(&self, id: &str) -> result::Result<Option<Row>, Self::Error>;
+
+    /// Store `row` under `id` as version `row.version + 1`, but only if the stored row
+    /// is still at `row.version`, with version 0 meaning there is no stored row.
+    /// Returns false without writing if the version has moved on.
+    fn put(&self, id: &str, row: &Row) -> result::Result<bool, Self::Error>;
+
+    /// Delete the row stored under `id`, if any.
+    fn delete(&self, id: &str) -> result::Result<(), Self::Error>;
+}
+
+/// Shares one store between several counters and queues, e.g. across server threads.
+impl <S> VersionedStore for Arc<S> where S: VersionedStore {
+    type Error = S::Error;
+
+    fn get(&self, id: &str) -> result::Result<Option<Row>, Self::Error> {
+        (**self).get(id)
+    }
+
+    fn put(&self, id: &str, row: &Row) -> result::Result<bool, Self::Error> {
+        (**self).put(id, row)
+    }
+
+    fn delete(&self, id: &str) -> result::Result<(), Self::Error> {
+        (**self).delete(id)
+    }
+}
@@ -0,0 +1,211 @@
+use std::sync::Arc;
+use std::ops::Range;
+use std::time::{Duration, Instant};
+use std::collections::BTreeMap;
+use futures::{future, Future};
+use futures_cpupool::CpuPool;
+use tokio_timer::Timer;
+use ::*;
+use observer::*;
+use nonblocking;
+use nonblocking::*;
+use super::*;
+use super::counter::Counter;
+use super::queue::Queue;
+use super::error::*;
+
+fn is_conflict(e: &Error) -> bool {
+    matches!(*e.kind(), ErrorKind::ConditionalUpdateFailed)
+}
+
+fn observe_conflict(observer: &dyn Observer, id: &str, operation: &str, conflicts: u32, delay: Option<Duration>) {
+    observer.conflict(id, operation, conflicts);
+
+    if let Some(delay) = delay {
+        observer.backoff(id, operation, delay);
+    }
+}
+
+fn observe_result<T>(observer: &dyn Observer, id: &str, operation: &str, started: Instant, result: &Result<T>) {
+    match *result {
+        Ok(_) => observer.success(id, operation, started.elapsed()),
+        Err(ref e) => observer.error(id, operation, started.elapsed(), e),
+    }
+}
+
+/// Run `attempt` on `pool` until it doesn't conflict with a concurrent write, waiting on `timer`
+/// between attempts, the way `store::retry` does on the calling thread.
+fn retry<T, F>(id: String, policy: RetryPolicy, observer: Arc<dyn Observer>, pool: CpuPool, timer: Timer, operation: &'static str, attempt: F) -> BoxFuture<T, ::error::Error>
+        where T: Send + 'static, F: Fn() -> Result<T> + Send + Sync + 'static {
+    let conflicted = observer.clone();
+    let conflicted_id = id.clone();
+    let attempt = Arc::new(attempt);
+
+    let on_conflict = move |conflicts, delay| {
+        observe_conflict(&*conflicted, &conflicted_id, operation, conflicts, delay);
+    };
+
+    Box::new(future::lazy(move || {
+        let started = Instant::now();
+        let attempted = observer.clone();
+        let attempted_id = id.clone();
+
+        nonblocking::retry(timer, policy, is_conflict, gave_up, on_conflict, move || {
+            let attempt = attempt.clone();
+
+            attempted.attempt(&attempted_id, operation);
+
+            pool.spawn_fn(move || attempt())
+        }).then(move |result| {
+            observe_result(&*observer, &id, operation, started, &result);
+
+            result
+        })
+    }).map_err(Into::into))
+}
+
+/// Async view of a store-backed `Counter`.
+///
+/// Each request to the store runs on `pool`; conflicting writes are retried after the delay
+/// from the counter's `RetryPolicy` on `timer`, so no thread is put to sleep waiting to retry.
+pub struct AsyncCounter<S> where S: VersionedStore {
+    pub counter: Arc<Counter<S>>,
+    pub pool: CpuPool,
+    pub timer: Timer,
+}
+
+impl <S> AsyncCounter<S> where S: VersionedStore + Send + Sync + 'static {
+    pub fn new(counter: Counter<S>, pool: CpuPool, timer: Timer) -> AsyncCounter<S> {
+        AsyncCounter {
+            counter: Arc::new(counter),
+            pool: pool,
+            timer: timer,
+        }
+    }
+
+    fn retry<T, F>(&self, operation: &'static str, attempt: F) -> BoxFuture<T, ::error::Error> where T: Send + 'static, F: Fn(&Counter<S>) -> Result<T> + Send + Sync + 'static {
+        let counter = self.counter.clone();
+
+        retry(self.counter.id.clone(), self.counter.retry_policy.clone(), self.counter.observer.clone(), self.pool.clone(), self.timer.clone(), operation, move || attempt(&counter))
+    }
+}
+
+impl <S> AsyncMonotonicCounter for AsyncCounter<S> where S: VersionedStore + Send + Sync + 'static {
+    type Error = ::error::Error;
+
+    fn get_value(&self) -> BoxFuture<u64, ::error::Error> {
+        let counter = self.counter.clone();
+
+        Box::new(self.pool.spawn_fn(move || counter.get_value()))
+    }
+
+    fn next_value(&self) -> BoxFuture<u64, ::error::Error> {
+        Box::new(self.retry("next_value", |c| c.try_next_values(1)).map(|r| r.start))
+    }
+
+    fn next_values(&self, count: u64) -> BoxFuture<Range<u64>, ::error::Error> {
+        self.retry("next_values", move |c| c.try_next_values(count))
+    }
+
+    fn compare_and_set(&self, expected: u64, new: u64) -> BoxFuture<bool, ::error::Error> {
+        self.retry("compare_and_set", move |c| c.try_compare_and_set(expected, new))
+    }
+
+    fn advance_to(&self, min_value: u64) -> BoxFuture<u64, ::error::Error> {
+        self.retry("advance_to", move |c| c.try_advance_to(min_value))
+    }
+}
+
+/// Async view of a store-backed `Queue`, run the same way as `AsyncCounter`.
+pub struct AsyncQueue<S> where S: VersionedStore {
+    pub queue: Arc<Queue<S>>,
+    pub pool: CpuPool,
+    pub timer: Timer,
+}
+
+impl <S> AsyncQueue<S> where S: VersionedStore + Send + Sync + 'static {
+    pub fn new(queue: Queue<S>, pool: CpuPool, timer: Timer) -> AsyncQueue<S> {
+        AsyncQueue {
+            queue: Arc::new(queue),
+            pool: pool,
+            timer: timer,
+        }
+    }
+
+    fn retry<T, F>(&self, operation: &'static str, attempt: F) -> BoxFuture<T, ::error::Error> where T: Send + 'static, F: Fn(&Queue<S>) -> Result<T> + Send + Sync + 'static {
+        let queue = self.queue.clone();
+
+        retry(self.queue.id.clone(), self.queue.retry_policy.clone(), self.queue.observer.clone(), self.pool.clone(), self.timer.clone(), operation, move || attempt(&queue))
+    }
+}
+
+impl <S> AsyncMonotonicQueue for AsyncQueue<S> where S: VersionedStore + Send + Sync + 'static {
+    type Error = ::error::Error;
+
+    fn join_queue<T, L>(&self, process_id: String, tags: T, lease: L) -> BoxFuture<(FencingToken, Ticket), ::error::Error>
+            where T: Into<Option<BTreeMap<String, String>>>, L: Into<Option<Duration>> {
+        let tags = tags.into().unwrap_or(BTreeMap::new());
+        let lease = lease.into();
+
+        self.retry("join_queue", move |q| q.try_join_queue(&process_id, &tags, lease))
+    }
+
+    fn leave_queue(&self, process_id: &str) -> BoxFuture<FencingToken, ::error::Error> {
+        let process_id = process_id.to_owned();
+
+        self.retry("leave_queue", move |q| q.try_leave_queue(&process_id))
+    }
+
+    fn heartbeat(&self, process_id: &str) -> BoxFuture<(FencingToken, Ticket), ::error::Error> {
+        let process_id = process_id.to_owned();
+
+        self.retry("heartbeat", move |q| q.try_heartbeat(&process_id))
+    }
+
+    fn get_ticket(&self, process_id: &str) -> BoxFuture<(FencingToken, Ticket), ::error::Error> {
+        let queue = self.queue.clone();
+        let process_id = process_id.to_owned();
+
+        Box::new(self.pool.spawn_fn(move || queue.get_ticket(&process_id)))
+    }
+
+    fn get_tickets(&self) -> BoxFuture<(FencingToken, Vec<Ticket>), ::error::Error> {
+        let queue = self.queue.clone();
+
+        Box::new(self.pool.spawn_fn(move || queue.get_tickets()))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::sync::Arc;
+    use std::time::Duration;
+    use futures::Future;
+    use futures::future::join_all;
+    use futures_cpupool::CpuPool;
+    use tokio_timer::Timer;
+    use nonblocking::*;
+    use store::memory::*;
+    use super::*;
+
+    #[test]
+    pub fn test_async_counter_concurrent_next() {
+        let metrics = Metrics::new();
+        let mut counter = Counter::new(MemoryStore::new(), "foo", Duration::from_millis(1));
+        counter.observer = Arc::new(metrics.clone());
+
+        let c = AsyncCounter::new(counter, CpuPool::new(4), Timer::default());
+
+        let mut values = join_all((0..20).map(|_| c.next_value()).collect::<Vec<_>>()).wait().expect("next");
+        values.sort();
+
+        assert_eq!(values, (1..21).collect::<Vec<_>>());
+        assert_eq!(20, c.get_value().wait().expect("get"));
+
+        let foo = metrics.get("foo").expect("metrics");
+        assert_eq!(foo.successes, 21);
+        assert_eq!(foo.errors, 0);
+        assert_eq!(foo.attempts, foo.successes + foo.conflicts);
+        assert_eq!(foo.operations.get("next_value"), Some(&20));
+    }
+}
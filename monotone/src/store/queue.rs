@@ -0,0 +1,688 @@
+use std::cmp;
+use std::thread;
+use std::sync::Arc;
+use std::time::{Duration, Instant};
+use std::collections::BTreeMap;
+use serde_json;
+use ::*;
+use string::*;
+use time::*;
+use retry::*;
+use observer::*;
+use watch::*;
+use super::*;
+use super::error::*;
+
+pub const QUEUE_TYPE: &'static str = "QUEUE";
+
+/// Longest a `Watch` waits between reads of a queue that isn't changing.
+pub const MAX_WATCH_INTERVAL_MILLIS: u64 = 5000;
+
+/// Queue stored as a single row of a `VersionedStore`, one serialised `QueuePosition` per item.
+pub struct Queue<S> where S: VersionedStore {
+    pub store: S,
+    pub id: String,
+    pub retry_policy: RetryPolicy,
+    /// Told about every attempt, conflict and retry. Ignores everything unless replaced.
+    pub observer: Arc<dyn Observer>,
+}
+
+#[derive(Serialize, Deserialize, Clone, Debug, PartialEq)]
+pub struct QueuePosition {
+    process_id: String,
+    counter: u64,
+    tags: Option<BTreeMap<String, String>>,
+    lease: Option<u64>,
+    expires: Option<u64>,
+}
+
+impl QueuePosition {
+    pub fn new(process_id: String, counter: u64, tags: BTreeMap<String, String>, lease: Option<Duration>) -> QueuePosition {
+        let lease = lease.map(duration_millis);
+
+        QueuePosition {
+            process_id: process_id,
+            counter: counter,
+            tags: Some(tags),
+            lease: lease,
+            expires: lease.map(|l| now_millis() + l),
+        }
+    }
+
+    pub fn is_expired(&self, now: u64) -> bool {
+        self.expires.map(|e| e <= now).unwrap_or(false)
+    }
+
+    pub fn renew(&mut self) {
+        self.expires = self.lease.map(|l| now_millis() + l);
+    }
+
+    pub fn to_ticket(&self, position: usize) -> Ticket {
+        Ticket::new(self.process_id.clone(), self.counter, position, self.tags.clone().unwrap_or_default())
+    }
+
+    pub fn from_vec(strs: &[String]) -> Result<Vec<QueuePosition>> {
+        let mut result = vec![];
+
+        for s in strs {
+            result.push(Self::from_str(s)?)
+        }
+
+        result.sort_by(|a,b| a.counter.cmp(&b.counter));
+
+        Ok(result)
+    }
+
+    pub fn to_string(&self) -> Result<String> {
+        serde_json::to_string(self).map_err(Error::from)
+    }
+
+    pub fn to_string_vec(positions: &[QueuePosition]) -> Result<Vec<String>> {
+        let mut result = vec![];
+
+        for p in positions {
+            let s = p.to_string()?;
+            result.push(s);
+        }
+
+        Ok(result)
+    }
+
+    pub fn from_str(s: &str) -> Result<QueuePosition> {
+        serde_json::from_str(s).map_err(Error::from)
+    }
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct QueueRow {
+    pub id: String,
+    pub version: u64,
+    pub value: u64,
+    pub items: Vec<QueuePosition>,
+}
+
+impl QueueRow {
+    pub fn new(id: String) -> QueueRow {
+        QueueRow {
+            id: id,
+            version: 0,
+            value: 0,
+            items: vec![],
+        }
+    }
+
+    /// Remove expired tickets, returning whether there were any.
+    pub fn prune(&mut self) -> bool {
+        let now = now_millis();
+        let before = self.items.len();
+
+        self.items.retain(|p| !p.is_expired(now));
+
+        self.items.len() != before
+    }
+}
+
+impl <S> Queue<S> where S: VersionedStore {
+    /// Retry conflicting writes forever, waiting `retry_time` plus up to 100ms between attempts.
+    pub fn new<S1>(store: S, id: S1, retry_time: Duration) -> Queue<S> where S1: Into<String> {
+        Queue::with_retry_policy(store, id, RetryPolicy::new(retry_time))
+    }
+
+    pub fn with_retry_policy<S1>(store: S, id: S1, retry_policy: RetryPolicy) -> Queue<S> where S1: Into<String> {
+        Queue {
+            store: store,
+            id: id.into(),
+            retry_policy: retry_policy,
+            observer: Arc::new(NoObserver),
+        }
+    }
+
+    /// Remove the queue and its tickets, leaving a tombstone so a queue recreated with
+    /// the same ID continues above the last issued ticket counter and fencing token.
+    pub fn remove(&self) -> Result<()> {
+        self.retry("remove", || self.try_remove())
+    }
+
+    /// Delete the queue's row, tombstone and all. A queue recreated with the same ID starts from 0.
+    pub fn purge(&self) -> Result<()> {
+        self.store.delete(&self.id).chain_err(|| ErrorKind::Backend)
+    }
+
+    pub fn read(&self) -> Result<Option<QueueRow>> {
+        let row = self.store.get(&self.id).chain_err(|| ErrorKind::Backend)?;
+
+        if let Some(row) = row {
+            debug!("queue id={} : {:?}", self.id, row);
+
+            if row.typ != QUEUE_TYPE && row.typ != tombstone_type(QUEUE_TYPE) {
+                bail!(ErrorKind::UnrecognisedType(row.typ));
+            }
+
+            Ok(Some(QueueRow {
+                id: self.id.clone(),
+                version: row.version,
+                value: row.value,
+                items: QueuePosition::from_vec(&row.items)?,
+            }))
+        } else {
+            debug!("empty queue id={}", self.id);
+
+            Ok(None)
+        }
+    }
+
+    /// Read the queue without its expired tickets. If any had expired, the queue is written
+    /// back without them first, so the tickets behind never move forward under a fencing token
+    /// the expired holders were given.
+    pub fn read_live(&self) -> Result<Option<QueueRow>> {
+        match self.read()? {
+            Some(mut queue) => {
+                if queue.prune() {
+                    queue.version = self.write(queue.clone())?;
+                }
+
+                Ok(Some(queue))
+            },
+            None => Ok(None),
+        }
+    }
+
+    /// `read_live` for a read-only `operation`. The queue is read once; only writing back
+    /// expired tickets is retried under the retry policy, reported as a `prune` operation.
+    fn read_live_for(&self, operation: &str) -> Result<Option<QueueRow>> {
+        self.observe(operation, || {
+            match self.read()? {
+                Some(mut queue) => {
+                    if queue.prune() {
+                        return self.retry("prune", || self.read_live());
+                    }
+
+                    Ok(Some(queue))
+                },
+                None => Ok(None),
+            }
+        })
+    }
+
+    pub fn write(&self, queue: QueueRow) -> Result<u64> {
+        let row = Row::new(QUEUE_TYPE, queue.version, queue.value, QueuePosition::to_string_vec(&queue.items)?);
+
+        if !self.store.put(&self.id, &row).chain_err(|| ErrorKind::Backend)? {
+            bail!(ErrorKind::ConditionalUpdateFailed);
+        }
+
+        Ok(queue.version + 1)
+    }
+
+    /// Run a single optimistic update attempt until it doesn't conflict with a concurrent write,
+    /// or the retry policy gives up, telling the observer about each step of `operation`.
+    pub fn retry<T, F>(&self, operation: &str, attempt: F) -> Result<T> where F: Fn() -> Result<T> {
+        retry(&self.id, &self.retry_policy, &*self.observer, operation, attempt)
+    }
+
+    /// Run a read once, telling the observer how `operation` went.
+    pub fn observe<T, F>(&self, operation: &str, read: F) -> Result<T> where F: FnOnce() -> Result<T> {
+        observe(&self.id, &*self.observer, operation, read)
+    }
+
+    /// Read the live tickets and when their leases run out.
+    pub fn snapshot(&self) -> Result<Snapshot> {
+        if let Some(queue) = self.read_live_for("snapshot")? {
+            let now = Instant::now();
+            let now_ms = now_millis();
+
+            // now_ms is up to a millisecond behind now, so round down to be sure the expiry
+            // has passed by the time the ticket is pruned. Live tickets expire after now_ms.
+            let expiry = |e: u64| now + Duration::from_millis(e.saturating_sub(now_ms + 1));
+
+            Ok(Snapshot::new(queue.version, queue.items
+                .iter()
+                .enumerate()
+                .map(|(position,t)| WatchedTicket::new(t.to_ticket(position), t.expires.map(&expiry)))
+                .collect()))
+        } else {
+            Ok(Snapshot::new(0, vec![]))
+        }
+    }
+
+    pub fn try_remove(&self) -> Result<()> {
+        match self.read()? {
+            Some(queue) => write_tombstone(&self.store, &self.id, QUEUE_TYPE, queue.version, queue.value),
+            None => Ok(()),
+        }
+    }
+
+    pub fn try_join_queue(&self, process_id: &str, tags: &BTreeMap<String, String>, lease: Option<Duration>) -> Result<(u64, Ticket)> {
+        let maybe_queue = self.read_live()?;
+
+        let mut queue = maybe_queue.unwrap_or_else(|| {
+            debug!("no queue read. creating new..");
+            QueueRow::new(self.id.clone())
+        });
+
+        if let Some(ticket) = queue.items
+                .iter()
+                .enumerate()
+                .find(|&(_pos, t)| t.process_id == process_id)
+                .map(|(position,t)| t.to_ticket(position)) {
+
+            return Ok((queue.version, ticket))
+        }
+
+        queue.value += 1;
+        let position = queue.items.len();
+        let counter = queue.value;
+        let ticket = QueuePosition::new(s(process_id), counter, tags.clone(), lease);
+
+        queue.items.push(ticket);
+
+        let version = self.write(queue)?;
+
+        Ok((version, Ticket::new(s(process_id), counter, position, tags.clone())))
+    }
+
+    pub fn try_leave_queue(&self, process_id: &str) -> Result<u64> {
+        let mut queue = self.read_live()?.ok_or_else(|| ErrorKind::TicketNotFound(s(process_id)))?;
+
+        if let Some(pos) = queue.items.iter().position(|t| t.process_id == process_id) {
+            queue.items.remove(pos);
+        } else {
+            bail!(ErrorKind::TicketNotFound(s(process_id)));
+        }
+
+        self.write(queue)
+    }
+
+    pub fn try_heartbeat(&self, process_id: &str) -> Result<(u64, Ticket)> {
+        let mut queue = self.read_live()?.ok_or_else(|| ErrorKind::TicketNotFound(s(process_id)))?;
+
+        let pos = queue.items.iter().position(|t| t.process_id == process_id).ok_or_else(|| ErrorKind::TicketNotFound(s(process_id)))?;
+
+        if queue.items[pos].lease.is_none() {
+            return Ok((queue.version, queue.items[pos].to_ticket(pos)));
+        }
+
+        queue.items[pos].renew();
+        let ticket = queue.items[pos].to_ticket(pos);
+
+        let version = self.write(queue)?;
+
+        Ok((version, ticket))
+    }
+}
+
+/// Watch on a `Queue`, polling the store.
+///
+/// Polls `min_interval` apart after a change, doubling the interval up to `max_interval`
+/// while the fencing token stays the same, and wakes early when the next lease runs out.
+pub struct Watch<'a, S> where S: 'a + VersionedStore {
+    pub queue: &'a Queue<S>,
+    pub min_interval: Duration,
+    pub max_interval: Duration,
+    interval: Option<Duration>,
+    watcher: Watcher,
+}
+
+impl <'a, S> Watch<'a, S> where S: VersionedStore {
+    pub fn new(queue: &'a Queue<S>, min_interval: Duration, max_interval: Duration) -> Watch<'a, S> {
+        Watch {
+            queue: queue,
+            min_interval: min_interval,
+            max_interval: max_interval,
+            interval: None,
+            watcher: Watcher::new(),
+        }
+    }
+
+    fn wait(&self, interval: Duration) {
+        let mut wait = interval;
+
+        if let Some(expires) = self.watcher.next_expiry() {
+            let now = Instant::now();
+            wait = cmp::min(wait, if expires > now { expires - now } else { Duration::from_millis(0) });
+        }
+
+        thread::sleep(wait);
+    }
+}
+
+impl <'a, S> Iterator for Watch<'a, S> where S: VersionedStore {
+    type Item = ::error::Result<QueueEvent>;
+
+    fn next(&mut self) -> Option<::error::Result<QueueEvent>> {
+        loop {
+            if let Some(event) = self.watcher.pop() {
+                return Some(Ok(event));
+            }
+
+            if let Some(interval) = self.interval {
+                self.wait(interval);
+            }
+
+            let snapshot = match self.queue.snapshot() {
+                Ok(snapshot) => snapshot,
+                Err(e) => {
+                    self.interval = Some(self.max_interval);
+
+                    return Some(Err(e.into()));
+                }
+            };
+
+            self.interval = if self.watcher.update(snapshot) {
+                Some(self.min_interval)
+            } else {
+                self.interval.map(|i| cmp::min(i * 2, self.max_interval))
+            };
+        }
+    }
+}
+
+impl <S> WatchableQueue for Queue<S> where S: VersionedStore {
+    fn watch<'a>(&'a self) -> Box<dyn Iterator<Item=::error::Result<QueueEvent>> + 'a> {
+        Box::new(Watch::new(self, self.retry_policy.initial_delay, Duration::from_millis(MAX_WATCH_INTERVAL_MILLIS)))
+    }
+}
+
+impl <S> MonotonicQueue for Queue<S> where S: VersionedStore {
+    type Error = ::error::Error;
+
+    fn join_queue<T, L>(&self, process_id: String, tags: T, lease: L) -> ::error::Result<(u64, Ticket)>
+            where T: Into<Option<BTreeMap<String, String>>>, L: Into<Option<Duration>> {
+        let tags = tags.into().unwrap_or(BTreeMap::new());
+        let lease = lease.into();
+
+        Ok(self.retry("join_queue", || self.try_join_queue(&process_id, &tags, lease))?)
+    }
+
+    fn leave_queue(&self, process_id: &str) -> ::error::Result<u64> {
+        Ok(self.retry("leave_queue", || self.try_leave_queue(process_id))?)
+    }
+
+    fn heartbeat(&self, process_id: &str) -> ::error::Result<(u64, Ticket)> {
+        Ok(self.retry("heartbeat", || self.try_heartbeat(process_id))?)
+    }
+
+    fn get_ticket(&self, process_id: &str) -> ::error::Result<(u64, Ticket)> {
+        if let Some(queue) = self.read_live_for("get_ticket")? {
+            queue.items
+                .iter()
+                .enumerate()
+                .find(|&(_pos, t)| t.process_id == process_id)
+                .map(|(position,t)| (queue.version, t.to_ticket(position)))
+                .ok_or_else(|| ::error::ErrorKind::NotFound(process_id.to_owned()).into())
+
+        } else {
+            bail!(::error::ErrorKind::NotFound(process_id.to_owned()));
+        }
+    }
+
+    fn get_tickets(&self) -> ::error::Result<(u64, Vec<Ticket>)> {
+        if let Some(queue) = self.read_live_for("get_tickets")? {
+            Ok((queue.version, queue.items
+                .iter()
+                .enumerate()
+                .map(|(position,t)| t.to_ticket(position))
+                .collect()))
+        } else {
+            Ok((0, vec![]))
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::thread;
+    use std::sync::mpsc;
+    use std::sync::atomic::{AtomicUsize, Ordering};
+    use std::time::Duration;
+    use ::*;
+    use string::*;
+    use store::memory::*;
+    use super::*;
+
+    fn queue(store: MemoryStore) -> Queue<MemoryStore> {
+        Queue::new(store, "foo", Duration::from_millis(1))
+    }
+
+    #[test]
+    pub fn test_queue_join_leave() {
+        let q = queue(MemoryStore::new());
+        assert!(q.get_ticket("foo").is_err());
+        assert!(q.leave_queue("foo").is_err());
+
+        let (ft, tok) = q.join_queue(s("foo"), None, None).expect("join");
+        assert_eq!(ft, 1);
+        assert_eq!(tok.counter, 1);
+        assert_eq!(tok.position, 0);
+
+        let (ft, tok2) = q.join_queue(s("foo"), None, None).expect("join");
+        assert_eq!(ft, 1);
+        assert_eq!(tok2, tok);
+
+        let (ft, tok) = q.join_queue(s("bar"), None, None).expect("join");
+        assert_eq!(ft, 2);
+        assert_eq!(tok.counter, 2);
+        assert_eq!(tok.position, 1);
+
+        assert_eq!(q.leave_queue("foo").expect("leave"), 3);
+
+        let (ft, toks) = q.get_tickets().expect("get all");
+        assert_eq!(ft, 3);
+        assert_eq!(toks, vec![Ticket::new(s("bar"), 2, 0, BTreeMap::new())]);
+    }
+
+    #[test]
+    pub fn test_queue_leave_missing() {
+        let metrics = Metrics::new();
+        let mut q = queue(MemoryStore::new());
+        q.observer = Arc::new(metrics.clone());
+
+        // No row at all: fails straight away rather than retrying until one appears.
+        match q.leave_queue("foo") {
+            Err(::error::Error(::error::ErrorKind::NotFound(ref id), _)) if id == "foo" => {},
+            r => panic!("expected NotFound, got {:?}", r),
+        }
+        assert!(q.read().expect("read").is_none());
+        assert_eq!(metrics.get("foo").expect("metrics").attempts, 1);
+
+        q.join_queue(s("foo"), None, None).expect("join");
+        q.remove().expect("remove");
+
+        match q.leave_queue("foo") {
+            Err(::error::Error(::error::ErrorKind::NotFound(ref id), _)) if id == "foo" => {},
+            r => panic!("expected NotFound, got {:?}", r),
+        }
+    }
+
+    #[test]
+    pub fn test_queue_conflicting_write() {
+        let store = MemoryStore::new();
+        let q = queue(store.clone());
+        q.join_queue(s("foo"), None, None).expect("join");
+
+        let row = q.read().expect("read").expect("row");
+        queue(store).join_queue(s("bar"), None, None).expect("join");
+
+        assert!(q.write(row).is_err());
+
+        let (_ft, tok) = q.join_queue(s("baz"), None, None).expect("join");
+        assert_eq!(tok.position, 2);
+    }
+
+    #[test]
+    pub fn test_queue_lease_expires() {
+        let q = queue(MemoryStore::new());
+        q.join_queue(s("foo"), None, Duration::from_millis(50)).expect("join");
+        q.join_queue(s("bar"), None, None).expect("join");
+
+        thread::sleep(Duration::from_millis(100));
+
+        assert!(q.heartbeat("foo").is_err());
+
+        // foo's expiry was written back, taking a fencing token, before bar is reported at the head.
+        let (ft, tok) = q.get_ticket("bar").expect("get");
+        assert_eq!(ft, 3);
+        assert_eq!(tok.position, 0);
+    }
+
+    /// Store where the first `races` writes lose to a concurrent writer that leaves the row as it was.
+    struct RacingStore {
+        inner: MemoryStore,
+        races: AtomicUsize,
+    }
+
+    impl RacingStore {
+        fn new(inner: MemoryStore, races: usize) -> RacingStore {
+            RacingStore {
+                inner: inner,
+                races: AtomicUsize::new(races),
+            }
+        }
+    }
+
+    impl VersionedStore for RacingStore {
+        type Error = Error;
+
+        fn get(&self, id: &str) -> Result<Option<Row>> {
+            self.inner.get(id)
+        }
+
+        fn put(&self, id: &str, row: &Row) -> Result<bool> {
+            if self.races.load(Ordering::SeqCst) > 0 {
+                self.races.fetch_sub(1, Ordering::SeqCst);
+
+                if let Some(current) = self.inner.get(id)? {
+                    self.inner.put(id, &current)?;
+                }
+            }
+
+            self.inner.put(id, row)
+        }
+
+        fn delete(&self, id: &str) -> Result<()> {
+            self.inner.delete(id)
+        }
+    }
+
+    /// A store holding queue `foo` with an expired ticket ahead of `bar`, at version 2.
+    fn expired_store() -> MemoryStore {
+        let store = MemoryStore::new();
+        queue(store.clone()).join_queue(s("foo"), None, Duration::from_millis(50)).expect("join");
+        queue(store.clone()).join_queue(s("bar"), None, None).expect("join");
+
+        thread::sleep(Duration::from_millis(100));
+
+        store
+    }
+
+    #[test]
+    pub fn test_queue_read_observed_once() {
+        let metrics = Metrics::new();
+        let mut q = queue(MemoryStore::new());
+        q.observer = Arc::new(metrics.clone());
+
+        q.join_queue(s("foo"), None, None).expect("join");
+        q.get_ticket("foo").expect("get");
+        q.get_tickets().expect("get all");
+
+        let foo = metrics.get("foo").expect("metrics");
+        assert_eq!((foo.attempts, foo.conflicts, foo.successes, foo.errors), (3, 0, 3, 0));
+    }
+
+    #[test]
+    pub fn test_queue_read_prune_conflict() {
+        let metrics = Metrics::new();
+        let mut q = Queue::new(RacingStore::new(expired_store(), 1), "foo", Duration::from_millis(1));
+        q.observer = Arc::new(metrics.clone());
+
+        // Writing back foo's expiry conflicts, so it is retried and pruned at version 3.
+        let (ft, tok) = q.get_ticket("bar").expect("get");
+        assert_eq!(ft, 4);
+        assert_eq!(tok.position, 0);
+
+        let foo = metrics.get("foo").expect("metrics");
+        assert_eq!((foo.attempts, foo.conflicts, foo.backoffs, foo.successes, foo.errors), (3, 1, 1, 2, 0));
+        assert_eq!(foo.operations.get("get_ticket"), Some(&1));
+        assert_eq!(foo.operations.get("prune"), Some(&1));
+    }
+
+    #[test]
+    pub fn test_queue_read_prune_retries_exhausted() {
+        let policy = RetryPolicy {
+            max_attempts: Some(3),
+            ..RetryPolicy::new(Duration::from_millis(1))
+        };
+
+        let q = Queue::with_retry_policy(RacingStore::new(expired_store(), usize::MAX), "foo", policy);
+
+        // The write-back keeps conflicting, so the read gives up rather than spinning.
+        match q.get_tickets() {
+            Err(::error::Error(::error::ErrorKind::RetriesExhausted(3), _)) => {},
+            r => panic!("expected RetriesExhausted, got {:?}", r),
+        }
+    }
+
+    #[test]
+    pub fn test_queue_remove_continues() {
+        let q = queue(MemoryStore::new());
+        q.join_queue(s("foo"), None, None).expect("join");
+
+        q.remove().expect("remove");
+        assert_eq!(q.get_tickets().expect("get all"), (2, vec![]));
+
+        let (ft, tok) = q.join_queue(s("foo"), None, None).expect("join");
+        assert_eq!(ft, 3);
+        assert_eq!(tok.counter, 2);
+    }
+
+    #[test]
+    pub fn test_queue_removed_counter() {
+        let store = MemoryStore::new();
+        let c = counter::Counter::new(store.clone(), "foo", Duration::from_millis(1));
+        c.next_value().expect("next");
+        c.remove().expect("remove");
+
+        match queue(store).join_queue(s("foo"), None, None) {
+            Err(::error::Error(::error::ErrorKind::WrongType, _)) => {},
+            r => panic!("expected WrongType, got {:?}", r),
+        }
+    }
+
+    #[test]
+    pub fn test_queue_watch() {
+        let store = MemoryStore::new();
+        let q = queue(store.clone());
+        q.join_queue(s("foo"), None, None).expect("join");
+
+        let (tx, rx) = mpsc::channel();
+
+        thread::spawn(move || {
+            let q2 = queue(store);
+
+            for event in Watch::new(&q2, Duration::from_millis(1), Duration::from_millis(20)) {
+                if tx.send(event.expect("event")).is_err() {
+                    break;
+                }
+            }
+        });
+
+        let foo = Ticket::new(s("foo"), 1, 0, BTreeMap::new());
+        assert_eq!(rx.recv().expect("recv"), QueueEvent::Joined { fencing_token: 1, ticket: foo.clone() });
+
+        let (_ft, bar) = q.join_queue(s("bar"), None, Duration::from_millis(100)).expect("join");
+        assert_eq!(rx.recv().expect("recv"), QueueEvent::Joined { fencing_token: 2, ticket: bar.clone() });
+
+        q.leave_queue("foo").expect("leave");
+        assert_eq!(rx.recv().expect("recv"), QueueEvent::Left { fencing_token: 3, ticket: foo });
+
+        let (_ft, bar) = q.get_ticket("bar").expect("get");
+        assert_eq!(rx.recv().expect("recv"), QueueEvent::PositionChanged { fencing_token: 3, ticket: bar.clone(), previous_position: 1 });
+
+        assert_eq!(rx.recv().expect("recv"), QueueEvent::Expired { fencing_token: 4, ticket: bar });
+    }
+
+    #[test]
+    pub fn test_queue_conformance() {
+        conformance::check_queue(|| queue(MemoryStore::new()));
+    }
+}
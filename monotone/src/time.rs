@@ -1,19 +1,37 @@
-
-use std::time::Duration;
+use std::time::{Duration, SystemTime, UNIX_EPOCH};
 use std::ops::Add;
 use rand;
 use rand::distributions::{IndependentSample, Range};
 
 pub trait Jitter {
+    /// This duration plus up to `millis` milliseconds, or exactly this duration if `millis` is 0.
     fn jitter(&self, millis: u64) -> Duration;
 }
 
 impl Jitter for Duration {
     fn jitter(&self, millis: u64) -> Duration {
+        if millis == 0 {
+            return *self;
+        }
+
         let mut rng = rand::thread_rng();
         let range = Range::new(0, millis);
         let rand = range.ind_sample(&mut rng);
 
         self.add(Duration::from_millis(rand))
     }
-}
\ No newline at end of file
+}
+
+pub fn duration_millis(duration: Duration) -> u64 {
+    duration.as_secs() * 1000 + u64::from(duration.subsec_millis())
+}
+
+pub fn duration_micros(duration: Duration) -> u64 {
+    duration.as_secs() * 1_000_000 + u64::from(duration.subsec_micros())
+}
+
+pub fn now_millis() -> u64 {
+    let since_epoch = SystemTime::now().duration_since(UNIX_EPOCH).unwrap_or(Duration::from_secs(0));
+
+    duration_millis(since_epoch)
+}
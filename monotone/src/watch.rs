@@ -0,0 +1,224 @@
+//! Notification of changes to the tickets in a queue.
+//!
+//! A watch compares successive snapshots of the queue whenever its fencing token changes
+//! or a ticket's lease runs out, and reports the differences as `QueueEvent`s.
+
+use std::result;
+use std::time::Instant;
+use std::collections::{BTreeMap, VecDeque};
+use ::*;
+
+/// A change to the tickets in a queue, with the fencing token of the queue after the change.
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
+pub enum QueueEvent {
+    /// The process joined the queue.
+    Joined { fencing_token: FencingToken, ticket: Ticket },
+    /// The process left the queue. The ticket is as it was last seen.
+    Left { fencing_token: FencingToken, ticket: Ticket },
+    /// The process moved up the queue because processes in front of it left or expired.
+    PositionChanged { fencing_token: FencingToken, ticket: Ticket, previous_position: usize },
+    /// The process's lease ran out without a heartbeat. The ticket is as it was last seen.
+    Expired { fencing_token: FencingToken, ticket: Ticket },
+}
+
+/// A live ticket along with the time its lease runs out, if it has one.
+#[derive(Debug, Clone, PartialEq)]
+pub struct WatchedTicket {
+    pub ticket: Ticket,
+    pub expires: Option<Instant>,
+}
+
+impl WatchedTicket {
+    pub fn new(ticket: Ticket, expires: Option<Instant>) -> WatchedTicket {
+        WatchedTicket {
+            ticket: ticket,
+            expires: expires,
+        }
+    }
+}
+
+/// The live tickets in a queue at one fencing token.
+#[derive(Debug, Clone, PartialEq)]
+pub struct Snapshot {
+    pub fencing_token: FencingToken,
+    pub tickets: Vec<WatchedTicket>,
+}
+
+impl Snapshot {
+    pub fn new(fencing_token: FencingToken, tickets: Vec<WatchedTicket>) -> Snapshot {
+        Snapshot {
+            fencing_token: fencing_token,
+            tickets: tickets,
+        }
+    }
+
+    /// The earliest time a lease in the snapshot runs out.
+    pub fn next_expiry(&self) -> Option<Instant> {
+        self.tickets.iter().filter_map(|t| t.expires).min()
+    }
+}
+
+/// Events turning `old` into `new`: departures first, then moves, then arrivals, each in queue order.
+///
+/// A ticket missing from `new` whose lease had run out by `now` is reported as `Expired`, otherwise as `Left`.
+/// A process that left and rejoined between the snapshots gets a new counter and is reported as leaving and joining.
+pub fn diff(old: &Snapshot, new: &Snapshot, now: Instant) -> Vec<QueueEvent> {
+    let fencing_token = new.fencing_token;
+
+    let old_tickets: BTreeMap<(&str, u64), &WatchedTicket> = old.tickets.iter()
+        .map(|t| ((t.ticket.process_id.as_str(), t.ticket.counter), t))
+        .collect();
+
+    let new_tickets: BTreeMap<(&str, u64), &WatchedTicket> = new.tickets.iter()
+        .map(|t| ((t.ticket.process_id.as_str(), t.ticket.counter), t))
+        .collect();
+
+    let mut events = vec![];
+
+    for t in &old.tickets {
+        if !new_tickets.contains_key(&(t.ticket.process_id.as_str(), t.ticket.counter)) {
+            let ticket = t.ticket.clone();
+
+            if t.expires.map(|e| e <= now).unwrap_or(false) {
+                events.push(QueueEvent::Expired { fencing_token: fencing_token, ticket: ticket });
+            } else {
+                events.push(QueueEvent::Left { fencing_token: fencing_token, ticket: ticket });
+            }
+        }
+    }
+
+    for t in &new.tickets {
+        if let Some(previous) = old_tickets.get(&(t.ticket.process_id.as_str(), t.ticket.counter)) {
+            if previous.ticket.position != t.ticket.position {
+                events.push(QueueEvent::PositionChanged {
+                    fencing_token: fencing_token,
+                    ticket: t.ticket.clone(),
+                    previous_position: previous.ticket.position,
+                });
+            }
+        }
+    }
+
+    for t in &new.tickets {
+        if !old_tickets.contains_key(&(t.ticket.process_id.as_str(), t.ticket.counter)) {
+            events.push(QueueEvent::Joined { fencing_token: fencing_token, ticket: t.ticket.clone() });
+        }
+    }
+
+    events
+}
+
+/// Turns successive snapshots of a queue into events.
+///
+/// The first snapshot is reported as a `Joined` event for each ticket already in the queue.
+#[derive(Debug, Clone, Default)]
+pub struct Watcher {
+    last: Option<Snapshot>,
+    pending: VecDeque<QueueEvent>,
+}
+
+impl Watcher {
+    pub fn new() -> Watcher {
+        Watcher {
+            last: None,
+            pending: VecDeque::new(),
+        }
+    }
+
+    /// Compare `snapshot` with the last one, queueing any events. Returns true if there were any.
+    pub fn update(&mut self, snapshot: Snapshot) -> bool {
+        let now = Instant::now();
+
+        let events = match self.last {
+            Some(ref last) if last == &snapshot => vec![],
+            Some(ref last) => diff(last, &snapshot, now),
+            None => diff(&Snapshot::new(snapshot.fencing_token, vec![]), &snapshot, now),
+        };
+
+        self.last = Some(snapshot);
+
+        let changed = !events.is_empty();
+
+        self.pending.extend(events);
+
+        changed
+    }
+
+    /// Take the oldest queued event.
+    pub fn pop(&mut self) -> Option<QueueEvent> {
+        self.pending.pop_front()
+    }
+
+    /// The fencing token of the last snapshot.
+    pub fn fencing_token(&self) -> Option<FencingToken> {
+        self.last.as_ref().map(|s| s.fencing_token)
+    }
+
+    /// The earliest time a lease in the last snapshot runs out.
+    pub fn next_expiry(&self) -> Option<Instant> {
+        self.last.as_ref().and_then(|s| s.next_expiry())
+    }
+}
+
+/// A queue whose changes can be watched.
+pub trait WatchableQueue: MonotonicQueue {
+    /// Watch for changes to the queue, blocking in `next` until there is an event.
+    /// The first events are `Joined` for the tickets already in the queue.
+    fn watch<'a>(&'a self) -> Box<dyn Iterator<Item=result::Result<QueueEvent, Self::Error>> + 'a>;
+}
+
+#[cfg(test)]
+mod tests {
+    use std::time::{Duration, Instant};
+    use std::collections::BTreeMap;
+    use string::*;
+    use super::*;
+
+    fn ticket(process_id: &str, counter: u64, position: usize) -> Ticket {
+        Ticket::new(s(process_id), counter, position, BTreeMap::new())
+    }
+
+    #[test]
+    pub fn test_diff() {
+        let now = Instant::now();
+        let expired = now - Duration::from_millis(1);
+        let live = now + Duration::from_secs(30);
+
+        let old = Snapshot::new(3, vec![
+            WatchedTicket::new(ticket("foo", 1, 0), None),
+            WatchedTicket::new(ticket("bar", 2, 1), Some(expired)),
+            WatchedTicket::new(ticket("baz", 3, 2), Some(live)),
+            WatchedTicket::new(ticket("qux", 4, 3), None),
+        ]);
+
+        let new = Snapshot::new(5, vec![
+            WatchedTicket::new(ticket("baz", 3, 0), Some(live)),
+            WatchedTicket::new(ticket("qux", 4, 1), None),
+            WatchedTicket::new(ticket("foo", 5, 2), None),
+        ]);
+
+        assert_eq!(diff(&old, &new, now), vec![
+            QueueEvent::Left { fencing_token: 5, ticket: ticket("foo", 1, 0) },
+            QueueEvent::Expired { fencing_token: 5, ticket: ticket("bar", 2, 1) },
+            QueueEvent::PositionChanged { fencing_token: 5, ticket: ticket("baz", 3, 0), previous_position: 2 },
+            QueueEvent::PositionChanged { fencing_token: 5, ticket: ticket("qux", 4, 1), previous_position: 3 },
+            QueueEvent::Joined { fencing_token: 5, ticket: ticket("foo", 5, 2) },
+        ]);
+    }
+
+    #[test]
+    pub fn test_watcher() {
+        let mut w = Watcher::new();
+
+        assert!(w.update(Snapshot::new(1, vec![WatchedTicket::new(ticket("foo", 1, 0), None)])));
+        assert_eq!(w.pop(), Some(QueueEvent::Joined { fencing_token: 1, ticket: ticket("foo", 1, 0) }));
+        assert_eq!(w.pop(), None);
+
+        assert!(!w.update(Snapshot::new(1, vec![WatchedTicket::new(ticket("foo", 1, 0), None)])));
+        assert_eq!(w.pop(), None);
+        assert_eq!(w.fencing_token(), Some(1));
+
+        assert!(w.update(Snapshot::new(2, vec![])));
+        assert_eq!(w.pop(), Some(QueueEvent::Left { fencing_token: 2, ticket: ticket("foo", 1, 0) }));
+    }
+}
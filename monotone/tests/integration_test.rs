@@ -7,6 +7,12 @@ extern crate rand;
 
 #[cfg(feature="aws")]
 extern crate rusoto;
+#[cfg(feature="async")]
+extern crate futures;
+#[cfg(feature="async")]
+extern crate futures_cpupool;
+#[cfg(feature="async")]
+extern crate tokio_timer;
 
 #[cfg(feature="aws")]
 mod aws {
@@ -14,12 +20,19 @@ mod aws {
 
 use monotone::*;
 use monotone::string::*;
+use monotone::election::*;
+use monotone::lock::*;
+use monotone::semaphore::*;
 use monotone::aws::counter::*;
 use monotone::aws::queue::*;
+use monotone::aws::store::*;
+use monotone::aws::emulator::Emulator;
 use self::error::*;
 use rusoto::*;
 use rusoto::dynamodb::*;
 use std::env;
+use std::result;
+use std::thread;
 use std::time::Duration;
 use std::str::FromStr;
 use std::ops::Deref;
@@ -41,6 +54,7 @@ mod error {
 
         links {
             MonotoneAws(monotone::aws::error::Error, monotone::aws::error::ErrorKind);
+            MonotoneStore(monotone::store::error::Error, monotone::store::error::ErrorKind);
         }
 
         errors {
@@ -63,10 +77,48 @@ for each initial condition [no row in db, row in db]:
   Queue::leave() with later items repositioned
 */
 
-pub fn client() -> Result<DynamoDbClient<DefaultCredentialsProvider,hyper::client::Client>> {
+/// Sends requests to DynamoDB if `MONOTONE_DYNAMODB=aws`, otherwise to an emulator shared by
+/// every client made on the same thread, which is to say by every client in the same test.
+pub enum TestDispatcher {
+    Aws(hyper::client::Client),
+    Emulator(Emulator),
+}
+
+impl DispatchSignedRequest for TestDispatcher {
+    fn dispatch(&self, request: &SignedRequest) -> result::Result<HttpResponse, HttpDispatchError> {
+        match *self {
+            TestDispatcher::Aws(ref client) => client.dispatch(request),
+            TestDispatcher::Emulator(ref emulator) => emulator.dispatch(request),
+        }
+    }
+}
+
+thread_local!(static EMULATOR: Emulator = Emulator::with_table(table_name()));
+
+/// The emulator for this test, or `None` when running against DynamoDB.
+pub fn emulator() -> Option<Emulator> {
+    match env::var("MONOTONE_DYNAMODB") {
+        Ok(ref target) if target == "aws" => None,
+        _ => Some(EMULATOR.with(|e| e.clone())),
+    }
+}
+
+pub fn client() -> Result<DynamoDbClient<DefaultCredentialsProvider,TestDispatcher>> {
+    let dispatcher = match emulator() {
+        Some(emulator) => {
+            if env::var("AWS_ACCESS_KEY_ID").is_err() {
+                env::set_var("AWS_ACCESS_KEY_ID", "emulator");
+                env::set_var("AWS_SECRET_ACCESS_KEY", "emulator");
+            }
+
+            TestDispatcher::Emulator(emulator)
+        },
+        None => TestDispatcher::Aws(default_tls_client()?),
+    };
+
     let provider = DefaultCredentialsProvider::new()?;
     let region = Region::from_str("eu-west-1")?;
-    let client = DynamoDbClient::new(default_tls_client()?, provider, region);
+    let client = DynamoDbClient::new(dispatcher, provider, region);
     Ok(client)
 }
 
@@ -102,25 +154,25 @@ pub fn retry_time() -> Duration {
 }
 
 pub struct TestCounter {
-    pub counter: Counter<DefaultCredentialsProvider, hyper::client::Client>,
+    pub counter: Counter<DefaultCredentialsProvider, TestDispatcher>,
 }
 
 impl TestCounter {
     pub fn new() -> TestCounter {
         TestCounter {
-            counter: Counter::new(client().expect("client"), table_name(), counter_id(), retry_time())
+            counter: Counter::new(DynamoDbStore::new(client().expect("client"), table_name()), counter_id(), retry_time())
         }
     }
 }
 
 impl Drop for TestCounter {
     fn drop(&mut self) {
-        self.counter.remove().expect("remove");
+        self.counter.purge().expect("purge");
     }
 }
 
 impl Deref for TestCounter {
-    type Target = Counter<DefaultCredentialsProvider, hyper::client::Client>;
+    type Target = Counter<DefaultCredentialsProvider, TestDispatcher>;
 
     fn deref(&self) -> &Self::Target {
         &self.counter
@@ -128,25 +180,25 @@ impl Deref for TestCounter {
 }
 
 pub struct TestQueue {
-    pub queue: Queue<DefaultCredentialsProvider, hyper::client::Client>,
+    pub queue: Queue<DefaultCredentialsProvider, TestDispatcher>,
 }
 
 impl TestQueue {
     pub fn new() -> TestQueue {
         TestQueue {
-            queue: Queue::new(client().expect("client"), table_name(), queue_id(), retry_time())
+            queue: Queue::new(DynamoDbStore::new(client().expect("client"), table_name()), queue_id(), retry_time())
         }
     }
 }
 
 impl Drop for TestQueue {
     fn drop(&mut self) {
-        self.queue.remove().expect("remove");
+        self.queue.purge().expect("purge");
     }
 }
 
 impl Deref for TestQueue {
-    type Target = Queue<DefaultCredentialsProvider, hyper::client::Client>;
+    type Target = Queue<DefaultCredentialsProvider, TestDispatcher>;
 
     fn deref(&self) -> &Self::Target {
         &self.queue
@@ -189,14 +241,110 @@ pub fn test_counter_row_next() {
     assert_eq!(v, 2);
 }
 
+#[test]
+pub fn test_counter_row_next_values() {
+    let c = TestCounter::new();
+
+    let v = c.next_values(10).expect("next values");
+    assert_eq!(v, 1..11);
+
+    let v = c.next_value().expect("next");
+    assert_eq!(v, 11);
+
+    let v = c.next_values(0).expect("next values");
+    assert_eq!(v, 12..12);
+
+    let v = c.get_value().expect("get");
+    assert_eq!(v, 11);
+}
+
+#[test]
+pub fn test_counter_compare_and_set() {
+    let c = TestCounter::new();
+
+    assert!(c.compare_and_set(0, 5000).expect("cas"));
+    assert_eq!(c.get_value().expect("get"), 5000);
+
+    assert!(!c.compare_and_set(0, 6000).expect("cas"));
+    assert_eq!(c.get_value().expect("get"), 5000);
+
+    assert!(c.compare_and_set(5000, 4000).is_err());
+    assert_eq!(c.next_value().expect("next"), 5001);
+}
+
+#[test]
+pub fn test_counter_advance_to() {
+    let c = TestCounter::new();
+
+    assert_eq!(c.advance_to(5000).expect("advance"), 5000);
+    assert_eq!(c.advance_to(10).expect("advance"), 5000);
+    assert_eq!(c.next_value().expect("next"), 5001);
+}
+
+#[test]
+pub fn test_counter_remove_continues() {
+    let c = TestCounter::new();
+
+    let v = c.next_value().expect("next");
+    assert_eq!(v, 1);
+
+    c.remove().expect("remove");
+
+    let v = c.get_value().expect("get");
+    assert_eq!(v, 1);
+
+    let v = c.next_value().expect("next");
+    assert_eq!(v, 2);
+}
+
+#[test]
+pub fn test_counter_purge_restarts() {
+    let c = TestCounter::new();
+
+    let v = c.next_value().expect("next");
+    assert_eq!(v, 1);
+
+    c.remove().expect("remove");
+    c.purge().expect("purge");
+
+    let v = c.next_value().expect("next");
+    assert_eq!(v, 1);
+}
+
 #[test]
 pub fn test_counter_no_row_interleaved_write_next() {
+    let emulator = match emulator() { Some(e) => e, None => return };
+    let c = TestCounter::new();
+
+    let id = c.id.clone();
+    emulator.interleave(move |_| {
+        let other = Counter::new(DynamoDbStore::new(client().expect("client"), table_name()), id, retry_time());
+        assert_eq!(other.next_value().expect("next"), 1);
+    });
+
+    let v = c.next_value().expect("next");
+    assert_eq!(v, 2);
 
+    let v = c.get_value().expect("get");
+    assert_eq!(v, 2);
 }
 
 #[test]
 pub fn test_counter_row_interleaved_write_next() {
+    let emulator = match emulator() { Some(e) => e, None => return };
+    let c = TestCounter::new();
+
+    let v = c.next_value().expect("next");
+    assert_eq!(v, 1);
 
+    let id = c.id.clone();
+    emulator.interleave(move |_| {
+        let other = Counter::new(DynamoDbStore::new(client().expect("client"), table_name()), id, retry_time());
+        assert_eq!(other.advance_to(10).expect("advance"), 10);
+    });
+
+    let v = c.next_value().expect("next");
+    assert_eq!(v, 11);
 }
 
 #[test]
@@ -210,7 +358,7 @@ pub fn test_queue_no_row_get() {
 pub fn test_queue_row_get() {
     let q = TestQueue::new();
 
-    let (ft, tok) = q.join_queue(s("foo"), None).expect("join");
+    let (ft, tok) = q.join_queue(s("foo"), None, None).expect("join");
     assert_eq!(ft, 1);
     assert_eq!(&tok.process_id, "foo");
     assert_eq!(tok.counter, 1);
@@ -234,7 +382,7 @@ pub fn test_queue_no_row_get_all() {
 pub fn test_queue_row_get_all() {
     let q = TestQueue::new();
 
-    let (ft, tok) = q.join_queue(s("foo"), None).expect("join");
+    let (ft, tok) = q.join_queue(s("foo"), None, None).expect("join");
     assert_eq!(ft, 1);
     assert_eq!(&tok.process_id, "foo");
     assert_eq!(tok.counter, 1);
@@ -249,13 +397,13 @@ pub fn test_queue_row_get_all() {
 pub fn test_queue_same_row_join() {
     let q = TestQueue::new();
 
-    let (ft, tok) = q.join_queue(s("foo"), None).expect("join");
+    let (ft, tok) = q.join_queue(s("foo"), None, None).expect("join");
     assert_eq!(ft, 1);
     assert_eq!(&tok.process_id, "foo");
     assert_eq!(tok.counter, 1);
     assert_eq!(tok.position, 0);
 
-    let (ft, tok) = q.join_queue(s("foo"), None).expect("join");
+    let (ft, tok) = q.join_queue(s("foo"), None, None).expect("join");
     assert_eq!(ft, 1);
     assert_eq!(&tok.process_id, "foo");
     assert_eq!(tok.counter, 1);
@@ -270,13 +418,13 @@ pub fn test_queue_same_row_join() {
 pub fn test_queue_different_row_join() {
     let q = TestQueue::new();
 
-    let (ft, tok) = q.join_queue(s("foo"), None).expect("join");
+    let (ft, tok) = q.join_queue(s("foo"), None, None).expect("join");
     assert_eq!(ft, 1);
     assert_eq!(&tok.process_id, "foo");
     assert_eq!(tok.counter, 1);
     assert_eq!(tok.position, 0);
 
-    let (ft, tok) = q.join_queue(s("bar"), None).expect("join");
+    let (ft, tok) = q.join_queue(s("bar"), None, None).expect("join");
     assert_eq!(ft, 2);
     assert_eq!(&tok.process_id, "bar");
     assert_eq!(tok.counter, 2);
@@ -285,12 +433,43 @@ pub fn test_queue_different_row_join() {
 
 #[test]
 pub fn test_queue_no_row_interleaved_write_join() {
+    let emulator = match emulator() { Some(e) => e, None => return };
+    let q = TestQueue::new();
+
+    let id = q.id.clone();
+    emulator.interleave(move |_| {
+        let other = Queue::new(DynamoDbStore::new(client().expect("client"), table_name()), id, retry_time());
+        assert_eq!(other.join_queue(s("bar"), None, None).expect("join").0, 1);
+    });
 
+    let (ft, tok) = q.join_queue(s("foo"), None, None).expect("join");
+    assert_eq!(ft, 2);
+    assert_eq!(&tok.process_id, "foo");
+    assert_eq!(tok.counter, 2);
+    assert_eq!(tok.position, 1);
 }
 
 #[test]
 pub fn test_queue_row_interleaved_write_join() {
+    let emulator = match emulator() { Some(e) => e, None => return };
+    let q = TestQueue::new();
+
+    q.join_queue(s("foo"), None, None).expect("join");
+
+    let id = q.id.clone();
+    emulator.interleave(move |_| {
+        let other = Queue::new(DynamoDbStore::new(client().expect("client"), table_name()), id, retry_time());
+        assert_eq!(other.join_queue(s("bar"), None, None).expect("join").0, 2);
+    });
+
+    let (ft, tok) = q.join_queue(s("bar"), None, None).expect("join");
+    assert_eq!(ft, 2);
+    assert_eq!(tok.counter, 2);
+    assert_eq!(tok.position, 1);
 
+    let (ft, toks) = q.get_tickets().expect("get all");
+    assert_eq!(ft, 2);
+    assert_eq!(toks.len(), 2);
 }
 
 #[test]
@@ -304,7 +483,7 @@ pub fn test_queue_no_row_leave() {
 pub fn test_queue_row_leave() {
     let q = TestQueue::new();
 
-    let (ft, tok) = q.join_queue(s("foo"), None).expect("join");
+    let (ft, tok) = q.join_queue(s("foo"), None, None).expect("join");
     assert_eq!(ft, 1);
     assert_eq!(&tok.process_id, "foo");
     assert_eq!(tok.counter, 1);
@@ -316,25 +495,57 @@ pub fn test_queue_row_leave() {
 
 #[test]
 pub fn test_queue_no_row_interleaved_write_leave() {
+    let emulator = match emulator() { Some(e) => e, None => return };
+    let q = TestQueue::new();
+
+    q.join_queue(s("foo"), None, None).expect("join");
+
+    let id = q.id.clone();
+    emulator.interleave(move |_| {
+        let other = Queue::new(DynamoDbStore::new(client().expect("client"), table_name()), id, retry_time());
+        assert_eq!(other.leave_queue("foo").expect("leave"), 2);
+    });
 
+    assert!(q.leave_queue("foo").is_err());
+
+    let (ft, toks) = q.get_tickets().expect("get all");
+    assert_eq!(ft, 2);
+    assert_eq!(toks, vec![]);
 }
 
 #[test]
 pub fn test_queue_row_interleaved_write_leave() {
+    let emulator = match emulator() { Some(e) => e, None => return };
+    let q = TestQueue::new();
+
+    q.join_queue(s("foo"), None, None).expect("join");
+    q.join_queue(s("bar"), None, None).expect("join");
 
+    let id = q.id.clone();
+    emulator.interleave(move |_| {
+        let other = Queue::new(DynamoDbStore::new(client().expect("client"), table_name()), id, retry_time());
+        assert_eq!(other.join_queue(s("baz"), None, None).expect("join").0, 3);
+    });
+
+    let ft = q.leave_queue("foo").expect("leave");
+    assert_eq!(ft, 4);
+
+    let (ft, toks) = q.get_tickets().expect("get all");
+    assert_eq!(ft, 4);
+    assert_eq!(toks.iter().map(|t| (&t.process_id[..], t.position)).collect::<Vec<_>>(), vec![("bar", 0), ("baz", 1)]);
 }
 
 #[test]
 pub fn test_queue_rows_leave() {
     let q = TestQueue::new();
 
-    let (ft, tok) = q.join_queue(s("foo"), None).expect("join");
+    let (ft, tok) = q.join_queue(s("foo"), None, None).expect("join");
     assert_eq!(ft, 1);
     assert_eq!(&tok.process_id, "foo");
     assert_eq!(tok.counter, 1);
     assert_eq!(tok.position, 0);
 
-    let (ft, tok) = q.join_queue(s("bar"), None).expect("join");
+    let (ft, tok) = q.join_queue(s("bar"), None, None).expect("join");
     assert_eq!(ft, 2);
     assert_eq!(&tok.process_id, "bar");
     assert_eq!(tok.counter, 2);
@@ -350,4 +561,347 @@ pub fn test_queue_rows_leave() {
     assert_eq!(tok.position, 0);
 }
 
-}
\ No newline at end of file
+#[test]
+pub fn test_queue_lease_expires() {
+    let q = TestQueue::new();
+
+    let (ft, _tok) = q.join_queue(s("foo"), None, Duration::from_millis(500)).expect("join");
+    assert_eq!(ft, 1);
+
+    let (ft, tok) = q.join_queue(s("bar"), None, None).expect("join");
+    assert_eq!(ft, 2);
+    assert_eq!(tok.position, 1);
+
+    thread::sleep(Duration::from_millis(1000));
+
+    assert!(q.get_ticket("foo").is_err());
+
+    let (ft, tok) = q.get_ticket("bar").expect("get");
+    assert_eq!(ft, 3);
+    assert_eq!(&tok.process_id, "bar");
+    assert_eq!(tok.position, 0);
+}
+
+#[test]
+pub fn test_queue_lease_heartbeat() {
+    let q = TestQueue::new();
+
+    let (ft, tok) = q.join_queue(s("foo"), None, Duration::from_millis(1000)).expect("join");
+    assert_eq!(ft, 1);
+
+    thread::sleep(Duration::from_millis(600));
+
+    let (ft, tok2) = q.heartbeat("foo").expect("heartbeat");
+    assert_eq!(ft, 2);
+    assert_eq!(tok2, tok);
+
+    thread::sleep(Duration::from_millis(600));
+
+    let (ft, tok3) = q.get_ticket("foo").expect("get");
+    assert_eq!(ft, 2);
+    assert_eq!(tok3, tok);
+}
+
+#[test]
+pub fn test_election() {
+    let q = TestQueue::new();
+
+    let foo = Election::new(Queue::new(DynamoDbStore::new(client().expect("client"), table_name()), q.id.clone(), retry_time()), "foo", None, None);
+    let bar = Election::new(Queue::new(DynamoDbStore::new(client().expect("client"), table_name()), q.id.clone(), retry_time()), "bar", None, None);
+
+    assert_eq!(foo.leader().expect("leader"), None);
+
+    let (_ft, foo_ticket) = foo.campaign().expect("campaign");
+    bar.campaign().expect("campaign");
+
+    assert!(foo.is_leader().expect("is leader"));
+    assert!(!bar.is_leader().expect("is leader"));
+    assert_eq!(bar.leader().expect("leader"), Some((2, foo_ticket)));
+
+    let ft = foo.resign().expect("resign");
+    assert_eq!(ft, 3);
+
+    assert!(bar.is_leader().expect("is leader"));
+}
+
+#[test]
+pub fn test_lock() {
+    let q = TestQueue::new();
+
+    let foo = Lock::new(Queue::new(DynamoDbStore::new(client().expect("client"), table_name()), q.id.clone(), retry_time()), "foo", None, retry_time());
+    let bar = Lock::new(Queue::new(DynamoDbStore::new(client().expect("client"), table_name()), q.id.clone(), retry_time()), "bar", None, retry_time());
+
+    let guard = foo.try_lock().expect("try lock").expect("acquired");
+    assert_eq!(guard.fencing_token(), 1);
+
+    assert!(bar.try_lock().expect("try lock").is_none());
+
+    let ft = guard.unlock().expect("unlock");
+    assert_eq!(ft, 4);
+
+    let guard = bar.try_lock().expect("try lock").expect("acquired");
+    assert_eq!(guard.fencing_token(), 5);
+}
+
+#[test]
+pub fn test_semaphore() {
+    let q = TestQueue::new();
+
+    let foo = Semaphore::new(Queue::new(DynamoDbStore::new(client().expect("client"), table_name()), q.id.clone(), retry_time()), "foo", 2, None, retry_time()).expect("semaphore");
+    let bar = Semaphore::new(Queue::new(DynamoDbStore::new(client().expect("client"), table_name()), q.id.clone(), retry_time()), "bar", 2, None, retry_time()).expect("semaphore");
+    let baz = Semaphore::new(Queue::new(DynamoDbStore::new(client().expect("client"), table_name()), q.id.clone(), retry_time()), "baz", 2, None, retry_time()).expect("semaphore");
+
+    let foo_permit = foo.try_acquire().expect("try acquire").expect("acquired");
+    assert_eq!(foo_permit.fencing_token(), 1);
+
+    let bar_permit = bar.try_acquire().expect("try acquire").expect("acquired");
+    assert_eq!(bar_permit.fencing_token(), 2);
+
+    assert!(baz.try_acquire().expect("try acquire").is_none());
+
+    let ft = foo_permit.release().expect("release");
+    assert_eq!(ft, 5);
+
+    let baz_permit = baz.try_acquire().expect("try acquire").expect("acquired");
+    assert_eq!(baz_permit.fencing_token(), 6);
+    assert_eq!(baz_permit.ticket().position, 1);
+}
+
+#[test]
+pub fn test_queue_remove_continues() {
+    let q = TestQueue::new();
+
+    let (ft, tok) = q.join_queue(s("foo"), None, None).expect("join");
+    assert_eq!(ft, 1);
+    assert_eq!(tok.counter, 1);
+
+    q.remove().expect("remove");
+
+    let (ft, toks) = q.get_tickets().expect("get all");
+    assert_eq!(ft, 2);
+    assert_eq!(toks, vec![]);
+
+    let (ft, tok) = q.join_queue(s("foo"), None, None).expect("join");
+    assert_eq!(ft, 3);
+    assert_eq!(tok.counter, 2);
+    assert_eq!(tok.position, 0);
+}
+
+#[test]
+pub fn test_conformance() {
+    conformance::check_counter(|| Counter::new(DynamoDbStore::new(client().expect("client"), table_name()), counter_id(), retry_time()));
+    conformance::check_queue(|| Queue::new(DynamoDbStore::new(client().expect("client"), table_name()), queue_id(), retry_time()));
+}
+
+#[cfg(feature="async")]
+mod nonblocking {
+    use futures::Future;
+    use futures::future::join_all;
+    use futures_cpupool::CpuPool;
+    use tokio_timer::Timer;
+    use monotone::nonblocking::*;
+    use monotone::aws::nonblocking::*;
+    use super::*;
+
+    #[test]
+    pub fn test_async_counter_and_queue() {
+        let pool = CpuPool::new(4);
+        let timer = Timer::default();
+
+        let c = AsyncCounter::new(Counter::new(DynamoDbStore::new(client().expect("client"), table_name()), counter_id(), retry_time()), pool.clone(), timer.clone());
+
+        let mut values = join_all((0..4).map(|_| c.next_value()).collect::<Vec<_>>()).wait().expect("next");
+        values.sort();
+        assert_eq!(values, vec![1, 2, 3, 4]);
+        assert_eq!(c.get_value().wait().expect("get"), 4);
+
+        c.counter.purge().expect("purge");
+
+        let q = AsyncQueue::new(Queue::new(DynamoDbStore::new(client().expect("client"), table_name()), queue_id(), retry_time()), pool, timer);
+
+        let (ft, tok) = q.join_queue(s("foo"), None, None).wait().expect("join");
+        assert_eq!(ft, 1);
+        assert_eq!(tok.position, 0);
+
+        let (_ft, tok) = q.join_queue(s("bar"), None, None).wait().expect("join");
+        assert_eq!(tok.position, 1);
+
+        q.leave_queue("foo").wait().expect("leave");
+
+        let (_ft, tok) = q.get_ticket("bar").wait().expect("get");
+        assert_eq!(tok.position, 0);
+
+        q.queue.purge().expect("purge");
+    }
+}
+
+}
+
+/// Checks for the network-backed stores, each run with a factory that opens a new connection
+/// per counter or queue, so that separate clients share the same rows.
+#[cfg(any(feature="postgres", feature="redis"))]
+mod stores {
+    use std::thread;
+    use std::time::Duration;
+    use rand;
+    use monotone::*;
+    use monotone::string::*;
+    use monotone::store::*;
+    use monotone::store::counter::Counter;
+    use monotone::store::queue::Queue;
+
+    pub fn id(prefix: &str) -> String {
+        format!("it-{}-{}", prefix, rand::random::<u64>())
+    }
+
+    fn retry_time() -> Duration {
+        Duration::from_millis(10)
+    }
+
+    pub fn check_store<S, F>(store: F) where S: VersionedStore + Send + 'static, F: Fn() -> S {
+        conformance::check_counter(|| Counter::new(store(), id("counter"), retry_time()));
+        conformance::check_queue(|| Queue::new(store(), id("queue"), retry_time()));
+
+        check_put_if_version(&store);
+        check_counter_shared(&store);
+        check_counter_concurrent_next(&store);
+        check_queue_shared(&store);
+    }
+
+    /// A row is only written if it is new at version 0, or still at the version it was read at.
+    pub fn check_put_if_version<S, F>(store: &F) where S: VersionedStore, F: Fn() -> S {
+        let store = store();
+        let id = id("row");
+        assert_eq!(store.get(&id).expect("get"), None);
+
+        assert!(store.put(&id, &Row::new("COUNTER", 0, 1, vec![])).expect("put"));
+        assert!(!store.put(&id, &Row::new("COUNTER", 0, 2, vec![])).expect("put"));
+        assert!(!store.put(&id, &Row::new("COUNTER", 2, 2, vec![])).expect("put"));
+        assert_eq!(store.get(&id).expect("get"), Some(Row::new("COUNTER", 1, 1, vec![])));
+
+        assert!(store.put(&id, &Row::new("QUEUE", 1, 2, vec![s("a"), s("b")])).expect("put"));
+        assert_eq!(store.get(&id).expect("get"), Some(Row::new("QUEUE", 2, 2, vec![s("a"), s("b")])));
+
+        store.delete(&id).expect("delete");
+        assert_eq!(store.get(&id).expect("get"), None);
+    }
+
+    /// Two clients of one counter see each other's writes, and removing it leaves a tombstone until purged.
+    pub fn check_counter_shared<S, F>(store: &F) where S: VersionedStore, F: Fn() -> S {
+        let c = Counter::new(store(), id("counter"), retry_time());
+        let c2 = Counter::new(store(), c.id.clone(), retry_time());
+
+        assert_eq!(c.next_value().expect("next"), 1);
+        assert_eq!(c2.next_values(10).expect("next values"), 2..12);
+        assert_eq!(c.get_value().expect("get"), 11);
+
+        let row = c.read().expect("read").expect("row");
+        assert_eq!(c2.next_value().expect("next"), 12);
+        assert!(c.write(row).is_err());
+
+        c.remove().expect("remove");
+        assert_eq!(c2.next_value().expect("next"), 13);
+
+        c.purge().expect("purge");
+        assert_eq!(c.get_value().expect("get"), 0);
+    }
+
+    pub fn check_counter_concurrent_next<S, F>(store: &F) where S: VersionedStore + Send + 'static, F: Fn() -> S {
+        let counter_id = id("counter");
+
+        let handles: Vec<_> = (0..4).map(|_| {
+            let c = Counter::new(store(), counter_id.clone(), retry_time());
+
+            thread::spawn(move || {
+                (0..10).map(|_| c.next_value().expect("next")).collect::<Vec<_>>()
+            })
+        }).collect();
+
+        let mut values: Vec<u64> = handles.into_iter().flat_map(|h| h.join().expect("join")).collect();
+        values.sort();
+
+        assert_eq!(values, (1..41).collect::<Vec<_>>());
+
+        Counter::new(store(), counter_id, retry_time()).purge().expect("purge");
+    }
+
+    /// Two clients of one queue see each other's tickets.
+    pub fn check_queue_shared<S, F>(store: &F) where S: VersionedStore, F: Fn() -> S {
+        let q = Queue::new(store(), id("queue"), retry_time());
+        let q2 = Queue::new(store(), q.id.clone(), retry_time());
+
+        q.join_queue(s("foo"), None, None).expect("join");
+
+        let (ft, tok) = q2.join_queue(s("bar"), None, None).expect("join");
+        assert_eq!(ft, 2);
+        assert_eq!(tok.position, 1);
+
+        assert_eq!(q.leave_queue("foo").expect("leave"), 3);
+
+        let (ft, tok) = q2.get_ticket("bar").expect("get");
+        assert_eq!(ft, 3);
+        assert_eq!(tok.position, 0);
+
+        q.purge().expect("purge");
+    }
+}
+
+#[cfg(feature="postgres")]
+mod postgres {
+    use std::env;
+    use monotone::string::*;
+    use monotone::postgres::store::*;
+    use stores;
+
+    pub fn store() -> PostgresStore {
+        let url = env::var("MONOTONE_POSTGRES_URL").unwrap_or(s("postgres://postgres@localhost/postgres"));
+
+        PostgresStore::connect(&url, "Counters").expect("connect")
+    }
+
+    #[test]
+    pub fn test_postgres_store() {
+        stores::check_store(store);
+    }
+}
+
+#[cfg(feature="redis")]
+mod redis {
+    use std::env;
+    use std::time::Duration;
+    use monotone::*;
+    use monotone::string::*;
+    use monotone::store::VersionedStore;
+    use monotone::redis::counter::*;
+    use monotone::redis::store::*;
+    use stores;
+
+    pub fn url() -> String {
+        env::var("MONOTONE_REDIS_URL").unwrap_or(s("redis://127.0.0.1/"))
+    }
+
+    pub fn store() -> RedisStore {
+        RedisStore::open(&url(), "monotone-it:").expect("open")
+    }
+
+    #[test]
+    pub fn test_redis_store() {
+        stores::check_store(store);
+    }
+
+    #[test]
+    pub fn test_redis_key_prefix() {
+        let id = stores::id("counter");
+        let c = Counter::new(store(), id.clone(), Duration::from_millis(10));
+        let other = Counter::new(RedisStore::open(&url(), "monotone-it-other:").expect("open"), id.clone(), Duration::from_millis(10));
+
+        assert_eq!(c.next_value().expect("next"), 1);
+        assert_eq!(other.get_value().expect("get"), 0);
+
+        let bare = RedisStore::open(&url(), "").expect("open");
+        assert_eq!(bare.get(&id).expect("get"), None);
+        assert!(bare.get(&format!("monotone-it:{}", id)).expect("get").is_some());
+
+        c.purge().expect("purge");
+    }
+}